@@ -0,0 +1,21 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// PIO programs are assembled by pio_proc::pio_file! while compiling, but the macro
+// doesn't tell cargo about the files it reads. Watching all of src covers those, and
+// it also reruns this whenever the firmware itself changes.
+//
+// BUILD_TIMESTAMP is when this last ran, so with src and memory.x watched it's the
+// last time the firmware was rebuilt, see info. SOURCE_DATE_EPOCH wins for
+// reproducible builds.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=memory.x");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs()));
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={timestamp}");
+}
@@ -0,0 +1,154 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use rp2040_hal::adc::{Adc, TempSense};
+use rp2040_hal::pac;
+
+pub const RING_LEN: usize = 64;
+
+// Temperature sensor is ADC input 4
+const TEMPERATURE_CHANNEL: u8 = 4;
+
+// 48 MHz ADC clock / 48000 = 1000 samples per second, plenty for a reading every 10 s
+const CLOCK_DIVIDER: u16 = 48_000 - 1;
+
+// DMA channel filling the ring, 0..=2 belong to uart_log, ws2812 and ir_tx
+const DMA_CHANNEL: usize = 3;
+
+// Longest transfer DMA can do, at 1000 S/s that's about 49 days
+const TRANSFER_COUNT: u32 = u32::MAX;
+
+// drain_adc_buffer() restarts the transfer once fewer than this are left, a bit over
+// a minute at 1000 S/s and it comes by every 10 s
+const REARM_BELOW: u32 = 1 << 16;
+
+// DMA ring mode wraps on an address boundary, so the buffer has to be aligned to its size
+#[repr(C, align(128))]
+struct Ring([u16; RING_LEN]);
+
+// Only DMA writes here, drain_adc_buffer() only reads behind it
+static mut RING: Ring = Ring([0; RING_LEN]);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Samples handed out so far, compared against what DMA has written. Both counts wrap,
+// 2^32 is a multiple of RING_LEN so their ring positions still line up.
+static READ_COUNT: AtomicU32 = AtomicU32::new(0);
+
+// Samples written by the transfers before the current one
+static WRITTEN_BEFORE: AtomicU32 = AtomicU32::new(0);
+
+const fn adc_regs() -> &'static pac::adc::RegisterBlock {
+    // SAFETY: hal's Adc owns the ADC, it's only touched while it isn't converting
+    unsafe { &*pac::ADC::ptr() }
+}
+
+const fn dma_regs() -> &'static pac::dma::RegisterBlock {
+    // SAFETY: only our own channel's bits are touched
+    unsafe { &*pac::DMA::ptr() }
+}
+
+const fn dma_channel() -> &'static pac::dma::CH {
+    &dma_regs().ch[DMA_CHANNEL]
+}
+
+// Samples written since start(), wrapping
+fn written() -> u32 {
+    let current = TRANSFER_COUNT - dma_channel().ch_trans_count.read().bits();
+    WRITTEN_BEFORE.load(Ordering::Relaxed).wrapping_add(current)
+}
+
+// Stops the transfer and starts a fresh one where it left off. The write address
+// isn't touched, so the ring carries on from the same slot.
+fn rearm() {
+    paused(|| {
+        let dma = dma_regs();
+        dma.chan_abort.write(|w| unsafe { w.bits(1 << DMA_CHANNEL) });
+        while dma.chan_abort.read().bits() & (1 << DMA_CHANNEL) != 0 {}
+        WRITTEN_BEFORE.store(written(), Ordering::Relaxed);
+        dma_channel().ch_al1_trans_count_trig.write(|w| unsafe { w.bits(TRANSFER_COUNT) });
+    });
+}
+
+// Starts the ADC free running on the temperature sensor, DMA keeps the last
+// RING_LEN samples around. `_adc` and `_sensor` show the ADC and the sensor are up.
+#[allow(clippy::cast_possible_truncation)]
+pub fn start(_adc: &mut Adc, _sensor: &mut TempSense, resets: &pac::RESETS) {
+    resets.reset.modify(|_, w| w.dma().clear_bit());
+    while resets.reset_done.read().dma().bit_is_clear() {}
+
+    let adc = adc_regs();
+    adc.div.write(|w| unsafe { w.int().bits(CLOCK_DIVIDER) });
+    adc.fcs.write(|w| unsafe {
+        w.en().set_bit();
+        w.dreq_en().set_bit();
+        w.thresh().bits(1)
+    });
+
+    let ring_addr = core::ptr::addr_of!(RING) as u32;
+    let ch = dma_channel();
+    unsafe {
+        ch.ch_read_addr.write(|w| w.bits(core::ptr::addr_of!(adc.fifo) as u32));
+        ch.ch_write_addr.write(|w| w.bits(ring_addr));
+        ch.ch_trans_count.write(|w| w.bits(TRANSFER_COUNT));
+        ch.ch_ctrl_trig.write(|w| {
+            w.treq_sel().adc();
+            w.data_size().size_halfword();
+            w.incr_read().clear_bit();
+            w.incr_write().set_bit();
+            // wrap writes on 1 << 7 = 128 bytes, the whole ring
+            w.ring_sel().set_bit();
+            w.ring_size().bits(7);
+            // chaining to itself means no chaining
+            w.chain_to().bits(DMA_CHANNEL as u8);
+            w.en().set_bit()
+        });
+    }
+
+    adc.cs.modify(|_, w| unsafe { w.ainsel().bits(TEMPERATURE_CHANNEL).start_many().set_bit() });
+    RUNNING.store(true, Ordering::Relaxed);
+}
+
+// Runs `f` with free running stopped, so one-shot reads of other inputs don't end
+// up in the ring
+pub fn paused<T>(f: impl FnOnce() -> T) -> T {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return f();
+    }
+
+    let adc = adc_regs();
+    adc.cs.modify(|_, w| w.start_many().clear_bit());
+    while adc.cs.read().ready().bit_is_clear() {}
+    // let DMA take the last sample before the FIFO goes away
+    while adc.fcs.read().level().bits() > 0 {}
+    adc.fcs.modify(|_, w| w.en().clear_bit());
+
+    let result = f();
+
+    adc.fcs.modify(|_, w| w.en().set_bit());
+    adc.cs.modify(|_, w| unsafe { w.ainsel().bits(TEMPERATURE_CHANNEL).start_many().set_bit() });
+    result
+}
+
+// Samples that came in since the last call, oldest first. If they wrap around the
+// end of the ring only the part up to the end is returned, call again for the rest.
+#[allow(clippy::cast_possible_truncation)]
+pub fn drain_adc_buffer() -> &'static [u16] {
+    if RUNNING.load(Ordering::Relaxed) && dma_channel().ch_trans_count.read().bits() < REARM_BELOW {
+        rearm();
+    }
+
+    let written = written();
+    let mut read = READ_COUNT.load(Ordering::Relaxed);
+
+    // anything older than one ring has been overwritten
+    if written.wrapping_sub(read) > RING_LEN as u32 {
+        read = written.wrapping_sub(RING_LEN as u32);
+    }
+
+    let start = read as usize % RING_LEN;
+    let len = (written.wrapping_sub(read) as usize).min(RING_LEN - start);
+    READ_COUNT.store(read.wrapping_add(len as u32), Ordering::Relaxed);
+
+    // SAFETY: DMA is only writing at or after `written`, which is past this slice
+    unsafe { core::slice::from_raw_parts(core::ptr::addr_of!(RING.0).cast::<u16>().add(start), len) }
+}
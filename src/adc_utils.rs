@@ -0,0 +1,77 @@
+use embedded_hal::adc::OneShot;
+use rp2040_hal::adc::{Adc, TempSense};
+
+use crate::adc_dma;
+use crate::bsp;
+
+// Nominal ADC reference, i.e. what the Pico regulator gives when VSYS is high enough
+pub const NOMINAL_VREF: f32 = 3.3;
+
+// Pico's RT6150 is a buck-boost, it holds 3V3 from about this VSYS up
+pub const MIN_REGULATED_VSYS: f32 = 1.8;
+
+// On Pico GPIO29 (ADC3) is wired to VSYS through a 1:3 divider.
+// Temperature sampling is stopped meanwhile, see adc_dma.
+pub fn read_vsys(adc: &mut Adc, vsys_sense: &mut bsp::VsysSense) -> f32 {
+    let raw: u16 = adc_dma::paused(|| adc.read(vsys_sense).unwrap());
+    f32::from(raw) * NOMINAL_VREF / 4096.0 * 3.0
+}
+
+// The ADC reference is 3V3 and the RP2040 has nothing else to measure it against, so
+// every conversion takes it as NOMINAL_VREF. That holds on USB and on a CR2032 alike as
+// long as the regulator keeps up, false here means it doesn't and no reading is to be trusted.
+pub fn check_regulation(vsys: f32) -> bool {
+    vsys >= MIN_REGULATED_VSYS
+}
+
+// Temperature readings are this many samples summed and decimated
+pub const TEMPERATURE_OVERSAMPLE: usize = 64;
+
+// Every 4x oversampling buys one bit, so 64 samples turn 12 bits into 15
+const fn extra_bits(count: usize) -> u32 {
+    count.ilog2() / 2
+}
+
+// Full scale of an oversampled reading, for converting it to volts
+#[allow(clippy::cast_precision_loss)]
+pub const OVERSAMPLED_FULL_SCALE: f32 = (4096u32 << extra_bits(TEMPERATURE_OVERSAMPLE)) as f32;
+
+// Sum of COUNT 12 bit samples scaled down to 12 + extra_bits(COUNT) bits
+#[allow(clippy::cast_possible_truncation)]
+pub fn decimate<const COUNT: usize>(samples: &[u16; COUNT]) -> u16 {
+    const { assert!(COUNT.is_power_of_two() && COUNT <= 64, "oversample count must be a power of two up to 64") };
+    let sum: u32 = samples.iter().map(|&s| u32::from(s)).sum();
+    (sum >> (COUNT.ilog2() - extra_bits(COUNT))) as u16
+}
+
+// Plausible 12 bit temperature readings. By the datasheet formula at 3.3 V the RP2040's
+// rated -20..=85 C reads 977 down to 752, the sensor's voltage falls as it warms. A vref
+// a few percent off moves that by up to about 50, so 700..=1030. Anything outside is
+// a glitch, not weather.
+pub const TEMPERATURE_RAW_MIN: u16 = 700;
+pub const TEMPERATURE_RAW_MAX: u16 = 1030;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AdcError {
+    OutOfRange,
+}
+
+// Oversampled reading as it comes from decimate(), compared at 12 bits
+pub const fn check_temperature(raw: u16) -> Result<u16, AdcError> {
+    let raw_12_bit = raw >> extra_bits(TEMPERATURE_OVERSAMPLE);
+    if raw_12_bit < TEMPERATURE_RAW_MIN || raw_12_bit > TEMPERATURE_RAW_MAX {
+        return Err(AdcError::OutOfRange);
+    }
+    Ok(raw)
+}
+
+// Takes COUNT one-shot samples of the temperature sensor, see decimate()
+pub fn oversample_temperature<const COUNT: usize>(adc: &mut Adc, sensor: &mut TempSense) -> u16 {
+    let mut samples = [0u16; COUNT];
+    adc_dma::paused(|| {
+        for sample in &mut samples {
+            *sample = adc.read(sensor).unwrap();
+        }
+    });
+    decimate(&samples)
+}
@@ -0,0 +1,18 @@
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+
+// Two quick white blinks when we meet a new badge
+pub const ACK_MS: u32 = 300;
+const BLINK_MS: u32 = 75;
+
+pub fn render(state: &AnimationState, channels: &mut PwmChannels, elapsed_ms: u32) {
+    if (elapsed_ms / BLINK_MS).is_multiple_of(2) {
+        let (r, g, b) = gamma3(eye_duties(Hsv::WHITE, state.led_config));
+        channels.set_left_eye(r, g, b);
+        channels.set_right_eye(r, g, b);
+        channels.set_heart(r, g, b);
+    } else {
+        channels.set_all_off();
+    }
+}
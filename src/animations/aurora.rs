@@ -0,0 +1,67 @@
+use core::f32::consts::TAU;
+
+use super::palettes;
+use super::{eye_rgb_duties, frame_ms, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Srgb;
+use crate::led_config::LedConfig;
+use crate::noise;
+
+// Band cycles per second, one full sweep in about 30 s
+pub const AURORA_SPEED: f32 = 1.0 / 30.0;
+
+// How far behind the left eye each group is in the sweep, so the bands move across
+const GROUP_LAG: [f32; 3] = [0.0, 0.15, 0.3];
+
+// Noise per second, the color wanders slower than the brightness flickers
+const COLOR_NOISE_RATE: f32 = 0.2;
+const VALUE_NOISE_RATE: f32 = 0.7;
+// share of the palette the noise pushes the color either way
+const COLOR_NOISE_SPAN: f32 = 0.08;
+
+// Never quite dark, the sky keeps a faint glow between bands
+const MIN_VALUE: f32 = 0.15;
+
+// Slow sinusoidal bands through a palette, sweeping from the left eye over the right
+// one to the heart, Perlin noise on top so it never looks quite the same
+pub struct AuroraAnimation {
+    time_ms: u32,
+}
+
+impl AuroraAnimation {
+    pub const fn new() -> Self {
+        Self { time_ms: 0 }
+    }
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn duties(&self, group: usize, palette: &[Srgb]) -> (u16, u16, u16) {
+        let seconds = self.time_ms as f32 / 1000.0;
+        let phase = seconds * AURORA_SPEED - GROUP_LAG[group];
+        let band = f32::midpoint(1.0, libm::sinf(phase * TAU));
+
+        // each group gets its own stretch of noise
+        let offset = group as f32 * 100.0;
+        let position = band + noise::perlin1d(seconds * COLOR_NOISE_RATE + offset) * COLOR_NOISE_SPAN;
+        let flicker = f32::midpoint(1.0, noise::perlin1d(seconds * VALUE_NOISE_RATE + offset));
+        let value = MIN_VALUE + (1.0 - MIN_VALUE) * flicker;
+
+        let (r, g, b) = palettes::palette_lerp(palette, position).to_rgb_u16();
+        let scale = |duty: u16| (f32::from(duty) * value) as u16;
+        (scale(r), scale(g), scale(b))
+    }
+
+    pub fn tick(&mut self, delta_ms: u32, palette: &[Srgb], channels: &mut PwmChannels, led_config: LedConfig) {
+        self.time_ms = self.time_ms.wrapping_add(delta_ms);
+        let duties = |group| gamma3(eye_rgb_duties(self.duties(group, palette), led_config));
+        let (r, g, b) = duties(0);
+        channels.set_left_eye(r, g, b);
+        let (r, g, b) = duties(1);
+        channels.set_right_eye(r, g, b);
+        let (r, g, b) = duties(2);
+        channels.set_heart(r, g, b);
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, palette: u8, cold: bool) {
+    state.aurora.tick(frame_ms(cold), palettes::palette(palette), channels, state.led_config);
+}
@@ -0,0 +1,74 @@
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::rng::XorShift32;
+
+// Two generations a second
+pub const DEFAULT_STEP_INTERVAL_MS: u32 = 500;
+
+// Rule 30 is chaos, 110 is Turing complete: complex but it doesn't run wild
+pub const DEFAULT_RULE: u8 = 30;
+
+const CELLS: u32 = 24;
+const CELL_MASK: u32 = (1 << CELLS) - 1;
+
+// Dark groups would look broken, so even an empty byte glows a little
+const MIN_VALUE: f32 = 0.2;
+
+// One bit per cell in a ring of 24, a byte each for left eye, right eye and heart.
+// A byte's value picks its group's hue, how many cells are alive its brightness.
+pub struct AutomataAnimation {
+    pub cells: [u8; 3],
+    elapsed_ms: u32,
+}
+
+impl AutomataAnimation {
+    pub const fn new() -> Self {
+        Self {
+            // one live cell in the middle, the classic start
+            cells: [0, 0x80, 0],
+            elapsed_ms: 0,
+        }
+    }
+
+    // Next generation: each cell looks at itself and both neighbours, the ring wraps
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn step(&mut self, rule: u8, rng: &mut XorShift32) {
+        let [a, b, c] = self.cells;
+        let state = u32::from_be_bytes([0, a, b, c]);
+        // higher bits are to the left, so cell i's left neighbour is bit i + 1
+        let left = (state >> 1 | state << (CELLS - 1)) & CELL_MASK;
+        let right = (state << 1 | state >> (CELLS - 1)) & CELL_MASK;
+        let mut next = 0;
+        for i in 0..CELLS {
+            let pattern = (left >> i & 1) << 2 | (state >> i & 1) << 1 | (right >> i & 1);
+            next |= u32::from(rule >> pattern & 1) << i;
+        }
+        // plenty of rules die out or fill up, start them over from noise
+        if next == 0 || next == CELL_MASK {
+            next = rng.next_u32() & CELL_MASK;
+        }
+        let [_, a, b, c] = next.to_be_bytes();
+        self.cells = [a, b, c];
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn color(cell: u8) -> Hsv {
+        let value = MIN_VALUE + (1.0 - MIN_VALUE) * cell.count_ones() as f32 / 8.0;
+        Hsv::from_f32(f32::from(cell) * 360.0 / 256.0, 1.0, value)
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, rule: u8, step_interval_ms: u32, delta_ms: u32) {
+    let automata = &mut state.automata;
+    automata.elapsed_ms += delta_ms;
+    if automata.elapsed_ms >= step_interval_ms.max(1) {
+        automata.elapsed_ms = 0;
+        automata.step(rule, &mut state.rng);
+    }
+
+    let [left, right, heart] = state.automata.cells.map(|cell| gamma3(eye_duties(AutomataAnimation::color(cell), state.led_config)));
+    channels.set_left_eye(left.0, left.1, left.2);
+    channels.set_right_eye(right.0, right.1, right.2);
+    channels.set_heart(heart.0, heart.1, heart.2);
+}
@@ -0,0 +1,49 @@
+use crate::bsp::prelude::PwmChannels;
+
+// Every 10 s the heart spells the temperature in whole degrees, 8 bits MSB first.
+// Each bit starts with a short red tick to count by, then blue for a 1, dark for a 0.
+pub const INTERVAL_MS: u32 = 10_000;
+const BITS: u32 = 8;
+const BIT_MS: u32 = 200;
+const SEPARATOR_MS: u32 = 50;
+
+// What the heart shows, out of the frames
+pub struct BinaryBlinker {
+    value: u8,
+    elapsed_ms: u32,
+}
+
+impl BinaryBlinker {
+    pub const fn new(value: u8) -> Self {
+        Self { value, elapsed_ms: 0 }
+    }
+
+    // Below zero blinks as 0, over 255 as 255
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self::new(libm::roundf(celsius).clamp(0.0, 255.0) as u8)
+    }
+
+    // Heart's red and blue after `delta_ms`, None once all bits are out
+    pub const fn tick(&mut self, delta_ms: u32) -> Option<(bool, bool)> {
+        let elapsed = self.elapsed_ms;
+        if elapsed >= BITS * BIT_MS {
+            return None;
+        }
+        self.elapsed_ms += delta_ms;
+        if elapsed % BIT_MS < SEPARATOR_MS {
+            return Some((true, false));
+        }
+        let bit = BITS - 1 - elapsed / BIT_MS;
+        Some((false, (self.value >> bit) & 1 == 1))
+    }
+
+    // Leaves the eyes alone, `duty` is whatever the heart may go up to
+    pub fn render(&mut self, channels: &mut PwmChannels, delta_ms: u32, duty: u16) -> bool {
+        let Some((red, blue)) = self.tick(delta_ms) else {
+            return false;
+        };
+        channels.set_heart(if red { duty } else { 0 }, 0, if blue { duty } else { 0 });
+        true
+    }
+}
@@ -0,0 +1,45 @@
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::gamma::gamma_correct;
+
+// Easter egg for whoever touches the nose. Nothing else gets drawn until it's over,
+// then whatever mode was on carries on.
+pub const BOOP_MS: u32 = 1000;
+// eyes go all the way round the colour wheel this fast
+const RAINBOW_MS: u32 = 500;
+// white heart flashes, evenly spread over the whole thing
+const FLASHES: u32 = 3;
+
+pub struct NoseBoopAnimation {
+    elapsed_ms: u32,
+}
+
+impl NoseBoopAnimation {
+    pub const fn new() -> Self {
+        Self { elapsed_ms: 0 }
+    }
+
+    pub const fn is_done(&self) -> bool {
+        self.elapsed_ms >= BOOP_MS
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn render(&mut self, state: &AnimationState, channels: &mut PwmChannels, delta_ms: u32) {
+        let hue = (self.elapsed_ms % RAINBOW_MS) as f32 * 360.0 / RAINBOW_MS as f32;
+        let (r, g, b) = gamma3(eye_duties(Hsv::from_f32(hue, 1.0, 1.0), state.led_config));
+        channels.set_left_eye(r, g, b);
+        channels.set_right_eye(r, g, b);
+
+        // first half of every flash lit
+        let flash_ms = BOOP_MS / FLASHES;
+        let heart = if self.elapsed_ms % flash_ms < flash_ms / 2 {
+            gamma_correct(state.led_config.max_heart_duty)
+        } else {
+            0
+        };
+        channels.set_heart(heart, heart, heart);
+
+        self.elapsed_ms += delta_ms;
+    }
+}
@@ -0,0 +1,39 @@
+use cortex_m::delay::Delay;
+
+use crate::bsp::prelude::PwmChannels;
+use crate::color::{self, HUE_CIRCLE};
+
+const STEP_MS: u32 = 150;
+const CYCLE_STEPS: u16 = 40;
+const CYCLE_STEP_MS: u32 = 10;
+
+// About one second sweep so you can see every LED works before the real show:
+// left eye white, right eye white, heart white, all off, then all through RGB.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn run_boot_animation(pwm_channels: &mut PwmChannels, delay: &mut Delay) {
+    let c = pwm_channels;
+
+    c.set_left_eye(u16::MAX, u16::MAX, u16::MAX);
+    delay.delay_ms(STEP_MS);
+
+    c.set_left_eye(0, 0, 0);
+    c.set_right_eye(u16::MAX, u16::MAX, u16::MAX);
+    delay.delay_ms(STEP_MS);
+
+    c.set_right_eye(0, 0, 0);
+    c.set_heart(u16::MAX, u16::MAX, u16::MAX);
+    delay.delay_ms(STEP_MS);
+
+    c.set_all_off();
+    delay.delay_ms(STEP_MS);
+
+    for step in 0..CYCLE_STEPS {
+        let (r, g, b) = color::hue_to_rgb_u16(u32::from(step) * HUE_CIRCLE / u32::from(CYCLE_STEPS));
+        c.set_left_eye(r, g, b);
+        c.set_right_eye(r, g, b);
+        c.set_heart(r, g, b);
+        delay.delay_ms(CYCLE_STEP_MS);
+    }
+
+    c.set_all_off();
+}
@@ -0,0 +1,19 @@
+use super::eye::scale_duty;
+use super::{frame_ms, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::gamma::gamma_correct;
+
+// Eyes and heart all breathe together, red when warm and blue when cold
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, cold: bool) {
+    state.heart_breath.set_cold(cold);
+    let delta = state.heart_breath.phase_delta(frame_ms(cold));
+    let (red, blue) = state.heart_breath.tick(delta);
+    let (red, blue) = (gamma_correct(red), gamma_correct(blue));
+
+    // heart breath runs at heart brightness, bring eyes to their own max
+    let max_eye = state.led_config.max_eye_duty;
+    let (eye_red, eye_blue) = (scale_duty(red, max_eye), scale_duty(blue, max_eye));
+    channels.set_left_eye(eye_red, 0, eye_blue);
+    channels.set_right_eye(eye_red, 0, eye_blue);
+    channels.set_heart(red, 0, blue);
+}
@@ -0,0 +1,58 @@
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::led_config::LedConfig;
+use crate::rng::XorShift32;
+
+// 4 Hz on average, each group drawing its own hold time around it so they drift
+// apart and never change together for long
+const MIN_HOLD_MS: u32 = 180;
+const MAX_HOLD_MS: u32 = 320;
+
+// A new color is at least this far round the wheel from the last one, so every
+// change is a jump and not a barely visible nudge
+const MIN_HUE_JUMP: u32 = 60;
+
+// Left eye, right eye, heart: a random hue each, held steady and then swapped for
+// another without any fade in between
+pub struct DiscoAnimation {
+    rng: XorShift32,
+    // until each group changes color
+    next_change_ms: [u32; 3],
+    colors: [Hsv; 3],
+}
+
+impl DiscoAnimation {
+    // Different start times, so the groups are out of step from the first beat on
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            rng: XorShift32::new(seed),
+            next_change_ms: [0, MIN_HOLD_MS / 3, MIN_HOLD_MS * 2 / 3],
+            colors: [Hsv::from_degrees(0), Hsv::from_degrees(120), Hsv::from_degrees(240)],
+        }
+    }
+
+    pub fn tick(&mut self, delta_ms: u32, channels: &mut PwmChannels, led_config: LedConfig) {
+        for group in 0..self.colors.len() {
+            if self.next_change_ms[group] <= delta_ms {
+                let jump = self.rng.next_range(MIN_HUE_JUMP, 360 - MIN_HUE_JUMP + 1);
+                self.colors[group] = Hsv::from_degrees((self.colors[group].degrees() + jump) % 360);
+                self.next_change_ms[group] = self.rng.next_range(MIN_HOLD_MS, MAX_HOLD_MS + 1);
+            } else {
+                self.next_change_ms[group] -= delta_ms;
+            }
+        }
+
+        let duties = |color: Hsv| gamma3(eye_duties(color, led_config));
+        let (r, g, b) = duties(self.colors[0]);
+        channels.set_left_eye(r, g, b);
+        let (r, g, b) = duties(self.colors[1]);
+        channels.set_right_eye(r, g, b);
+        let (r, g, b) = duties(self.colors[2]);
+        channels.set_heart(r, g, b);
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, delta_ms: u32) {
+    state.disco.tick(delta_ms, channels, state.led_config);
+}
@@ -0,0 +1,65 @@
+use core::f32::consts::PI;
+
+// All of these take and give 0.0..=1.0, 0 maps to 0 and 1 to 1
+
+pub fn ease_in_quad(t: f32) -> f32 {
+    t * t
+}
+
+pub fn ease_out_quad(t: f32) -> f32 {
+    t * (2.0 - t)
+}
+
+pub fn ease_in_out_cubic(t: f32) -> f32 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        let u = 2.0 * t - 2.0;
+        0.5 * u * u * u + 1.0
+    }
+}
+
+pub fn ease_in_out_sine(t: f32) -> f32 {
+    (1.0 - libm::cosf(t * PI)) / 2.0
+}
+
+// Goes from `start` to `end` in `duration_ms`, along `easing`
+#[derive(Clone, Copy)]
+pub struct Interpolator {
+    pub start: f32,
+    pub end: f32,
+    pub duration_ms: u32,
+    pub elapsed_ms: u32,
+    easing: fn(f32) -> f32,
+}
+
+impl Interpolator {
+    pub const fn new(start: f32, end: f32, duration_ms: u32, easing: fn(f32) -> f32) -> Self {
+        Self {
+            start,
+            end,
+            duration_ms,
+            elapsed_ms: 0,
+            easing,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn value(&self) -> f32 {
+        if self.is_done() {
+            return self.end;
+        }
+        let t = self.elapsed_ms as f32 / self.duration_ms as f32;
+        self.start + (self.end - self.start) * (self.easing)(t)
+    }
+
+    // Advances and returns where we are now
+    pub fn tick(&mut self, delta_ms: u32) -> f32 {
+        self.elapsed_ms = self.elapsed_ms.saturating_add(delta_ms).min(self.duration_ms);
+        self.value()
+    }
+
+    pub const fn is_done(&self) -> bool {
+        self.elapsed_ms >= self.duration_ms
+    }
+}
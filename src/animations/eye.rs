@@ -0,0 +1,52 @@
+use super::easing;
+
+// Closing or opening takes this long
+pub const BLINK_MS: u32 = 100;
+
+// Eye lid, progress runs 0..=255 through a closing or opening move
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EyeTransition {
+    Open,
+    Closing(u8),
+    Closed,
+    Opening(u8),
+}
+
+impl EyeTransition {
+    // Start moving the lid towards closed or open, continuing from where it is
+    pub const fn set_closed(&mut self, closed: bool) {
+        *self = match (*self, closed) {
+            (Self::Open, true) => Self::Closing(0),
+            (Self::Opening(progress), true) => Self::Closing(u8::MAX - progress),
+            (Self::Closed, false) => Self::Opening(0),
+            (Self::Closing(progress), false) => Self::Opening(u8::MAX - progress),
+            (state, _) => state,
+        };
+    }
+
+    // Advances the lid and returns duty multiplier, 0 = closed, u16::MAX = open.
+    // Lid speeds up and slows down at the ends, like a real one.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn tick(&mut self, delta_ms: u32) -> u16 {
+        let step = (delta_ms * u32::from(u8::MAX) / BLINK_MS).min(u32::from(u8::MAX)) as u8;
+
+        *self = match *self {
+            Self::Closing(progress) => progress.checked_add(step).map_or(Self::Closed, Self::Closing),
+            Self::Opening(progress) => progress.checked_add(step).map_or(Self::Open, Self::Opening),
+            state => state,
+        };
+
+        let open = match *self {
+            Self::Open => return u16::MAX,
+            Self::Closed => return 0,
+            Self::Closing(progress) => u8::MAX - progress,
+            Self::Opening(progress) => progress,
+        };
+        (easing::ease_in_out_sine(f32::from(open) / f32::from(u8::MAX)) * f32::from(u16::MAX)) as u16
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn scale_duty(duty: u16, multiplier: u16) -> u16 {
+    (u32::from(duty) * u32::from(multiplier) / u32::from(u16::MAX)) as u16
+}
@@ -0,0 +1,35 @@
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::led_config::LedConfig;
+use crate::rng::XorShift32;
+
+// Each LED group picks a new flame color on average every this many frames,
+// independently of the others
+const FLICKER_ODDS: u32 = 4;
+
+fn flame(rng: &mut XorShift32, led_config: LedConfig) -> (u16, u16, u16) {
+    let hue = rng.next_f32() * 30.0;
+    let value = 0.4 + rng.next_f32() * 0.6;
+    gamma3(eye_duties(Hsv::from_f32(hue, 1.0, value), led_config))
+}
+
+// Orange-red flicker on eyes and heart
+pub fn fire_tick(rng: &mut XorShift32, channels: &mut PwmChannels, led_config: LedConfig) {
+    if rng.next_u32().is_multiple_of(FLICKER_ODDS) {
+        let (r, g, b) = flame(rng, led_config);
+        channels.set_left_eye(r, g, b);
+    }
+    if rng.next_u32().is_multiple_of(FLICKER_ODDS) {
+        let (r, g, b) = flame(rng, led_config);
+        channels.set_right_eye(r, g, b);
+    }
+    if rng.next_u32().is_multiple_of(FLICKER_ODDS) {
+        let (r, g, b) = flame(rng, led_config);
+        channels.set_heart(r, g, b);
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels) {
+    fire_tick(&mut state.rng, channels, state.led_config);
+}
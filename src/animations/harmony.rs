@@ -0,0 +1,87 @@
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::led_config::LedConfig;
+
+// Left eye's hue goes round once in this long, everything else follows it
+const PERIOD_MS: u32 = 20_000;
+
+// Complementary heart is the left eye's color, dimmer so the eyes carry the contrast
+const HEART_VALUE: f32 = 0.5;
+
+// Only the left eye has a hue of its own, the right eye's is the opposite one on the
+// wheel, worked out from it every frame so the two can't drift apart
+pub struct ComplementaryRenderer {
+    time_ms: u32,
+}
+
+impl ComplementaryRenderer {
+    pub const fn new() -> Self {
+        Self { time_ms: 0 }
+    }
+
+    pub const fn advance(&mut self, delta_ms: u32) {
+        self.time_ms = (self.time_ms + delta_ms) % PERIOD_MS;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn left_hue(&self) -> f32 {
+        self.time_ms as f32 / PERIOD_MS as f32 * 360.0
+    }
+
+    pub fn right_hue(&self) -> f32 {
+        (self.left_hue() + 180.0) % 360.0
+    }
+
+    pub fn tick(&mut self, delta_ms: u32, channels: &mut PwmChannels, led_config: LedConfig) {
+        self.advance(delta_ms);
+        let duties = |hue: f32, value: f32| gamma3(eye_duties(Hsv::from_f32(hue, 1.0, value), led_config));
+        let (r, g, b) = duties(self.left_hue(), 1.0);
+        channels.set_left_eye(r, g, b);
+        let (r, g, b) = duties(self.right_hue(), 1.0);
+        channels.set_right_eye(r, g, b);
+        let (r, g, b) = duties(self.left_hue(), HEART_VALUE);
+        channels.set_heart(r, g, b);
+    }
+}
+
+// The same left eye with the other two a third of the wheel either side, the heart
+// 120° on and the right eye 240°. Complementary's 180° wouldn't be a triad.
+pub struct TriadicAnimation {
+    pub eyes: ComplementaryRenderer,
+}
+
+impl TriadicAnimation {
+    pub const fn new() -> Self {
+        Self {
+            eyes: ComplementaryRenderer::new(),
+        }
+    }
+
+    pub fn heart_hue(&self) -> f32 {
+        (self.eyes.left_hue() + 120.0) % 360.0
+    }
+
+    pub fn right_hue(&self) -> f32 {
+        (self.eyes.left_hue() + 240.0) % 360.0
+    }
+
+    pub fn tick(&mut self, delta_ms: u32, channels: &mut PwmChannels, led_config: LedConfig) {
+        self.eyes.advance(delta_ms);
+        let duties = |hue: f32| gamma3(eye_duties(Hsv::from_f32(hue, 1.0, 1.0), led_config));
+        let (r, g, b) = duties(self.eyes.left_hue());
+        channels.set_left_eye(r, g, b);
+        let (r, g, b) = duties(self.right_hue());
+        channels.set_right_eye(r, g, b);
+        let (r, g, b) = duties(self.heart_hue());
+        channels.set_heart(r, g, b);
+    }
+}
+
+pub fn render_complementary(state: &mut AnimationState, channels: &mut PwmChannels, delta_ms: u32) {
+    state.complementary.tick(delta_ms, channels, state.led_config);
+}
+
+pub fn render_triadic(state: &mut AnimationState, channels: &mut PwmChannels, delta_ms: u32) {
+    state.triadic.tick(delta_ms, channels, state.led_config);
+}
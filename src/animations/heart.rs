@@ -0,0 +1,199 @@
+use core::f32::consts::PI;
+
+use super::easing;
+use super::keyframe::KeyframeTrack;
+use crate::led_config;
+
+// Full breath cycle takes this long
+pub const DEFAULT_BREATH_PERIOD_MS: u32 = 4000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HeartMode {
+    // quick lub-dub beat from a spring ringing down
+    Pulse,
+    // slow sinusoidal breathing
+    Breath,
+    // lub-dub drawn from BEAT_WAVEFORM
+    Waveform,
+    // lub-dub from CardiacRhythm's state machine
+    Rhythm,
+}
+
+// Smooth breathing heart. Phase wraps around at u16::MAX, one wrap is one breath.
+pub struct HeartBreath {
+    phase: u16,
+    period_ms: u32,
+    max_duty: u16,
+    cold: bool,
+}
+
+impl HeartBreath {
+    pub const fn new(period_ms: u32, max_duty: u16) -> Self {
+        Self {
+            phase: 0,
+            period_ms,
+            max_duty,
+            cold: false,
+        }
+    }
+
+    // Cold heart breathes blue, warm one red
+    pub const fn set_cold(&mut self, cold: bool) {
+        self.cold = cold;
+    }
+
+    // How much phase advances in `delta_ms`
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn phase_delta(&self, delta_ms: u32) -> u16 {
+        (delta_ms * 0x1_0000 / self.period_ms) as u16
+    }
+
+    // Returns (red_duty, blue_duty)
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn tick(&mut self, phase_delta: u16) -> (u16, u16) {
+        self.phase = self.phase.wrapping_add(phase_delta);
+
+        let angle = f32::from(self.phase) / 65_536.0 * 2.0 * PI;
+        let level = (1.0 - libm::cosf(angle)) / 2.0;
+        let main = (level * f32::from(self.max_duty)) as u16;
+        let glow = main / 16;
+
+        if self.cold {
+            (glow, main)
+        } else {
+            (main, glow)
+        }
+    }
+}
+
+// Beat spring, in units of full heart duty per second. A kick sends the heart one way,
+// it swings back past rest for the second, weaker beat and dies out. Brightness is
+// how far it is from rest, either way. A cold heart dies out faster.
+pub const SPRING_STIFFNESS: f32 = 250.0; // swings back in about 0.2 s
+pub const WARM_SPRING_DAMPING: f32 = 7.0; // second beat about half of the first
+pub const COLD_SPRING_DAMPING: f32 = 11.0; // second beat about a third of the first
+pub const SPRING_IMPULSE: f32 = -18.0;
+
+// One semi-implicit Euler step towards `target`, returns the new (position, velocity)
+pub fn spring_tick(pos: f32, vel: f32, target: f32, k: f32, damping: f32, dt: f32) -> (f32, f32) {
+    let accel = -k * (pos - target) - damping * vel;
+    let vel = vel + accel * dt;
+    (pos + vel * dt, vel)
+}
+
+pub struct HeartSpring {
+    pos: f32,
+    vel: f32,
+}
+
+impl HeartSpring {
+    pub const fn new() -> Self {
+        Self { pos: 0.0, vel: 0.0 }
+    }
+
+    pub const fn beat(&mut self) {
+        self.vel = SPRING_IMPULSE;
+    }
+
+    // Returns (main, glow) duty after `delta_ms`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+    pub fn tick(&mut self, delta_ms: u32, max_duty: u16, cold: bool) -> (u16, u16) {
+        let dt = delta_ms as f32 / 1000.0;
+        let damping = if cold { COLD_SPRING_DAMPING } else { WARM_SPRING_DAMPING };
+        (self.pos, self.vel) = spring_tick(self.pos, self.vel, 0.0, SPRING_STIFFNESS, damping, dt);
+
+        let level = libm::fabsf(self.pos).min(1.0);
+        let main = (level * f32::from(max_duty)) as u16;
+        (main, main / led_config::HEART_GLOW_DIVISOR)
+    }
+}
+
+// One beat as a share of full heart duty: lub, a dip, dub, then fade out
+pub const BEAT_WAVEFORM: KeyframeTrack<f32, 5> = KeyframeTrack::new(
+    [(0, 0.0), (50, 1.0), (150, 0.6), (300, 1.0), (600, 0.0)],
+    easing::ease_in_out_sine,
+);
+
+// Returns (main, glow) duty `beat_ms` into a beat
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn waveform_duties(beat_ms: u32, max_duty: u16) -> (u16, u16) {
+    let main = (BEAT_WAVEFORM.sample(beat_ms) * f32::from(max_duty)) as u16;
+    (main, main / led_config::HEART_GLOW_DIVISOR)
+}
+
+// Lub is the stronger and longer of the two. Durations are in frames like the beat
+// itself (every 100 frames), so a cold heart beats slower all through.
+const LUB_INTENSITY: f32 = 1.0;
+const DUB_INTENSITY: f32 = 0.65;
+const LUB_FRAMES: u32 = 12;
+const LUB_RELAX_FRAMES: u32 = 10;
+const DUB_FRAMES: u32 = 8;
+const DUB_RELAX_FRAMES: u32 = 14;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RhythmState {
+    // rising to the intensity
+    Lub(f32),
+    LubRelax,
+    Dub(f32),
+    DubRelax,
+    // dark until the next beat()
+    Wait,
+}
+
+pub struct CardiacRhythm {
+    state: RhythmState,
+    // frames into the current state
+    phase: u32,
+}
+
+impl CardiacRhythm {
+    pub const fn new() -> Self {
+        Self {
+            state: RhythmState::Wait,
+            phase: 0,
+        }
+    }
+
+    pub const fn beat(&mut self) {
+        self.state = RhythmState::Lub(LUB_INTENSITY);
+        self.phase = 0;
+    }
+
+    // Share of full duty in this state, and whether it's over
+    #[allow(clippy::cast_precision_loss)]
+    fn level(&self) -> (f32, bool) {
+        let progress = |frames: u32| (self.phase as f32 / frames as f32).min(1.0);
+        match self.state {
+            RhythmState::Lub(intensity) => (intensity * easing::ease_in_out_sine(progress(LUB_FRAMES)), self.phase >= LUB_FRAMES),
+            RhythmState::LubRelax => (
+                LUB_INTENSITY * (1.0 - easing::ease_in_out_sine(progress(LUB_RELAX_FRAMES))),
+                self.phase >= LUB_RELAX_FRAMES,
+            ),
+            RhythmState::Dub(intensity) => (intensity * easing::ease_in_out_sine(progress(DUB_FRAMES)), self.phase >= DUB_FRAMES),
+            RhythmState::DubRelax => (
+                DUB_INTENSITY * (1.0 - easing::ease_in_out_sine(progress(DUB_RELAX_FRAMES))),
+                self.phase >= DUB_RELAX_FRAMES,
+            ),
+            RhythmState::Wait => (0.0, false),
+        }
+    }
+
+    // One frame on, returns (main, glow) duty
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn tick(&mut self, max_duty: u16) -> (u16, u16) {
+        let (level, done) = self.level();
+        self.phase += 1;
+        if done {
+            self.phase = 0;
+            self.state = match self.state {
+                RhythmState::Lub(_) => RhythmState::LubRelax,
+                RhythmState::LubRelax => RhythmState::Dub(DUB_INTENSITY),
+                RhythmState::Dub(_) => RhythmState::DubRelax,
+                RhythmState::DubRelax | RhythmState::Wait => RhythmState::Wait,
+            };
+        }
+        let main = (level * f32::from(max_duty)) as u16;
+        (main, main / led_config::HEART_GLOW_DIVISOR)
+    }
+}
@@ -0,0 +1,56 @@
+use core::f32::consts::TAU;
+
+use super::{eye_duties, frame_ms, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::led_config::LedConfig;
+
+// Shimmer periods are different so the left eye never quite repeats
+const HUE_PERIOD_MS: u32 = 3000;
+const SATURATION_PERIOD_MS: u32 = 2300;
+const VALUE_PERIOD_MS: u32 = 1700;
+const HEART_PERIOD_MS: u32 = 3000;
+
+// Half closed, squinting in the cold
+const RIGHT_EYE_VALUE: f32 = 0.15;
+
+// 0.0..=1.0 sine wave with `period_ms`
+#[allow(clippy::cast_precision_loss)]
+fn wave(time_ms: u32, period_ms: u32) -> f32 {
+    let phase = (time_ms % period_ms) as f32 / period_ms as f32;
+    f32::midpoint(1.0, libm::sinf(phase * TAU))
+}
+
+// Frosty blues: shimmering left eye, squinting right eye and a slow pulsing heart
+pub struct IceAnimation {
+    time_ms: u32,
+}
+
+impl IceAnimation {
+    pub const fn new() -> Self {
+        Self { time_ms: 0 }
+    }
+
+    pub fn tick(&mut self, delta_ms: u32, channels: &mut PwmChannels, led_config: LedConfig) {
+        self.time_ms = self.time_ms.wrapping_add(delta_ms);
+        let t = self.time_ms;
+
+        let hue = 200.0 + 20.0 * wave(t, HUE_PERIOD_MS);
+        let saturation = 0.7 + 0.3 * wave(t, SATURATION_PERIOD_MS);
+        let value = 0.3 + 0.5 * wave(t, VALUE_PERIOD_MS);
+        let (r, g, b) = gamma3(eye_duties(Hsv::from_f32(hue, saturation, value), led_config));
+        channels.set_left_eye(r, g, b);
+
+        let (r, g, b) = gamma3(eye_duties(Hsv::from_f32(hue, saturation, RIGHT_EYE_VALUE), led_config));
+        channels.set_right_eye(r, g, b);
+
+        // squared wave makes a short pulse with a long rest
+        let pulse = wave(t, HEART_PERIOD_MS);
+        let (r, g, b) = gamma3(eye_duties(Hsv::from_f32(195.0, 0.5, pulse * pulse), led_config));
+        channels.set_heart(r, g, b);
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, cold: bool) {
+    state.ice.tick(frame_ms(cold), channels, state.led_config);
+}
@@ -0,0 +1,47 @@
+// Anything that can be blended, t runs 0.0..=1.0 from self to other
+pub trait Lerp {
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+// (time_ms, value) pairs, eased from one to the next. Before the first key it's the
+// first value, after the last one the last value.
+pub struct KeyframeTrack<T: Lerp + Copy, const N: usize> {
+    keys: [(u32, T); N],
+    easing: fn(f32) -> f32,
+}
+
+impl<T: Lerp + Copy, const N: usize> KeyframeTrack<T, N> {
+    // Times have to go up, in const context a bad table fails the build
+    pub const fn new(keys: [(u32, T); N], easing: fn(f32) -> f32) -> Self {
+        assert!(N > 0, "a track needs at least one keyframe");
+        let mut i = 1;
+        while i < N {
+            assert!(keys[i - 1].0 < keys[i].0, "keyframe times have to go up");
+            i += 1;
+        }
+        Self { keys, easing }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn sample(&self, time_ms: u32) -> T {
+        // first key that's later than `time_ms`
+        let next = self.keys.partition_point(|&(time, _)| time <= time_ms);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == N {
+            return self.keys[N - 1].1;
+        }
+
+        let (from_ms, from) = self.keys[next - 1];
+        let (to_ms, to) = self.keys[next];
+        let t = (time_ms - from_ms) as f32 / (to_ms - from_ms) as f32;
+        from.lerp(to, (self.easing)(t))
+    }
+}
@@ -0,0 +1,54 @@
+use core::f32::consts::TAU;
+
+use super::{eye_rgb_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::{self, Srgb};
+
+// Once round the a*b* circle in this long
+const PERIOD_MS: u32 = 10_000;
+
+// Bright enough to see, low enough that most of the circle is still inside sRGB.
+// Any higher and yellow clamps while blue can't keep up.
+pub const DEFAULT_LIGHTNESS: u8 = 60;
+pub const DEFAULT_CHROMA: u8 = 40;
+
+// L* goes to 100, chroma past this is out of sRGB all the way round
+pub const MAX_LIGHTNESS: u8 = 100;
+pub const MAX_CHROMA: u8 = 127;
+
+// Lightness and chroma of `color`, for cycling at how bright it looks
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn lightness_and_chroma(color: Srgb) -> (u8, u8) {
+    let (l, a, b) = color::rgb_to_lab(color);
+    let chroma = libm::hypotf(a, b);
+    (
+        (l.clamp(0.0, f32::from(MAX_LIGHTNESS)) + 0.5) as u8,
+        (chroma.clamp(0.0, f32::from(MAX_CHROMA)) + 0.5) as u8,
+    )
+}
+
+// A rainbow that keeps the same L*, so no part of it stands out brighter. Eyes and
+// heart are a third of the circle apart. Soft float cube roots and powers, nine a
+// frame, like kelvin_to_rgb it's affordable.
+#[allow(clippy::cast_precision_loss)]
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, lightness: u8, chroma: u8, delta_ms: u32) {
+    state.lab_cycle_ms = (state.lab_cycle_ms + delta_ms) % PERIOD_MS;
+    let angle = state.lab_cycle_ms as f32 / PERIOD_MS as f32 * TAU;
+    let (lightness, chroma) = (f32::from(lightness), f32::from(chroma));
+    let led_config = state.led_config;
+    let duties = |offset: f32| {
+        let color = color::lab_to_rgb(
+            lightness,
+            chroma * libm::cosf(angle + offset),
+            chroma * libm::sinf(angle + offset),
+        );
+        gamma3(eye_rgb_duties(color.to_rgb_u16(), led_config))
+    };
+
+    let (r, g, b) = duties(0.0);
+    channels.set_left_eye(r, g, b);
+    let (r, g, b) = duties(TAU / 3.0);
+    channels.set_right_eye(r, g, b);
+    let (r, g, b) = duties(TAU * 2.0 / 3.0);
+    channels.set_heart(r, g, b);
+}
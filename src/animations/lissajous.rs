@@ -0,0 +1,60 @@
+use core::f32::consts::TAU;
+
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+
+// t goes round once in this long, a and b are how many times x and y do meanwhile
+const PERIOD_MS: u32 = 12_000;
+
+// Both eyes wander this far either side of the base hue, so each sweeps the whole wheel
+const HUE_SWING: f32 = 180.0;
+const BASE_HUE: f32 = 200.0;
+
+// x(t) = sin(a t + delta) on the left eye's hue, y(t) = sin(b t) on the right one's,
+// together they trace a Lissajous figure in color. The heart lights with x * y.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LissajousAnimation {
+    pub a: u8,
+    pub b: u8,
+    pub delta_deg: u16,
+}
+
+// The usual figures, the quarter turn keeps them open instead of collapsing to a line
+pub const RATIO_1_2: LissajousAnimation = LissajousAnimation { a: 1, b: 2, delta_deg: 90 };
+pub const RATIO_2_3: LissajousAnimation = LissajousAnimation { a: 2, b: 3, delta_deg: 90 };
+pub const RATIO_3_4: LissajousAnimation = LissajousAnimation { a: 3, b: 4, delta_deg: 90 };
+
+// For `set_lissajous <a:b>`
+pub const FIGURES: [(&str, LissajousAnimation); 3] = [("1:2", RATIO_1_2), ("2:3", RATIO_2_3), ("3:4", RATIO_3_4)];
+
+pub fn find_figure(ratio: &str) -> Option<LissajousAnimation> {
+    FIGURES.iter().find(|&&(name, _)| name == ratio).map(|&(_, figure)| figure)
+}
+
+impl LissajousAnimation {
+    // (x, y) at `time_ms`, both -1.0..=1.0
+    #[allow(clippy::cast_precision_loss)]
+    pub fn point(self, time_ms: u32) -> (f32, f32) {
+        let t = (time_ms % PERIOD_MS) as f32 / PERIOD_MS as f32 * TAU;
+        let delta = f32::from(self.delta_deg) * TAU / 360.0;
+        (
+            libm::sinf(f32::from(self.a) * t + delta),
+            libm::sinf(f32::from(self.b) * t),
+        )
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, figure: LissajousAnimation, delta_ms: u32) {
+    // whole periods are the same figure again, so wrapping there doesn't jump
+    state.lissajous_ms = (state.lissajous_ms + delta_ms) % PERIOD_MS;
+    let (left, right) = figure.point(state.lissajous_ms);
+    let duties = |hue: f32, value: f32| gamma3(eye_duties(Hsv::from_f32(hue, 1.0, value), state.led_config));
+
+    let (r, g, b) = duties(BASE_HUE + left * HUE_SWING, 1.0);
+    channels.set_left_eye(r, g, b);
+    let (r, g, b) = duties(BASE_HUE + right * HUE_SWING, 1.0);
+    channels.set_right_eye(r, g, b);
+    let (r, g, b) = duties(BASE_HUE + f32::midpoint(left, right) * HUE_SWING, f32::midpoint(1.0, left * right));
+    channels.set_heart(r, g, b);
+}
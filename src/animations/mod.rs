@@ -0,0 +1,592 @@
+pub mod ack;
+pub mod aurora;
+pub mod automata;
+pub mod binary;
+pub mod boop;
+pub mod boot;
+pub mod breathe;
+pub mod disco;
+pub mod easing;
+pub mod eye;
+pub mod fire;
+pub mod harmony;
+pub mod heart;
+pub mod ice;
+pub mod keyframe;
+pub mod lab_cycle;
+pub mod lissajous;
+pub mod off;
+pub mod palettes;
+pub mod particles;
+pub mod rainbow;
+pub mod shutdown;
+pub mod siren;
+pub mod sleep;
+pub mod solid;
+pub mod spectral;
+pub mod strip;
+pub mod strobe;
+pub mod thermometer;
+pub mod transition;
+pub mod white_balance;
+
+
+use crate::bsp::prelude::PwmChannels;
+use crate::calibration;
+use crate::color::{self, Hsv, Srgb};
+use crate::filter::TempTrend;
+use crate::gamma::gamma_correct;
+use crate::led_config::LedConfig;
+use crate::rng::XorShift32;
+use crate::timer;
+use eye::EyeTransition;
+use harmony::{ComplementaryRenderer, TriadicAnimation};
+use heart::{CardiacRhythm, HeartBreath, HeartMode, HeartSpring};
+use aurora::AuroraAnimation;
+use automata::AutomataAnimation;
+use disco::DiscoAnimation;
+use ice::IceAnimation;
+use lissajous::LissajousAnimation;
+use particles::ParticleAnimation;
+use siren::{SirenHeart, SirenVariant};
+use spectral::SpectralAnimation;
+use thermometer::ThermometerAnimation;
+
+// Not Eq, Spectral's speeds are floats
+#[derive(Clone, Copy, PartialEq)]
+pub enum AnimationMode {
+    // the classic: rainbow eyes and beating heart
+    Rainbow,
+    // everything breathes slowly
+    Breathe,
+    // fixed colors, no cycling
+    Solid { left_eye: Srgb, right_eye: Srgb, heart: Srgb },
+    Fire,
+    Ice,
+    // northern lights sweeping over eyes and heart, palette is an index into palettes::PALETTES
+    Aurora { palette: u8 },
+    // sparks drifting from the eyes to the heart
+    Particles,
+    Off,
+    // flashes everything, see strobe.rs before turning the rate up
+    Strobe { rate_hz: u8, duty_percent: u8, color: Hsv },
+    // steady white of a color temperature, for filming next to the badge
+    WhiteBalance { kelvin: u16 },
+    // eyes flash one after the other, see siren.rs about the rate
+    PoliceSiren { rate_hz: u8, variant: SirenVariant },
+    // a 1D cellular automaton over the three groups, one generation every step_interval_ms
+    Automata { rule: u8, step_interval_ms: u32 },
+    // the eyes' hues trace a Lissajous figure
+    Lissajous(LissajousAnimation),
+    // rainbow at one L*, so yellow doesn't outshine blue
+    LabCycle { lightness: u8, chroma: u8 },
+    // right eye opposite the left one on the hue wheel
+    Complementary,
+    // and with the heart, three hues a third of the wheel apart
+    Triadic,
+    // hue follows the temperature, blue at min_celsius to red at max_celsius
+    Thermometer { min_celsius: i8, max_celsius: i8 },
+    // a new random color on every beat, each group on its own beat
+    Disco,
+    // every group round the hue wheel at its own speed, 1.0 is once in 10 s
+    Spectral { speeds: [f32; 3] },
+}
+
+// How many steps next() takes to get back where it started, strobe isn't in the loop
+const MODE_COUNT: u32 = 8;
+
+// Magenta, when nobody has picked a color
+pub const DEFAULT_SOLID_COLOR: Srgb = Srgb::new(0xff, 0, 0xff);
+
+// Slow white flashes, under the range that's known to set off seizures
+pub const DEFAULT_STROBE: AnimationMode = AnimationMode::strobe(2, 50, Hsv::WHITE);
+
+pub const DEFAULT_SIREN: AnimationMode = AnimationMode::siren(siren::DEFAULT_RATE_HZ, SirenVariant::Police);
+
+pub const DEFAULT_AUTOMATA: AnimationMode = AnimationMode::Automata {
+    rule: automata::DEFAULT_RULE,
+    step_interval_ms: automata::DEFAULT_STEP_INTERVAL_MS,
+};
+
+// Automata packs its rule under the interval, which gets the other 15 bits
+const AUTOMATA_INTERVAL_SHIFT: u32 = 8;
+
+// Lissajous packs a and b in four bits each, then the phase in degrees
+const LISSAJOUS_B_SHIFT: u32 = 4;
+const LISSAJOUS_DELTA_SHIFT: u32 = 8;
+
+pub const DEFAULT_LAB_CYCLE: AnimationMode = AnimationMode::LabCycle {
+    lightness: lab_cycle::DEFAULT_LIGHTNESS,
+    chroma: lab_cycle::DEFAULT_CHROMA,
+};
+
+// Lab cycle packs the chroma above the lightness
+const LAB_CHROMA_SHIFT: u32 = 8;
+
+pub const DEFAULT_THERMOMETER: AnimationMode = AnimationMode::Thermometer {
+    min_celsius: thermometer::DEFAULT_MIN_CELSIUS,
+    max_celsius: thermometer::DEFAULT_MAX_CELSIUS,
+};
+
+pub const DEFAULT_SPECTRAL: AnimationMode = AnimationMode::Spectral {
+    speeds: spectral::DEFAULT_SPEEDS,
+};
+
+// Thermometer packs both ends as bytes, max above min
+const THERMOMETER_MAX_SHIFT: u32 = 8;
+
+// Siren packs its rate and variant, the variant above the five bits of rate
+const SIREN_VARIANT_SHIFT: u32 = 5;
+
+pub const DEFAULT_AURORA: AnimationMode = AnimationMode::Aurora { palette: 0 };
+
+// Warm white like an indoor bulb, 6500 K is daylight
+pub const DEFAULT_WHITE_KELVIN: u16 = 2700;
+
+// Strobe packs its hue, rate and duty next to each other
+const STROBE_RATE_SHIFT: u32 = 9;
+const STROBE_DUTY_SHIFT: u32 = 14;
+
+impl AnimationMode {
+    // Same color everywhere
+    pub const fn solid(color: Srgb) -> Self {
+        Self::Solid {
+            left_eye: color,
+            right_eye: color,
+            heart: color,
+        }
+    }
+
+    // Used in const context the asserts fail the build, unpack() clamps instead
+    pub const fn strobe(rate_hz: u8, duty_percent: u8, color: Hsv) -> Self {
+        assert!(rate_hz >= strobe::MIN_RATE_HZ, "strobe rate_hz has to be at least 1");
+        assert!(rate_hz <= strobe::MAX_RATE_HZ, "strobe rate_hz over 20 Hz, see strobe.rs");
+        assert!(duty_percent <= 100, "strobe duty_percent over 100");
+        Self::Strobe {
+            rate_hz,
+            duty_percent,
+            color,
+        }
+    }
+
+    // Used in const context the assert fails the build, unpack() clamps instead
+    pub const fn siren(rate_hz: u8, variant: SirenVariant) -> Self {
+        assert!(
+            rate_hz >= siren::MIN_RATE_HZ && rate_hz <= siren::MAX_RATE_HZ,
+            "siren rate_hz has to be 1-8, see siren.rs"
+        );
+        Self::PoliceSiren { rate_hz, variant }
+    }
+
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Rainbow => "rainbow",
+            Self::Breathe => "breathe",
+            Self::Solid { .. } => "solid",
+            Self::Fire => "fire",
+            Self::Ice => "ice",
+            Self::Aurora { .. } => "aurora",
+            Self::Particles => "sparks",
+            Self::Off => "off",
+            Self::Strobe { .. } => "strobe",
+            Self::WhiteBalance { .. } => "white",
+            Self::PoliceSiren {
+                variant: SirenVariant::Police,
+                ..
+            } => "siren",
+            Self::PoliceSiren {
+                variant: SirenVariant::Nordic,
+                ..
+            } => "nordic_siren",
+            Self::Automata { .. } => "automata",
+            Self::Lissajous(_) => "lissajous",
+            Self::LabCycle { .. } => "lab",
+            Self::Complementary => "complementary",
+            Self::Triadic => "triadic",
+            Self::Thermometer { .. } => "thermometer",
+            Self::Disco => "disco",
+            Self::Spectral { .. } => "spectral",
+        }
+    }
+
+    // Name as printed by name(), solid gets the default color
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "rainbow" => Some(Self::Rainbow),
+            "breathe" => Some(Self::Breathe),
+            "solid" => Some(Self::solid(DEFAULT_SOLID_COLOR)),
+            "fire" => Some(Self::Fire),
+            "ice" => Some(Self::Ice),
+            "aurora" => Some(DEFAULT_AURORA),
+            "sparks" => Some(Self::Particles),
+            "off" => Some(Self::Off),
+            "strobe" => Some(DEFAULT_STROBE),
+            "white" => Some(Self::WhiteBalance {
+                kelvin: DEFAULT_WHITE_KELVIN,
+            }),
+            "siren" => Some(DEFAULT_SIREN),
+            "nordic_siren" => Some(Self::siren(siren::DEFAULT_RATE_HZ, SirenVariant::Nordic)),
+            "automata" => Some(DEFAULT_AUTOMATA),
+            "lissajous" => Some(Self::Lissajous(lissajous::RATIO_1_2)),
+            "lab" => Some(DEFAULT_LAB_CYCLE),
+            "complementary" => Some(Self::Complementary),
+            "triadic" => Some(Self::Triadic),
+            "thermometer" => Some(DEFAULT_THERMOMETER),
+            "disco" => Some(Self::Disco),
+            "spectral" => Some(DEFAULT_SPECTRAL),
+            _ => None,
+        }
+    }
+
+    // Next one in line for the button, wraps around
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Rainbow => Self::Breathe,
+            Self::Breathe => Self::solid(DEFAULT_SOLID_COLOR),
+            Self::Solid { .. } => Self::Fire,
+            Self::Fire => Self::Ice,
+            Self::Ice => DEFAULT_AURORA,
+            Self::Aurora { .. } => Self::Particles,
+            Self::Particles => Self::Off,
+            Self::Off | Self::Strobe { .. } | Self::WhiteBalance { .. } | Self::PoliceSiren { .. }
+            | Self::Automata { .. }
+            | Self::Lissajous(_)
+            | Self::LabCycle { .. }
+            | Self::Complementary
+            | Self::Triadic
+            | Self::Thermometer { .. }
+            | Self::Disco
+            | Self::Spectral { .. } => {
+                Self::Rainbow
+            }
+        }
+    }
+
+    // Compact form for the core 1 FIFO and flash: which mode, and up to 23 bits of
+    // parameters. Hue goes in whole degrees, saturation and value don't make it across
+    // except for a white strobe. Solid's nine bytes of color and Spectral's three
+    // floats don't fit at all, they go separately, see solid_colors() and spectral_speeds().
+    #[allow(clippy::cast_sign_loss)]
+    pub fn pack(self) -> (u8, u32) {
+        match self {
+            Self::Rainbow => (0, 0),
+            Self::Breathe => (1, 0),
+            Self::Solid { .. } => (2, 0),
+            Self::Fire => (3, 0),
+            Self::Ice => (4, 0),
+            Self::Aurora { palette } => (9, u32::from(palette)),
+            Self::Particles => (10, 0),
+            Self::Off => (5, 0),
+            Self::Strobe {
+                rate_hz,
+                duty_percent,
+                color,
+            } => {
+                // 360 is out of the hue range, so it can mean white
+                let hue = if color.saturation < 128 { 360 } else { color.degrees() };
+                let rate = u32::from(rate_hz) << STROBE_RATE_SHIFT;
+                (6, hue | rate | u32::from(duty_percent) << STROBE_DUTY_SHIFT)
+            }
+            Self::WhiteBalance { kelvin } => (7, u32::from(kelvin)),
+            Self::PoliceSiren { rate_hz, variant } => (8, u32::from(rate_hz) | (variant as u32) << SIREN_VARIANT_SHIFT),
+            Self::Automata { rule, step_interval_ms } => {
+                (11, u32::from(rule) | step_interval_ms.min(0x7fff) << AUTOMATA_INTERVAL_SHIFT)
+            }
+            Self::Lissajous(figure) => (
+                12,
+                u32::from(figure.a & 0xf)
+                    | u32::from(figure.b & 0xf) << LISSAJOUS_B_SHIFT
+                    | u32::from(figure.delta_deg % 360) << LISSAJOUS_DELTA_SHIFT,
+            ),
+            Self::LabCycle { lightness, chroma } => (13, u32::from(lightness) | u32::from(chroma) << LAB_CHROMA_SHIFT),
+            // the two harmonies share an index
+            Self::Complementary => (14, 0),
+            Self::Triadic => (14, 1),
+            Self::Thermometer {
+                min_celsius,
+                max_celsius,
+            } => (15, u32::from(min_celsius as u8) | u32::from(max_celsius as u8) << THERMOMETER_MAX_SHIFT),
+            Self::Disco => (16, 0),
+            Self::Spectral { .. } => (17, 0),
+        }
+    }
+
+    // None for an index pack() never gives out
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub fn unpack(index: u8, params: u32) -> Option<Self> {
+        let hue = params & ((1 << STROBE_RATE_SHIFT) - 1);
+        match index {
+            0 => Some(Self::Rainbow),
+            1 => Some(Self::Breathe),
+            2 => Some(Self::solid(DEFAULT_SOLID_COLOR)),
+            3 => Some(Self::Fire),
+            4 => Some(Self::Ice),
+            5 => Some(Self::Off),
+            6 => Some(Self::Strobe {
+                rate_hz: ((params >> STROBE_RATE_SHIFT) as u8 & 0x1f).clamp(strobe::MIN_RATE_HZ, strobe::MAX_RATE_HZ),
+                duty_percent: ((params >> STROBE_DUTY_SHIFT) as u8 & 0x7f).min(100),
+                color: if hue >= 360 { Hsv::WHITE } else { Hsv::from_degrees(hue) },
+            }),
+            7 => Some(Self::WhiteBalance {
+                kelvin: (params as u16).clamp(color::MIN_KELVIN, color::MAX_KELVIN),
+            }),
+            8 => Some(Self::PoliceSiren {
+                rate_hz: (params as u8 & 0x1f).clamp(siren::MIN_RATE_HZ, siren::MAX_RATE_HZ),
+                variant: if (params >> SIREN_VARIANT_SHIFT) & 1 == 0 {
+                    SirenVariant::Police
+                } else {
+                    SirenVariant::Nordic
+                },
+            }),
+            9 => Some(Self::Aurora {
+                palette: if (params as usize) < palettes::PALETTES.len() { params as u8 } else { 0 },
+            }),
+            10 => Some(Self::Particles),
+            11 => Some(Self::Automata {
+                rule: params as u8,
+                step_interval_ms: (params >> AUTOMATA_INTERVAL_SHIFT & 0x7fff).max(1),
+            }),
+            12 => Some(Self::Lissajous(LissajousAnimation {
+                a: params as u8 & 0xf,
+                b: (params >> LISSAJOUS_B_SHIFT) as u8 & 0xf,
+                delta_deg: (params >> LISSAJOUS_DELTA_SHIFT) as u16 % 360,
+            })),
+            13 => Some(Self::LabCycle {
+                lightness: (params as u8).min(lab_cycle::MAX_LIGHTNESS),
+                chroma: ((params >> LAB_CHROMA_SHIFT) as u8).min(lab_cycle::MAX_CHROMA),
+            }),
+            14 => Some(if params & 1 == 0 { Self::Complementary } else { Self::Triadic }),
+            15 => Some(Self::Thermometer {
+                min_celsius: params as u8 as i8,
+                max_celsius: (params >> THERMOMETER_MAX_SHIFT) as u8 as i8,
+            }),
+            16 => Some(Self::Disco),
+            17 => Some(DEFAULT_SPECTRAL),
+            _ => None,
+        }
+    }
+
+    // Left eye, right eye and heart for the ones that carry them past pack()
+    pub const fn solid_colors(self) -> Option<[Srgb; 3]> {
+        match self {
+            Self::Solid {
+                left_eye,
+                right_eye,
+                heart,
+            } => Some([left_eye, right_eye, heart]),
+            _ => None,
+        }
+    }
+
+    // Puts colors from solid_colors() back into what unpack() gave, other modes stay as they are
+    pub const fn with_solid_colors(self, [left_eye, right_eye, heart]: [Srgb; 3]) -> Self {
+        match self {
+            Self::Solid { .. } => Self::Solid {
+                left_eye,
+                right_eye,
+                heart,
+            },
+            mode => mode,
+        }
+    }
+
+    // Same again for Spectral
+    pub const fn spectral_speeds(self) -> Option<[f32; 3]> {
+        match self {
+            Self::Spectral { speeds } => Some(speeds),
+            _ => None,
+        }
+    }
+
+    pub const fn with_spectral_speeds(self, speeds: [f32; 3]) -> Self {
+        match self {
+            Self::Spectral { .. } => Self::Spectral { speeds },
+            mode => mode,
+        }
+    }
+
+    // Nothing moves, core 1 gets by with a slow clk_sys, see power::set_sys_clock_slow
+    pub const fn is_steady(&self) -> bool {
+        matches!(self, Self::Off | Self::Solid { .. } | Self::WhiteBalance { .. })
+    }
+
+    // Any mode but this one, for when the badge gets shaken
+    pub const fn random(self, rng: &mut XorShift32) -> Self {
+        let mut mode = self.next();
+        let mut skip = rng.next_range(0, MODE_COUNT - 1);
+        while skip > 0 {
+            mode = mode.next();
+            skip -= 1;
+        }
+        mode
+    }
+}
+
+// What the renderers need to remember from one frame to the next
+pub struct AnimationState {
+    pub led_config: LedConfig,
+    pub heart_mode: HeartMode,
+    pub right_eye: EyeTransition,
+    pub heart_breath: HeartBreath,
+    pub heart_spring: HeartSpring,
+    pub cardiac: CardiacRhythm,
+    // since the last beat started, for the waveform heart
+    pub beat_ms: u32,
+    pub rng: XorShift32,
+    pub ice: IceAnimation,
+    pub aurora: AuroraAnimation,
+    pub particles: ParticleAnimation,
+    pub automata: AutomataAnimation,
+    // how far into the Lissajous period
+    pub lissajous_ms: u32,
+    // how far round the lab cycle
+    pub lab_cycle_ms: u32,
+    pub complementary: ComplementaryRenderer,
+    pub triadic: TriadicAnimation,
+    pub thermometer: ThermometerAnimation,
+    pub disco: DiscoAnimation,
+    pub spectral: SpectralAnimation,
+    pub siren: SirenHeart,
+    // rainbow eyes lean warm or cold while this isn't stable
+    pub trend: TempTrend,
+    // hot band, rainbow goes frantic
+    pub hot: bool,
+}
+
+impl AnimationState {
+    pub const fn new(led_config: LedConfig, heart_mode: HeartMode, seed: u32) -> Self {
+        Self {
+            led_config,
+            heart_mode,
+            right_eye: EyeTransition::Open,
+            heart_breath: HeartBreath::new(heart::DEFAULT_BREATH_PERIOD_MS, led_config.max_heart_duty),
+            heart_spring: HeartSpring::new(),
+            cardiac: CardiacRhythm::new(),
+            beat_ms: 0,
+            rng: XorShift32::new(seed),
+            ice: IceAnimation::new(),
+            aurora: AuroraAnimation::new(),
+            particles: ParticleAnimation::new(),
+            automata: AutomataAnimation::new(),
+            lissajous_ms: 0,
+            lab_cycle_ms: 0,
+            complementary: ComplementaryRenderer::new(),
+            triadic: TriadicAnimation::new(),
+            thermometer: ThermometerAnimation::new(),
+            // its own stream, so the other random animations don't shift when it's on
+            disco: DiscoAnimation::new(seed.rotate_left(16)),
+            spectral: SpectralAnimation::new(),
+            siren: SirenHeart::new(),
+            trend: TempTrend::Stable,
+            hot: false,
+        }
+    }
+}
+
+// The first mode after boot fades in over this long
+pub const BOOT_FADE_MS: u32 = 500;
+
+// Brightness changes take this long, so low power doesn't just snap
+pub const BRIGHTNESS_FADE_MS: u32 = 300;
+
+// Cold alpacca is slower in everything
+pub const fn frame_ms(cold: bool) -> u32 {
+    if cold {
+        20 // slower pulse
+    } else {
+        10 // normal pulse
+    }
+}
+
+// The frame tick is fixed, cold is slower by skipping every other one
+pub const fn frames_per_step(cold: bool) -> u32 {
+    frame_ms(cold) / timer::FRAME_MS
+}
+
+pub fn advance_animation(
+    mode: &AnimationMode,
+    state: &mut AnimationState,
+    channels: &mut PwmChannels,
+    tick: u32,
+    cold: bool,
+) {
+    match *mode {
+        AnimationMode::Rainbow => rainbow::render(state, channels, tick, cold),
+        AnimationMode::Breathe => breathe::render(state, channels, cold),
+        AnimationMode::Solid {
+            left_eye,
+            right_eye,
+            heart,
+        } => solid::render(state, channels, left_eye, right_eye, heart),
+        AnimationMode::Fire => fire::render(state, channels),
+        AnimationMode::Ice => ice::render(state, channels, cold),
+        AnimationMode::Aurora { palette } => aurora::render(state, channels, palette, cold),
+        AnimationMode::Particles => particles::render(state, channels, cold),
+        AnimationMode::Off => off::render(channels),
+        AnimationMode::Strobe {
+            rate_hz,
+            duty_percent,
+            color,
+        } => strobe::render(state, channels, rate_hz, duty_percent, color),
+        AnimationMode::WhiteBalance { kelvin } => white_balance::render(state, channels, kelvin),
+        AnimationMode::Automata { rule, step_interval_ms } => {
+            automata::render(state, channels, rule, step_interval_ms, frame_ms(cold));
+        }
+        AnimationMode::Lissajous(figure) => lissajous::render(state, channels, figure, frame_ms(cold)),
+        AnimationMode::Complementary => harmony::render_complementary(state, channels, frame_ms(cold)),
+        AnimationMode::Triadic => harmony::render_triadic(state, channels, frame_ms(cold)),
+        AnimationMode::Disco => disco::render(state, channels, frame_ms(cold)),
+        AnimationMode::Spectral { speeds } => spectral::render(state, channels, speeds, frame_ms(cold)),
+        AnimationMode::Thermometer {
+            min_celsius,
+            max_celsius,
+        } => thermometer::render(state, channels, min_celsius, max_celsius, frame_ms(cold)),
+        AnimationMode::LabCycle { lightness, chroma } => {
+            lab_cycle::render(state, channels, lightness, chroma, frame_ms(cold));
+        }
+        AnimationMode::PoliceSiren { rate_hz, variant } => {
+            siren::render(state, channels, rate_hz, variant, frame_ms(cold));
+        }
+    }
+}
+
+// Full saturation and value, one entry per degree of hue, worked out at compile time
+const fn eye_lut() -> [(u16, u16, u16); 360] {
+    let mut lut = [(0, 0, 0); 360];
+    let mut degrees: u32 = 0;
+    while degrees < 360 {
+        lut[degrees as usize] = color::hue_to_rgb_u16(degrees * 100);
+        degrees += 1;
+    }
+    lut
+}
+
+pub const EYE_LUT: [(u16, u16, u16); 360] = eye_lut();
+
+// Calibration scales as fractions of 0xffff, so the eyes don't need floats either
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const EYE_GAINS: [u32; 3] = [
+    (calibration::EYE_R_SCALE * 65535.0) as u32,
+    (calibration::EYE_G_SCALE * 65535.0) as u32,
+    (calibration::EYE_B_SCALE * 65535.0) as u32,
+];
+
+// Linear eye duties for a color, red and green are held back since those LEDs look brighter.
+// Hue comes from EYE_LUT, saturation and value are just a mix towards white and a scale.
+#[allow(clippy::cast_possible_truncation)]
+pub fn eye_duties(color: Hsv, led_config: LedConfig) -> (u16, u16, u16) {
+    let hue = EYE_LUT[color.degrees() as usize % 360];
+    eye_rgb_duties(color::saturate_and_scale(hue, color.saturation, color.value), led_config)
+}
+
+// Same for a color that's already RGB, full scale in
+#[allow(clippy::cast_possible_truncation)]
+pub fn eye_rgb_duties((r, g, b): (u16, u16, u16), led_config: LedConfig) -> (u16, u16, u16) {
+    let max_eye = u32::from(led_config.max_eye_duty);
+    let channel = |duty: u16, gain: u32| ((u32::from(duty) * max_eye / 0xffff) * gain / 0xffff) as u16;
+    (channel(r, EYE_GAINS[0]), channel(g, EYE_GAINS[1]), channel(b, EYE_GAINS[2]))
+}
+
+pub fn gamma3((r, g, b): (u16, u16, u16)) -> (u16, u16, u16) {
+    (gamma_correct(r), gamma_correct(g), gamma_correct(b))
+}
@@ -0,0 +1,5 @@
+use crate::bsp::prelude::PwmChannels;
+
+pub fn render(channels: &mut PwmChannels) {
+    channels.set_all_off();
+}
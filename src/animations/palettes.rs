@@ -0,0 +1,47 @@
+use crate::color::Srgb;
+
+// Finnish northern lights, from the green and violet of the aurora itself to the
+// navy sky between the bands
+pub const PALETTE_AURORA: [Srgb; 8] = [
+    Srgb::new(0, 0xff, 0x88), // deep green
+    Srgb::new(0, 0xdd, 0xaa), // teal
+    Srgb::new(0x88, 0, 0xff), // violet
+    Srgb::new(0xcc, 0, 0xaa), // magenta
+    Srgb::new(0xaa, 0xff, 0xff), // pale cyan
+    Srgb::new(0, 0x11, 0x33), // dark navy
+    Srgb::new(0xff, 0xff, 0xcc), // warm white
+    Srgb::new(0, 0x44, 0xff), // electric blue
+];
+
+// What the aurora animation had before there were palettes: green, cyan and violet
+pub const PALETTE_BANDS: [Srgb; 3] = [
+    Srgb::new(0, 0xff, 0),
+    Srgb::new(0, 0xff, 0xff),
+    Srgb::new(0xaa, 0, 0xff),
+];
+
+// For `set_palette <name>`, the index is what AnimationMode::Aurora keeps
+pub const PALETTES: [(&str, &[Srgb]); 2] = [("aurora", &PALETTE_AURORA), ("bands", &PALETTE_BANDS)];
+
+pub fn find_palette(name: &str) -> Option<u8> {
+    PALETTES.iter().position(|&(palette, _)| palette == name).and_then(|index| u8::try_from(index).ok())
+}
+
+// The palette for an index, the first one for anything out of range
+pub fn palette(index: u8) -> &'static [Srgb] {
+    PALETTES.get(usize::from(index)).unwrap_or(&PALETTES[0]).1
+}
+
+// `t` 0.0..=1.0 from the first entry to the last, straight lines between neighbours
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+pub fn palette_lerp(palette: &[Srgb], t: f32) -> Srgb {
+    let Some(last) = palette.len().checked_sub(1) else {
+        return Srgb::new(0, 0, 0);
+    };
+    let scaled = t.clamp(0.0, 1.0) * last as f32;
+    let index = (scaled as usize).min(last);
+    let (from, to) = (palette[index], palette[(index + 1).min(last)]);
+    let frac = scaled - index as f32;
+    let mix = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * frac + 0.5) as u8;
+    Srgb::new(mix(from.red, to.red), mix(from.green, to.green), mix(from.blue, to.blue))
+}
@@ -0,0 +1,125 @@
+use super::{eye_rgb_duties, frame_ms, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::led_config::LedConfig;
+use crate::rng::XorShift32;
+
+const MAX_PARTICLES: usize = 8;
+
+// A new spark on average every this many frames, if there's room for one
+const SPAWN_ODDS: u32 = 12;
+
+// Positions per second, so a spark takes one to two seconds to reach the heart
+const MIN_VELOCITY: f32 = 0.5;
+const MAX_VELOCITY: f32 = 1.0;
+
+// Frames, a spark that's out of time before the heart just fades away on the way
+const MIN_LIFETIME: u32 = 120;
+const MAX_LIFETIME: u32 = 250;
+
+// Embers: orange to yellow
+const MIN_HUE: f32 = 20.0;
+const MAX_HUE: f32 = 50.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LedGroup {
+    LeftEye,
+    RightEye,
+    Heart,
+}
+
+#[derive(Clone, Copy)]
+pub struct Particle {
+    pub source: LedGroup,
+    // 0.0 at the source eye, 1.0 at the heart
+    pub position: f32,
+    // positions per second
+    pub velocity: f32,
+    pub color: Hsv,
+    // frames left, 0 is a free slot
+    pub lifetime: u8,
+}
+
+impl Particle {
+    const DEAD: Self = Self {
+        source: LedGroup::LeftEye,
+        position: 0.0,
+        velocity: 0.0,
+        color: Hsv::WHITE,
+        lifetime: 0,
+    };
+}
+
+// Glowing sparks drifting from the eyes down to the heart. Each one lights its eye
+// and the heart in proportion to where it is between them, and dims as it runs out.
+pub struct ParticleAnimation {
+    particles: [Particle; MAX_PARTICLES],
+}
+
+impl ParticleAnimation {
+    pub const fn new() -> Self {
+        Self {
+            particles: [Particle::DEAD; MAX_PARTICLES],
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn spawn(&mut self, rng: &mut XorShift32) {
+        let Some(free) = self.particles.iter_mut().find(|particle| particle.lifetime == 0) else {
+            return;
+        };
+        *free = Particle {
+            source: if rng.next_u32() & 1 == 0 { LedGroup::LeftEye } else { LedGroup::RightEye },
+            position: 0.0,
+            velocity: MIN_VELOCITY + rng.next_f32() * (MAX_VELOCITY - MIN_VELOCITY),
+            color: Hsv::from_f32(MIN_HUE + rng.next_f32() * (MAX_HUE - MIN_HUE), 1.0, 1.0),
+            lifetime: rng.next_range(MIN_LIFETIME, MAX_LIFETIME + 1) as u8,
+        };
+    }
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn tick(&mut self, delta_ms: u32, rng: &mut XorShift32, channels: &mut PwmChannels, led_config: LedConfig) {
+        if rng.next_u32().is_multiple_of(SPAWN_ODDS) {
+            self.spawn(rng);
+        }
+
+        // linear light summed per group: left eye, right eye, heart
+        let mut light = [[0.0f32; 3]; 3];
+        let dt = delta_ms as f32 / 1000.0;
+        for particle in self.particles.iter_mut().filter(|particle| particle.lifetime > 0) {
+            particle.position += particle.velocity * dt;
+            particle.lifetime -= 1;
+            if particle.position >= 1.0 {
+                // reached the heart, it's had its moment
+                particle.lifetime = 0;
+                continue;
+            }
+
+            let fade = f32::from(particle.lifetime) / MAX_LIFETIME as f32;
+            let (r, g, b) = particle.color.to_rgb_u16();
+            let rgb = [f32::from(r), f32::from(g), f32::from(b)];
+            // lerp between the source eye and the heart
+            let weights = [(particle.source as usize, 1.0 - particle.position), (LedGroup::Heart as usize, particle.position)];
+            for (group, weight) in weights {
+                for (sum, channel) in light[group].iter_mut().zip(rgb) {
+                    *sum += channel * weight * fade;
+                }
+            }
+        }
+
+        let duties = |[r, g, b]: [f32; 3]| {
+            let duty = |x: f32| x.min(65535.0) as u16;
+            gamma3(eye_rgb_duties((duty(r), duty(g), duty(b)), led_config))
+        };
+        let (r, g, b) = duties(light[LedGroup::LeftEye as usize]);
+        channels.set_left_eye(r, g, b);
+        let (r, g, b) = duties(light[LedGroup::RightEye as usize]);
+        channels.set_right_eye(r, g, b);
+        let (r, g, b) = duties(light[LedGroup::Heart as usize]);
+        channels.set_heart(r, g, b);
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, cold: bool) {
+    state.particles.tick(frame_ms(cold), &mut state.rng, channels, state.led_config);
+}
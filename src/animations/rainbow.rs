@@ -0,0 +1,109 @@
+use super::eye::scale_duty;
+use super::heart::{self, HeartMode};
+use super::{eye_duties, frame_ms, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::filter::TempTrend;
+use crate::gamma::gamma_correct;
+use crate::noise;
+
+// Eye hue goes around 40 times per 65500 ticks
+#[allow(clippy::cast_precision_loss)]
+fn base_eye_hue(tick: u32) -> f32 {
+    (tick % 65_500) as f32 / 65_500.0 * 360.0 * 40.0
+}
+
+// How far the hue wanders off the plain cycle, either way
+const HUE_WANDER_DEGREES: f32 = 30.0;
+
+// and how fast, one noise cell per 100 ticks
+const HUE_WANDER_RATE: f32 = 0.01;
+
+// The cycle with some noise on top, so it doesn't look like clockwork
+#[allow(clippy::cast_precision_loss)]
+fn eye_hue(tick: u32) -> f32 {
+    base_eye_hue(tick) + noise::perlin1d(tick as f32 * HUE_WANDER_RATE) * HUE_WANDER_DEGREES
+}
+
+// Hot alpacca is frantic, the eyes cycle five times faster and the heart races
+const HOT_HUE_SPEED: u32 = 5;
+const BEAT_TICKS: u32 = 100;
+const HOT_BEAT_TICKS: u32 = 30;
+
+// Orange-red when it's getting warmer fast, blue when it's getting colder
+const RISING_HUE: f32 = 20.0;
+const FALLING_HUE: f32 = 220.0;
+
+// Share of the way from the cycle to that hue
+const TREND_HUE_PULL: f32 = 0.6;
+
+// Shorter way round the hue circle
+fn pull_hue(hue: f32, target: f32, amount: f32) -> f32 {
+    let diff = ((target - hue) % 360.0 + 540.0) % 360.0 - 180.0;
+    hue + diff * amount
+}
+
+fn trend_hue(hue: f32, trend: TempTrend) -> f32 {
+    match trend {
+        TempTrend::Rising => pull_hue(hue, RISING_HUE, TREND_HUE_PULL),
+        TempTrend::Falling => pull_hue(hue, FALLING_HUE, TREND_HUE_PULL),
+        TempTrend::Stable => hue,
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, tick: u32, cold: bool) {
+    let hue = trend_hue(eye_hue(if state.hot { tick * HOT_HUE_SPEED } else { tick }), state.trend);
+    let (eye_r, eye_g, eye_b) = eye_duties(Hsv::from_f32(hue, 1.0, 1.0), state.led_config);
+    let (r, g, b) = gamma3((eye_r, eye_g, eye_b));
+    channels.set_left_eye(r, g, b);
+
+    // close right eye if cold, looks funny and saves power (if CR2032 used)
+    state.right_eye.set_closed(cold);
+    let lid = state.right_eye.tick(frame_ms(cold));
+    let (r, g, b) = gamma3((scale_duty(eye_r, lid), scale_duty(eye_g, lid), scale_duty(eye_b, lid)));
+    channels.set_right_eye(r, g, b);
+
+    // Change of <3, the spring makes the second beat by itself
+    if tick.is_multiple_of(if state.hot { HOT_BEAT_TICKS } else { BEAT_TICKS }) {
+        state.heart_spring.beat();
+        state.cardiac.beat();
+        state.beat_ms = 0;
+    }
+
+    // Give either BLUE or RED <3
+    match state.heart_mode {
+        HeartMode::Pulse => {
+            let (main, glow) = state.heart_spring.tick(frame_ms(cold), state.led_config.max_heart_duty, cold);
+            if cold {
+                // Blue <3
+                channels.set_heart(gamma_correct(glow), 0, gamma_correct(main));
+            } else {
+                // Red <3
+                channels.set_heart(gamma_correct(main), 0, gamma_correct(glow));
+            }
+        }
+        HeartMode::Waveform => {
+            let (main, glow) = heart::waveform_duties(state.beat_ms, state.led_config.max_heart_duty);
+            state.beat_ms = state.beat_ms.saturating_add(frame_ms(cold));
+            if cold {
+                channels.set_heart(gamma_correct(glow), 0, gamma_correct(main));
+            } else {
+                channels.set_heart(gamma_correct(main), 0, gamma_correct(glow));
+            }
+        }
+        HeartMode::Rhythm => {
+            let (main, glow) = state.cardiac.tick(state.led_config.max_heart_duty);
+            if cold {
+                channels.set_heart(gamma_correct(glow), 0, gamma_correct(main));
+            } else {
+                channels.set_heart(gamma_correct(main), 0, gamma_correct(glow));
+            }
+        }
+        HeartMode::Breath => {
+            state.heart_breath.set_cold(cold);
+            let delta = state.heart_breath.phase_delta(frame_ms(cold));
+            let (red, blue) = state.heart_breath.tick(delta);
+            channels.set_heart(gamma_correct(red), 0, gamma_correct(blue));
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use super::easing;
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::gamma::gamma_correct;
+
+// Eyes dim out first, the heart goes a bit later, then it all stays dark
+pub const SHUTDOWN_MS: u32 = 900;
+const EYES_MS: u32 = 600;
+
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn render(state: &AnimationState, channels: &mut PwmChannels, elapsed_ms: u32) {
+    let fade = |duration_ms: u32| 1.0 - easing::ease_in_out_sine((elapsed_ms.min(duration_ms)) as f32 / duration_ms as f32);
+
+    let (r, g, b) = gamma3(eye_duties(Hsv::new(0, 0, (fade(EYES_MS) * 255.0) as u8), state.led_config));
+    channels.set_left_eye(r, g, b);
+    channels.set_right_eye(r, g, b);
+
+    let heart = (f32::from(state.led_config.max_heart_duty) * fade(SHUTDOWN_MS)) as u16;
+    channels.set_heart(gamma_correct(heart), 0, 0);
+}
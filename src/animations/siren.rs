@@ -0,0 +1,76 @@
+use super::heart::spring_tick;
+use super::{eye_rgb_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Srgb;
+use crate::timer;
+
+// Same worry as strobe.rs, this kind of flashing can set off seizures. 4 Hz is what
+// sirens do, 8 is the cap, and siren is only ever picked by name.
+pub const MIN_RATE_HZ: u8 = 1;
+pub const MAX_RATE_HZ: u8 = 8;
+pub const DEFAULT_RATE_HZ: u8 = 4;
+
+// Heart swings between the two colors a bit past each one, it settles well within a
+// flash at 8 Hz
+const SPRING_STIFFNESS: f32 = 4000.0;
+const SPRING_DAMPING: f32 = 60.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SirenVariant {
+    // red and blue
+    Police,
+    // blue and white, like up north
+    Nordic,
+}
+
+impl SirenVariant {
+    const fn colors(self) -> (Srgb, Srgb) {
+        match self {
+            Self::Police => (Srgb::new(0xff, 0, 0), Srgb::new(0, 0, 0xff)),
+            Self::Nordic => (Srgb::new(0, 0, 0xff), Srgb::new(0xff, 0xff, 0xff)),
+        }
+    }
+}
+
+// Where the heart is between the first color (0.0) and the second (1.0)
+pub struct SirenHeart {
+    pos: f32,
+    vel: f32,
+}
+
+impl SirenHeart {
+    pub const fn new() -> Self {
+        Self { pos: 0.0, vel: 0.0 }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn mix(first: Srgb, second: Srgb, pos: f32) -> (u16, u16, u16) {
+    // how far from each color, either way, so the overshoot flashes the other one a little
+    let first_level = libm::fabsf(1.0 - pos).min(1.0);
+    let second_level = libm::fabsf(pos).min(1.0);
+    let (a, b) = (first.to_rgb_u16(), second.to_rgb_u16());
+    let channel = |a: u16, b: u16| (f32::from(a) * first_level + f32::from(b) * second_level).min(65535.0) as u16;
+    (channel(a.0, b.0), channel(a.1, b.1), channel(a.2, b.2))
+}
+
+// Left eye flashes the first color, then the right eye the second, the heart follows.
+// Flashes come by uptime like strobe, so cold and warm flash at the same rate.
+#[allow(clippy::cast_precision_loss)]
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, rate_hz: u8, variant: SirenVariant, delta_ms: u32) {
+    let period_ms = 1000 / u32::from(rate_hz.clamp(MIN_RATE_HZ, MAX_RATE_HZ));
+    let second_half = timer::uptime_ms() % period_ms >= period_ms / 2;
+    let (first, second) = variant.colors();
+    let eye = |color: Srgb| gamma3(eye_rgb_duties(color.to_rgb_u16(), state.led_config));
+
+    let (left, right) = if second_half { ((0, 0, 0), eye(second)) } else { (eye(first), (0, 0, 0)) };
+    channels.set_left_eye(left.0, left.1, left.2);
+    channels.set_right_eye(right.0, right.1, right.2);
+
+    let heart = &mut state.siren;
+    let target = if second_half { 1.0 } else { 0.0 };
+    let dt = delta_ms as f32 / 1000.0;
+    (heart.pos, heart.vel) = spring_tick(heart.pos, heart.vel, target, SPRING_STIFFNESS, SPRING_DAMPING, dt);
+    let (r, g, b) = gamma3(eye_rgb_duties(mix(first, second, heart.pos), state.led_config));
+    channels.set_heart(r, g, b);
+}
@@ -0,0 +1,62 @@
+use core::f32::consts::FRAC_PI_2;
+
+use super::transition::LED_COUNT;
+use crate::bsp::prelude::PwmChannels;
+
+// The last this long before going dormant everything fades out
+pub const SLEEP_FADE_MS: u32 = 10_000;
+
+// Waking is quicker, somebody just pressed the button and wants to see something
+pub const WAKE_FADE_MS: u32 = 2000;
+
+// Share of the fade the eyes take from the start, the heart starts later and goes last
+const EYES_END: f32 = 0.7;
+const HEART_START: f32 = 0.3;
+
+// Dims whatever is being drawn, on top of the animation, so it fades out from where
+// it is. progress comes from the main loop: 0.0 untouched, 1.0 all dark. Run back
+// from 1.0 to 0.0 it's the fade in again, heart first and then the eyes.
+pub struct SleepFadeAnimation {
+    pub progress: f32,
+}
+
+impl SleepFadeAnimation {
+    pub const fn new() -> Self {
+        Self { progress: 0.0 }
+    }
+
+    // Quarter cosine of the way through `start..end`, 1.0 before and 0.0 after
+    fn fade(&self, start: f32, end: f32) -> f32 {
+        let t = ((self.progress - start) / (end - start)).clamp(0.0, 1.0);
+        libm::cosf(t * FRAC_PI_2)
+    }
+
+    // Left eye, right eye and heart, as fractions of 0xffff
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn scales(&self) -> [u16; LED_COUNT] {
+        let eyes = (self.fade(0.0, EYES_END) * 65535.0) as u16;
+        let heart = (self.fade(HEART_START, 1.0) * 65535.0) as u16;
+        [eyes, eyes, heart]
+    }
+
+    pub fn apply(&self, channels: &mut PwmChannels) {
+        channels.fade = self.scales();
+    }
+}
+
+// Fade out over the last SLEEP_FADE_MS of `sleep_after_ms` idle, or over all of it if
+// that's shorter
+#[allow(clippy::cast_precision_loss)]
+pub fn fade_out_progress(idle_ms: u32, sleep_after_ms: u32) -> f32 {
+    let fade_ms = SLEEP_FADE_MS.min(sleep_after_ms);
+    let fading_ms = idle_ms.saturating_sub(sleep_after_ms - fade_ms);
+    if fade_ms == 0 {
+        return 1.0;
+    }
+    (fading_ms as f32 / fade_ms as f32).min(1.0)
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn fade_in_progress(awake_ms: u32) -> f32 {
+    1.0 - (awake_ms as f32 / WAKE_FADE_MS as f32).min(1.0)
+}
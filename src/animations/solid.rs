@@ -0,0 +1,14 @@
+use super::{eye_rgb_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Srgb;
+
+// The same every frame, only brightness and the eye calibration go on top
+pub fn render(state: &AnimationState, channels: &mut PwmChannels, left_eye: Srgb, right_eye: Srgb, heart: Srgb) {
+    let duties = |color: Srgb| gamma3(eye_rgb_duties(color.to_rgb_u16(), state.led_config));
+    let (r, g, b) = duties(left_eye);
+    channels.set_left_eye(r, g, b);
+    let (r, g, b) = duties(right_eye);
+    channels.set_right_eye(r, g, b);
+    let (r, g, b) = duties(heart);
+    channels.set_heart(r, g, b);
+}
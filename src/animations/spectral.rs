@@ -0,0 +1,45 @@
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::led_config::LedConfig;
+
+// At speed 1.0 a group goes round the hue wheel once in this long
+const PERIOD_MS: u32 = 10_000;
+
+// Powers of the golden ratio, the most irrational of ratios, so the three never
+// line up again the way they started
+pub const DEFAULT_SPEEDS: [f32; 3] = [1.0, 1.618, 2.618];
+
+// Flash could hold anything, faster than this is a blur anyway
+pub const MAX_SPEED: f32 = 10.0;
+
+// Left eye, right eye and heart each with a phase of their own, 0.0..1.0 round the wheel
+pub struct SpectralAnimation {
+    phases: [f32; 3],
+}
+
+impl SpectralAnimation {
+    pub const fn new() -> Self {
+        Self { phases: [0.0; 3] }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn tick(&mut self, delta_ms: u32, speeds: [f32; 3], channels: &mut PwmChannels, led_config: LedConfig) {
+        let turns = delta_ms as f32 / PERIOD_MS as f32;
+        for (phase, speed) in self.phases.iter_mut().zip(speeds) {
+            *phase = (*phase + turns * speed.clamp(0.0, MAX_SPEED)) % 1.0;
+        }
+
+        let duties = |phase: f32| gamma3(eye_duties(Hsv::from_f32(phase * 360.0, 1.0, 1.0), led_config));
+        let (r, g, b) = duties(self.phases[0]);
+        channels.set_left_eye(r, g, b);
+        let (r, g, b) = duties(self.phases[1]);
+        channels.set_right_eye(r, g, b);
+        let (r, g, b) = duties(self.phases[2]);
+        channels.set_heart(r, g, b);
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, speeds: [f32; 3], delta_ms: u32) {
+    state.spectral.tick(delta_ms, speeds, channels, state.led_config);
+}
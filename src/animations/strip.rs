@@ -0,0 +1,17 @@
+use crate::color::{self, HUE_CIRCLE};
+use crate::pio::ws2812;
+
+// Dim, the strip is decoration and shouldn't outshine the alpacca, about 20 %
+const STRIP_VALUE: u8 = 51;
+
+// Rainbow spread over the strip, going round once every 36 s of ticks
+#[allow(clippy::cast_possible_truncation)]
+pub fn render(tick: u32, pixels: &mut [u8]) {
+    let count = pixels.len() / ws2812::BYTES_PER_PIXEL;
+    let base = tick % 3600 * 10;
+    for i in 0..count {
+        let hue = base + (i * HUE_CIRCLE as usize / count) as u32;
+        let (r, g, b) = color::hsv_to_rgb_u16(hue, u8::MAX, STRIP_VALUE);
+        ws2812::set_pixel(pixels, i, ((r >> 8) as u8, (g >> 8) as u8, (b >> 8) as u8));
+    }
+}
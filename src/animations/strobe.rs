@@ -0,0 +1,27 @@
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::timer;
+
+// Flashing light between about 3 and 30 Hz can set off seizures in people with
+// photosensitive epilepsy, worst around 15 to 20 Hz. Nothing can make that safe, so
+// the default stays under 3 Hz and strobe is never picked by the button or a shake,
+// only asked for by name. The cap is there so nobody gets more than that by accident,
+// 20 Hz also still gives on and off at least two frames each.
+pub const MIN_RATE_HZ: u8 = 1;
+pub const MAX_RATE_HZ: u8 = 20;
+
+// Flashes come by uptime, not by tick, so cold and warm flash at the same rate
+pub fn render(state: &AnimationState, channels: &mut PwmChannels, rate_hz: u8, duty_percent: u8, color: Hsv) {
+    let period_ms = 1000 / u32::from(rate_hz.clamp(MIN_RATE_HZ, MAX_RATE_HZ));
+    let on_ms = period_ms * u32::from(duty_percent.min(100)) / 100;
+
+    if timer::uptime_ms() % period_ms < on_ms {
+        let (r, g, b) = gamma3(eye_duties(color, state.led_config));
+        channels.set_left_eye(r, g, b);
+        channels.set_right_eye(r, g, b);
+        channels.set_heart(r, g, b);
+    } else {
+        channels.set_all_off();
+    }
+}
@@ -0,0 +1,82 @@
+use core::f32::consts::TAU;
+use core::sync::atomic::{AtomicI16, Ordering};
+
+use super::{eye_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color::Hsv;
+use crate::led_config::LedConfig;
+
+// Finnish outdoors, from a proper winter day to a hot summer one
+pub const DEFAULT_MIN_CELSIUS: i8 = -10;
+pub const DEFAULT_MAX_CELSIUS: i8 = 30;
+
+// Blue at the cold end, through cyan, green and yellow down to red
+const COLD_HUE: f32 = 240.0;
+
+// Slow, a resting breath rather than a heartbeat
+const PULSE_MS: u32 = 4000;
+const MIN_PULSE: f32 = 0.2;
+
+// Share of the way to the new hue each frame, a new reading only comes once a second
+const HUE_FOLLOW: f32 = 0.02;
+
+// Filtered temperature in tenths of a degree, core 0 writes it with every reading
+static CELSIUS_TENTHS: AtomicI16 = AtomicI16::new(200);
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn note_celsius(celsius: f32) {
+    CELSIUS_TENTHS.store((celsius * 10.0) as i16, Ordering::Relaxed);
+}
+
+pub fn celsius() -> f32 {
+    f32::from(CELSIUS_TENTHS.load(Ordering::Relaxed)) / 10.0
+}
+
+// 240° at min_celsius to 0° at max_celsius, clamped outside
+pub fn hue_at(celsius: f32, min_celsius: i8, max_celsius: i8) -> f32 {
+    let span = f32::from(max_celsius) - f32::from(min_celsius);
+    if span <= 0.0 {
+        return COLD_HUE;
+    }
+    let t = ((celsius - f32::from(min_celsius)) / span).clamp(0.0, 1.0);
+    COLD_HUE * (1.0 - t)
+}
+
+// Everything in the hue of the temperature, the heart pulsing slowly in it
+pub struct ThermometerAnimation {
+    // None until the first frame, so it starts at the right color instead of sliding there
+    hue: Option<f32>,
+    time_ms: u32,
+}
+
+impl ThermometerAnimation {
+    pub const fn new() -> Self {
+        Self { hue: None, time_ms: 0 }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn tick(
+        &mut self,
+        delta_ms: u32,
+        (min_celsius, max_celsius): (i8, i8),
+        channels: &mut PwmChannels,
+        led_config: LedConfig,
+    ) {
+        let target = hue_at(celsius(), min_celsius, max_celsius);
+        let hue = self.hue.map_or(target, |hue| hue + (target - hue) * HUE_FOLLOW);
+        self.hue = Some(hue);
+        self.time_ms = (self.time_ms + delta_ms) % PULSE_MS;
+
+        let (r, g, b) = gamma3(eye_duties(Hsv::from_f32(hue, 1.0, 1.0), led_config));
+        channels.set_left_eye(r, g, b);
+        channels.set_right_eye(r, g, b);
+        let phase = self.time_ms as f32 / PULSE_MS as f32;
+        let pulse = MIN_PULSE + (1.0 - MIN_PULSE) * (1.0 - libm::cosf(phase * TAU)) / 2.0;
+        let (r, g, b) = gamma3(eye_duties(Hsv::from_f32(hue, 1.0, pulse), led_config));
+        channels.set_heart(r, g, b);
+    }
+}
+
+pub fn render(state: &mut AnimationState, channels: &mut PwmChannels, min_celsius: i8, max_celsius: i8, delta_ms: u32) {
+    state.thermometer.tick(delta_ms, (min_celsius, max_celsius), channels, state.led_config);
+}
@@ -0,0 +1,43 @@
+// Mode switches blend from the last frame of the old mode to the new one over this long
+pub const CROSSFADE_MS: u32 = 500;
+
+pub type Rgb = (u16, u16, u16);
+
+// Left eye, right eye, heart
+pub const LED_COUNT: usize = 3;
+
+// The old mode isn't run anymore, its last frame just fades out under the new one
+pub struct CrossfadeTransition {
+    pub previous: [Rgb; LED_COUNT],
+    pub next: [Rgb; LED_COUNT],
+    // 0.0 all previous, 1.0 all next
+    pub progress: f32,
+}
+
+impl CrossfadeTransition {
+    pub const fn new(previous: [Rgb; LED_COUNT]) -> Self {
+        Self {
+            previous,
+            next: previous,
+            progress: 0.0,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn advance(&mut self, delta_ms: u32) {
+        self.progress = (self.progress + delta_ms as f32 / CROSSFADE_MS as f32).min(1.0);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress >= 1.0
+    }
+
+    // What `led` shows now that the new mode wants `next` there
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn blend(&mut self, led: usize, next: Rgb) -> Rgb {
+        self.next[led] = next;
+        let (from, to) = (self.previous[led], next);
+        let mix = |a: u16, b: u16| (f32::from(a) + (f32::from(b) - f32::from(a)) * self.progress) as u16;
+        (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+    }
+}
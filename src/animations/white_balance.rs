@@ -0,0 +1,11 @@
+use super::{eye_rgb_duties, gamma3, AnimationState};
+use crate::bsp::prelude::PwmChannels;
+use crate::color;
+
+// Everything at the same white, eye calibration included so the mix comes out right
+pub fn render(state: &AnimationState, channels: &mut PwmChannels, kelvin: u16) {
+    let (r, g, b) = gamma3(eye_rgb_duties(color::kelvin_to_rgb(kelvin), state.led_config));
+    channels.set_left_eye(r, g, b);
+    channels.set_right_eye(r, g, b);
+    channels.set_heart(r, g, b);
+}
@@ -0,0 +1,195 @@
+use rp2040_hal::pac;
+
+use super::rtttl::Rtttl;
+use crate::bsp;
+
+// GPIO21 is slice 2 channel B. Slice 2's other pins are I2C, so nothing else on it cares
+// what frequency it runs at, unlike the LED slices.
+const SLICE: usize = bsp::gpio_to_pwm_slice(<bsp::Buzzer as bsp::GpioNum>::GPIO).0 as usize;
+const _: () = assert!(bsp::gpio_to_pwm_slice(<bsp::Buzzer as bsp::GpioNum>::GPIO).1 == 1, "the buzzer is driven as channel B");
+
+// 125 MHz / 64, TOP then goes from 987 for B6 to 14908 for C3. With clk_sys slowed
+// down it's 140 to 2128, still close enough to the note.
+const CLOCK_DIVIDER: u8 = 64;
+
+fn counter_hz() -> u32 {
+    crate::power::sys_clock_hz() / u32::from(CLOCK_DIVIDER)
+}
+
+// C3 to B6, sharps only, and a pause
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum Note {
+    C3, Cs3, D3, Ds3, E3, F3, Fs3, G3, Gs3, A3, As3, B3,
+    C4, Cs4, D4, Ds4, E4, F4, Fs4, G4, Gs4, A4, As4, B4,
+    C5, Cs5, D5, Ds5, E5, F5, Fs5, G5, Gs5, A5, As5, B5,
+    C6, Cs6, D6, Ds6, E6, F6, Fs6, G6, Gs6, A6, As6, B6,
+    Rest,
+}
+
+// Same order as above, for looking them up by number
+#[allow(clippy::enum_glob_use)]
+const NOTES: [Note; 48] = {
+    use Note::*;
+    [
+        C3, Cs3, D3, Ds3, E3, F3, Fs3, G3, Gs3, A3, As3, B3,
+        C4, Cs4, D4, Ds4, E4, F4, Fs4, G4, Gs4, A4, As4, B4,
+        C5, Cs5, D5, Ds5, E5, F5, Fs5, G5, Gs5, A5, As5, B5,
+        C6, Cs6, D6, Ds6, E6, F6, Fs6, G6, Gs6, A6, As6, B6,
+    ]
+};
+
+const LOWEST_OCTAVE: u8 = 3;
+const HIGHEST_OCTAVE: u8 = 6;
+
+// Equal temperament from A4 = 440 Hz, rounded, in the order of Note
+const NOTE_FREQUENCIES_HZ: [u16; 48] = [
+    131, 139, 147, 156, 165, 175, 185, 196, 208, 220, 233, 247,
+    262, 277, 294, 311, 330, 349, 370, 392, 415, 440, 466, 494,
+    523, 554, 587, 622, 659, 698, 740, 784, 831, 880, 932, 988,
+    1047, 1109, 1175, 1245, 1319, 1397, 1480, 1568, 1661, 1760, 1865, 1976,
+];
+
+impl Note {
+    // 0 for a rest
+    pub const fn frequency_hz(self) -> u16 {
+        match self {
+            Self::Rest => 0,
+            note => NOTE_FREQUENCIES_HZ[note as usize],
+        }
+    }
+
+    // `semitone` up from C in `octave`, further than B rolls over into the next octave.
+    // Octaves we can't play move a whole octave at a time until they fit.
+    pub const fn from_octave(octave: u8, semitone: u8) -> Self {
+        let mut octave = octave + semitone / 12;
+        let semitone = semitone % 12;
+        while octave < LOWEST_OCTAVE {
+            octave += 1;
+        }
+        while octave > HIGHEST_OCTAVE {
+            octave -= 1;
+        }
+        NOTES[(octave - LOWEST_OCTAVE) as usize * 12 + semitone as usize]
+    }
+}
+
+// Notes one after the other, (note, duration_ms)
+pub type Melody = &'static [(Note, u32)];
+
+// What the buzzer is going through, a fixed melody or an RTTTL string
+enum Notes {
+    Melody(Melody),
+    Rtttl(Rtttl<'static>),
+}
+
+impl Iterator for Notes {
+    type Item = (Note, u32);
+
+    fn next(&mut self) -> Option<(Note, u32)> {
+        match self {
+            Self::Melody(melody) => {
+                let (&first, rest) = melody.split_first()?;
+                *melody = rest;
+                Some(first)
+            }
+            Self::Rtttl(rtttl) => rtttl.next(),
+        }
+    }
+}
+
+// C major arpeggio going down
+pub const LOW_BATTERY_ALERT: Melody = &[(Note::G5, 150), (Note::E5, 150), (Note::C5, 300)];
+// minor third going up
+pub const COLD_ENTRY: Melody = &[(Note::A4, 200), (Note::C5, 300)];
+// up the C major chord and a little hop, as long as the nose boop
+pub const HAPPY_BOOP: Melody = &[
+    (Note::C5, 120),
+    (Note::E5, 120),
+    (Note::G5, 120),
+    (Note::C6, 240),
+    (Note::Rest, 80),
+    (Note::G5, 120),
+    (Note::C6, 200),
+];
+
+const fn pwm_slice() -> &'static pac::pwm::CH {
+    // SAFETY: slice 2 is only ever touched from here, hal's Slices doesn't use it
+    unsafe { &(*pac::PWM::ptr()).ch[SLICE] }
+}
+
+// Piezo on GPIO21 driven straight from PWM at 50% duty. tick() once per frame moves
+// through a melody and stops the note when it's over.
+pub struct Buzzer {
+    _pin: bsp::Buzzer,
+    notes: Notes,
+    remaining_ms: u32,
+}
+
+impl Buzzer {
+    // PWM has to be out of reset already, see hal::pwm::Slices
+    pub fn new(pin: bsp::Buzzer) -> Self {
+        let slice = pwm_slice();
+        slice.div.write(|w| unsafe { w.int().bits(CLOCK_DIVIDER).frac().bits(0) });
+        slice.cc.write(|w| unsafe { w.b().bits(0) });
+        slice.csr.write(|w| w.en().set_bit());
+        Self {
+            _pin: pin,
+            notes: Notes::Melody(&[]),
+            remaining_ms: 0,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn play_note(&mut self, note: Note, duration_ms: u32) {
+        let slice = pwm_slice();
+        if note == Note::Rest {
+            slice.cc.write(|w| unsafe { w.b().bits(0) });
+        } else {
+            let top = (counter_hz() / u32::from(note.frequency_hz()) - 1) as u16;
+            slice.top.write(|w| unsafe { w.top().bits(top) });
+            slice.cc.write(|w| unsafe { w.b().bits(top / 2) });
+        }
+        self.remaining_ms = duration_ms;
+    }
+
+    // Nothing playing, not even a rest. clk_sys only changes then, a note that's already
+    // sounding would go off key.
+    pub const fn is_quiet(&self) -> bool {
+        self.remaining_ms == 0
+    }
+
+    // Replaces whatever was playing
+    pub fn play_melody(&mut self, melody: Melody) {
+        self.notes = Notes::Melody(melody);
+        self.next_note();
+    }
+
+    pub fn play_rtttl(&mut self, rtttl: Rtttl<'static>) {
+        self.notes = Notes::Rtttl(rtttl);
+        self.next_note();
+    }
+
+    pub fn stop(&mut self) {
+        self.notes = Notes::Melody(&[]);
+        self.remaining_ms = 0;
+        pwm_slice().cc.write(|w| unsafe { w.b().bits(0) });
+    }
+
+    pub fn tick(&mut self, delta_ms: u32) {
+        if self.remaining_ms == 0 {
+            return;
+        }
+        self.remaining_ms = self.remaining_ms.saturating_sub(delta_ms);
+        if self.remaining_ms == 0 {
+            self.next_note();
+        }
+    }
+
+    fn next_note(&mut self) {
+        match self.notes.next() {
+            Some((note, duration_ms)) => self.play_note(note, duration_ms),
+            None => self.stop(),
+        }
+    }
+}
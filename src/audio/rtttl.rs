@@ -0,0 +1,120 @@
+use core::str::Split;
+
+use super::buzzer::Note;
+
+// Built in tunes for `play <name>`, all public domain
+pub const MELODIES: [(&str, &str); 3] = [
+    // Beethoven, Symphony No. 9
+    ("ode", "Ode to Joy:d=4,o=5,b=140:e,e,f,g,g,f,e,d,c,c,d,e,e.,8d,2d,e,e,f,g,g,f,e,d,c,c,d,e,d.,8c,2c"),
+    ("twinkle", "Twinkle:d=4,o=5,b=120:c,c,g,g,a,a,2g,f,f,e,e,d,d,2c"),
+    // our own, short
+    ("alpakka", "Alpakka:d=8,o=5,b=160:c,e,g,c6,p,g,4c6"),
+];
+
+// Header defaults when a value is missing or broken, as in the Nokia spec
+const DEFAULT_DURATION: u32 = 4;
+const DEFAULT_OCTAVE: u8 = 6;
+const DEFAULT_BPM: u32 = 63;
+
+pub fn find_melody(name: &str) -> Option<&'static str> {
+    MELODIES.iter().find(|&&(melody, _)| melody == name).map(|&(_, rtttl)| rtttl)
+}
+
+// Notes of an RTTTL string as (note, duration_ms), anything it can't read is skipped
+pub struct Rtttl<'a> {
+    notes: Split<'a, char>,
+    duration: u32,
+    octave: u8,
+    bpm: u32,
+}
+
+// "name:d=4,o=5,b=100:8c6,8d,p,16e.,..." Beats are quarter notes.
+#[allow(clippy::cast_possible_truncation)]
+pub fn parse_rtttl(s: &str) -> Rtttl<'_> {
+    let mut sections = s.splitn(3, ':');
+    let _name = sections.next();
+    let header = sections.next().unwrap_or("");
+    let notes = sections.next().unwrap_or("");
+
+    let mut rtttl = Rtttl {
+        notes: notes.split(','),
+        duration: DEFAULT_DURATION,
+        octave: DEFAULT_OCTAVE,
+        bpm: DEFAULT_BPM,
+    };
+    for setting in header.split(',') {
+        let Some((key, value)) = setting.trim().split_once('=') else {
+            continue;
+        };
+        match (key, value.parse::<u32>()) {
+            ("d", Ok(duration)) if duration > 0 => rtttl.duration = duration,
+            ("o", Ok(octave)) if octave <= 9 => rtttl.octave = octave as u8,
+            ("b", Ok(bpm)) if bpm > 0 => rtttl.bpm = bpm,
+            _ => {}
+        }
+    }
+    rtttl
+}
+
+// Leading digits and what's left
+fn take_number(s: &str) -> (Option<u32>, &str) {
+    let end = s.bytes().position(|b| !b.is_ascii_digit()).unwrap_or(s.len());
+    (s[..end].parse().ok(), &s[end..])
+}
+
+impl Rtttl<'_> {
+    // One "8c#6." style entry
+    #[allow(clippy::cast_possible_truncation)]
+    fn parse_note(&self, entry: &str) -> Option<(Note, u32)> {
+        let (duration, rest) = take_number(entry.trim());
+        let duration = duration.filter(|&d| d > 0).unwrap_or(self.duration);
+
+        let mut chars = rest.chars();
+        let semitone = match chars.next()?.to_ascii_lowercase() {
+            'c' => 0,
+            'd' => 2,
+            'e' => 4,
+            'f' => 5,
+            'g' => 7,
+            'a' => 9,
+            'b' | 'h' => 11,
+            'p' => 12,
+            _ => return None,
+        };
+        let mut rest = chars.as_str();
+        let sharp = rest.starts_with('#');
+        if sharp {
+            rest = &rest[1..];
+        }
+        // the dot shows up before or after the octave depending on who wrote it
+        let dotted = rest.contains('.');
+        let (octave, _) = take_number(rest.trim_start_matches('.'));
+
+        // whole note is four beats
+        let mut ms = 4 * 60_000 / (self.bpm * duration);
+        if dotted {
+            ms += ms / 2;
+        }
+
+        let note = if semitone == 12 {
+            Note::Rest
+        } else {
+            let octave = octave.map_or(self.octave, |o| o.min(9) as u8);
+            Note::from_octave(octave, semitone + u8::from(sharp))
+        };
+        Some((note, ms))
+    }
+}
+
+impl Iterator for Rtttl<'_> {
+    type Item = (Note, u32);
+
+    fn next(&mut self) -> Option<(Note, u32)> {
+        loop {
+            let entry = self.notes.next()?;
+            if let Some(note) = self.parse_note(entry) {
+                return Some(note);
+            }
+        }
+    }
+}
@@ -1,4 +1,10 @@
 pub mod prelude;
+// no SPI0 while the encoder has its pins
+#[cfg(not(feature = "encoder"))]
+pub mod spi_device;
+
+#[cfg(not(feature = "encoder"))]
+pub use spi_device::SpiDevice;
 
 pub use rp2040_hal as hal;
 
@@ -7,9 +13,27 @@ pub use rp2040_hal as hal;
 #[used]
 pub static BOOT2_FIRMWARE: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 
-hal::bsp_pins!(Gpio25 {
+hal::bsp_pins!(Gpio0 {
+    name: uart_tx,
+    aliases: { FunctionUart: UartTx }
+},Gpio1 {
+    name: i2s_data,
+    aliases: { FunctionPio0: I2SDATA }
+},Gpio2 {
+    name: i2s_bclk,
+    aliases: { FunctionPio0: I2SBCLK }
+},Gpio3 {
+    name: i2s_lrck,
+    aliases: { FunctionPio0: I2SLRCK }
+},Gpio25 {
     name: led,
     aliases: { PushPullOutput: Led }
+},Gpio4 {
+    name: i2c0_sda,
+    aliases: { FunctionI2C: I2C0SDA }
+},Gpio5 {
+    name: i2c0_scl,
+    aliases: { FunctionI2C: I2C0SCL }
 },Gpio7 {
     name: pwm7,
     aliases: { FunctionPwm: PWM7 }
@@ -37,6 +61,290 @@ hal::bsp_pins!(Gpio25 {
 },Gpio15 {
     name: pwm15,
     aliases: { FunctionPwm: PWM15 }
+},Gpio16 {
+    name: neopixel,
+    aliases: { FunctionPio0: NEOPIXEL }
+},Gpio17 {
+    name: spi0_cs,
+    aliases: { PushPullOutput: SPI0CS }
+},Gpio18 {
+    name: spi0_sck,
+    aliases: { FunctionSpi: SPI0SCK, FunctionPio1: ENCA }
+},Gpio19 {
+    name: spi0_mosi,
+    aliases: { FunctionSpi: SPI0MOSI, FunctionPio1: ENCB }
+},Gpio20 {
+    name: spi0_miso,
+    aliases: { FunctionSpi: SPI0MISO, PullUpInput: ENCSW }
+},Gpio21 {
+    name: buzzer,
+    aliases: { FunctionPwm: Buzzer }
+},Gpio22 {
+    name: button,
+    aliases: { PullUpInput: Button }
+},Gpio24 {
+    name: vbus_detect,
+    aliases: { FloatingInput: VbusDetect }
+},Gpio26 {
+    name: touch_pad,
+    aliases: { FunctionPio1: TouchPad }
+},Gpio27 {
+    name: ir_rx,
+    aliases: { PullUpInput: IRRX }
+},Gpio28 {
+    name: ir_tx,
+    aliases: { FunctionPio0: IRTX }
+},Gpio29 {
+    name: vsys_sense,
+    aliases: { FloatingInput: VsysSense }
 },);
 
 pub const XOSC_CRYSTAL_FREQ: u32 = 12_000_000;
+
+// LIS3DH INT1, if a board has it wired to a GPIO it can wake the badge. The stock one doesn't.
+pub const ACCEL_INT_GPIO: Option<u8> = None;
+
+// LED PWM, about where the slices' defaults were (~950 Hz). Some LEDs and most cameras
+// want more, 20 kHz still leaves 3125 steps phase correct and 6250 edge aligned.
+pub const LED_PWM_FREQ_HZ: u32 = 1_000;
+
+// How a slice counts.
+// PhaseCorrect counts up to top and back down, so a pulse sits in the middle of the
+// period and is symmetric. That's what motor drivers want, and channels with different
+// duties don't all switch on at the same moment. It takes two counts per step though,
+// half the top frequency, or half the duty resolution at the same frequency.
+// EdgeAligned counts up and wraps to 0, every pulse starts with the period. Simpler,
+// and twice as fast.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PwmMode {
+    #[cfg_attr(not(feature = "phase-correct-pwm"), allow(dead_code))]
+    PhaseCorrect,
+    #[cfg_attr(feature = "phase-correct-pwm", allow(dead_code))]
+    EdgeAligned,
+}
+
+impl PwmMode {
+    // counter ticks per step of the wrap
+    const fn ticks_per_count(self) -> u64 {
+        match self {
+            Self::PhaseCorrect => 2,
+            Self::EdgeAligned => 1,
+        }
+    }
+}
+
+// what the LED slices run in, see the phase-correct-pwm feature
+#[cfg(feature = "phase-correct-pwm")]
+pub const LED_PWM_MODE: PwmMode = PwmMode::PhaseCorrect;
+#[cfg(not(feature = "phase-correct-pwm"))]
+pub const LED_PWM_MODE: PwmMode = PwmMode::EdgeAligned;
+
+// Fast mode, everything on the expansion header should handle it
+pub const I2C0_DEFAULT_FREQ_HZ: u32 = 400_000;
+
+// Plenty for displays and flash, slow enough for long wires
+#[cfg(not(feature = "encoder"))]
+pub const SPI0_DEFAULT_FREQ_HZ: u32 = 8_000_000;
+
+pub type I2C0 = hal::I2C<hal::pac::I2C0, (I2C0SDA, I2C0SCL)>;
+
+// I2C0 controller on GPIO4 (SDA) and GPIO5 (SCL)
+pub fn init_i2c0(
+    i2c: hal::pac::I2C0,
+    sda: I2C0SDA,
+    scl: I2C0SCL,
+    resets: &mut hal::pac::RESETS,
+    system_clock: &hal::clocks::SystemClock,
+    freq_hz: u32,
+) -> I2C0 {
+    use hal::Clock;
+    hal::I2C::i2c0(i2c, sda, scl, fugit::HertzU32::from_raw(freq_hz), resets, system_clock.freq())
+}
+
+// Same SCL timing the hal works out in init_i2c0, for a clk_sys that changed since, see
+// power::set_sys_clock_slow. Only between transfers, the controller goes off for it.
+#[allow(clippy::cast_possible_truncation)]
+pub fn set_i2c0_clock(system_clock_hz: u32, freq_hz: u32) {
+    // SAFETY: I2C0 is only ever used from core 0's main loop, which is in here
+    let i2c = unsafe { &*hal::pac::I2C0::ptr() };
+    let period = (system_clock_hz + freq_hz / 2) / freq_hz;
+    let lcnt = period * 3 / 5;
+    let hcnt = period - lcnt;
+    // 300 ns, +1 for the truncation
+    let sda_hold = system_clock_hz * 3 / 10_000_000 + 1;
+
+    i2c.ic_enable.write(|w| w.enable().disabled());
+    while i2c.ic_enable_status.read().ic_en().bit_is_set() {}
+    unsafe {
+        i2c.ic_fs_scl_hcnt.write(|w| w.ic_fs_scl_hcnt().bits(hcnt as u16));
+        i2c.ic_fs_scl_lcnt.write(|w| w.ic_fs_scl_lcnt().bits(lcnt as u16));
+        i2c.ic_fs_spklen.write(|w| w.ic_fs_spklen().bits(if lcnt < 16 { 1 } else { (lcnt / 16) as u8 }));
+        i2c.ic_sda_hold.modify(|_, w| w.ic_sda_tx_hold().bits(sda_hold as u16));
+    }
+    i2c.ic_enable.write(|w| w.enable().enabled());
+}
+
+#[cfg(not(feature = "encoder"))]
+pub type SPI0 = hal::Spi<hal::spi::Enabled, hal::pac::SPI0, 8>;
+
+// SPI0 in mode 0 on GPIO19 (MOSI), GPIO20 (MISO) and GPIO18 (SCK). CS on GPIO17 is
+// driven by hand, see SpiDevice. Pins only need to be in SPI mode, so they are let go.
+#[cfg(not(feature = "encoder"))]
+pub fn init_spi0(
+    spi: hal::pac::SPI0,
+    _mosi: SPI0MOSI,
+    _miso: SPI0MISO,
+    _sck: SPI0SCK,
+    resets: &mut hal::pac::RESETS,
+    peripheral_clock: &hal::clocks::PeripheralClock,
+    freq_hz: u32,
+) -> SPI0 {
+    use hal::Clock;
+    hal::Spi::<_, _, 8>::new(spi).init(
+        resets,
+        peripheral_clock.freq(),
+        fugit::HertzU32::from_raw(freq_hz),
+        &embedded_hal::spi::MODE_0,
+    )
+}
+
+// PWM channels the LED pins above end up on
+pub type LeftEyeRed = hal::pwm::Channel<hal::pwm::Pwm3, hal::pwm::FreeRunning, hal::pwm::B>;
+pub type LeftEyeBlue = hal::pwm::Channel<hal::pwm::Pwm4, hal::pwm::FreeRunning, hal::pwm::A>;
+pub type LeftEyeGreen = hal::pwm::Channel<hal::pwm::Pwm4, hal::pwm::FreeRunning, hal::pwm::B>;
+pub type RightEyeRed = hal::pwm::Channel<hal::pwm::Pwm5, hal::pwm::FreeRunning, hal::pwm::A>;
+pub type RightEyeBlue = hal::pwm::Channel<hal::pwm::Pwm5, hal::pwm::FreeRunning, hal::pwm::B>;
+pub type RightEyeGreen = hal::pwm::Channel<hal::pwm::Pwm6, hal::pwm::FreeRunning, hal::pwm::A>;
+pub type HeartRed = hal::pwm::Channel<hal::pwm::Pwm6, hal::pwm::FreeRunning, hal::pwm::B>;
+pub type HeartBlue = hal::pwm::Channel<hal::pwm::Pwm7, hal::pwm::FreeRunning, hal::pwm::A>;
+pub type HeartGreen = hal::pwm::Channel<hal::pwm::Pwm7, hal::pwm::FreeRunning, hal::pwm::B>;
+
+// Mode, divider and wrap for `freq_hz`, one period is (top + 1) divided ticks edge aligned
+// and twice that phase correct. The divider is kept as small as it goes, so the wrap, and
+// with it the duty resolution, stays as big as possible. Duties still come as 0..=0xffff,
+// scale_duty() fits them under the new top.
+pub fn set_pwm_freq<S: hal::pwm::SliceId>(
+    slice: &mut hal::pwm::Slice<S, hal::pwm::FreeRunning>,
+    mode: PwmMode,
+    freq_hz: u32,
+    sys_clock_hz: u32,
+) where
+    hal::pwm::FreeRunning: hal::pwm::ValidSliceMode<S>,
+{
+    let (div_int, div_frac, top) = pwm_dividers(mode, freq_hz, sys_clock_hz);
+    match mode {
+        PwmMode::PhaseCorrect => slice.set_ph_correct(),
+        PwmMode::EdgeAligned => slice.clr_ph_correct(),
+    }
+    slice.set_top(top);
+    slice.set_div_int(div_int);
+    slice.set_div_frac(div_frac);
+}
+
+// (div_int, div_frac, top) behind set_pwm_freq, power::set_sys_clock_slow writes them raw.
+// Out of reach frequencies get the nearest the divider can do, pwm_freq_reachable() says
+// whether that's close enough.
+#[allow(clippy::cast_possible_truncation)]
+pub const fn pwm_dividers(mode: PwmMode, freq_hz: u32, sys_clock_hz: u32) -> (u8, u8, u16) {
+    let (div, wrap) = pwm_div_wrap(mode, freq_hz, sys_clock_hz);
+    ((div / 16) as u8, (div % 16) as u8, (wrap - 1) as u16)
+}
+
+// divider in 1/16 ticks and wrap, both clamped to what the slice takes
+const fn pwm_div_wrap(mode: PwmMode, freq_hz: u32, sys_clock_hz: u32) -> (u64, u64) {
+    // in 1/16 ticks, the divider has four fractional bits
+    let period = sys_clock_hz as u64 * 16 / (mode.ticks_per_count() * freq_hz as u64);
+    let div = period.div_ceil(0x1_0000);
+    let div = if div < 16 { 16 } else if div > 0xfff { 0xfff } else { div };
+    let wrap = period / div;
+    let wrap = if wrap < 1 { 1 } else if wrap > 0x1_0000 { 0x1_0000 } else { wrap };
+    (div, wrap)
+}
+
+// pwm_dividers() gets within 5% of `freq_hz`
+pub const fn pwm_freq_reachable(mode: PwmMode, freq_hz: u32, sys_clock_hz: u32) -> bool {
+    let (div, wrap) = pwm_div_wrap(mode, freq_hz, sys_clock_hz);
+    let actual_hz = sys_clock_hz as u64 * 16 / (mode.ticks_per_count() * div * wrap);
+    actual_hz.abs_diff(freq_hz as u64) * 20 <= freq_hz as u64
+}
+
+// the LEDs run at both clk_sys speeds, see power::set_sys_clock_slow
+const _: () = {
+    let full = crate::power::NOMINAL_SYS_CLOCK_HZ;
+    assert!(pwm_freq_reachable(LED_PWM_MODE, LED_PWM_FREQ_HZ, full), "LED PWM frequency out of reach");
+    assert!(
+        pwm_freq_reachable(LED_PWM_MODE, LED_PWM_FREQ_HZ, full / crate::power::SLOW_SYS_CLOCK_DIV),
+        "LED PWM frequency out of reach with clk_sys slowed down"
+    );
+};
+
+// 0..=0xffff duty onto 0..=top, a slice at top 0xffff gets it as it is
+#[allow(clippy::cast_possible_truncation)]
+pub const fn scale_duty(duty: u16, top: u16) -> u16 {
+    if duty == u16::MAX {
+        // fully on at any top, over top never goes low
+        return top.saturating_add(1);
+    }
+    (duty as u32 * (top as u32 + 1) / 0x1_0000) as u16
+}
+
+// RP2040 datasheet 4.5.2: GPIO n is on slice (n / 2) % 8, channel A (0) on even pins
+// and B (1) on odd ones
+pub const fn gpio_to_pwm_slice(gpio: u8) -> (u8, u8) {
+    ((gpio / 2) % 8, gpio % 2)
+}
+
+// GPIO number of the pin aliases above
+pub trait GpioNum {
+    const GPIO: u8;
+}
+
+impl<I: hal::gpio::PinId, M: hal::gpio::PinMode + hal::gpio::ValidPinMode<I>> GpioNum for hal::gpio::Pin<I, M> {
+    const GPIO: u8 = I::DYN.num;
+}
+
+// (slice, channel) of the channel types above, same encoding as gpio_to_pwm_slice
+pub trait PwmChannelId {
+    const SLICE_CHANNEL: (u8, u8);
+}
+
+impl<S: hal::pwm::SliceId, M: hal::pwm::SliceMode, C: hal::pwm::ChannelId> PwmChannelId for hal::pwm::Channel<S, M, C> {
+    const SLICE_CHANNEL: (u8, u8) = (
+        S::DYN.num,
+        match C::DYN {
+            hal::pwm::DynChannelId::A => 0,
+            hal::pwm::DynChannelId::B => 1,
+        },
+    );
+}
+
+// All nine in the order of PwmChannels::gains, for code that goes to the registers itself
+pub const LED_PWM_CHANNELS: [(u8, u8); crate::calibration::CHANNEL_COUNT] = [
+    LeftEyeRed::SLICE_CHANNEL,
+    LeftEyeGreen::SLICE_CHANNEL,
+    LeftEyeBlue::SLICE_CHANNEL,
+    RightEyeRed::SLICE_CHANNEL,
+    RightEyeGreen::SLICE_CHANNEL,
+    RightEyeBlue::SLICE_CHANNEL,
+    HeartRed::SLICE_CHANNEL,
+    HeartGreen::SLICE_CHANNEL,
+    HeartBlue::SLICE_CHANNEL,
+];
+
+// A board revision that moves an LED pin without fixing the channel type fails the
+// build here instead of lighting the wrong colour
+const _: () = {
+    const fn check(gpio: u8, channel: (u8, u8)) {
+        let (slice, ab) = gpio_to_pwm_slice(gpio);
+        assert!(slice == channel.0 && ab == channel.1, "LED pin isn't on the PWM channel of its type");
+    }
+    check(PWM7::GPIO, LeftEyeRed::SLICE_CHANNEL);
+    check(PWM8::GPIO, LeftEyeBlue::SLICE_CHANNEL);
+    check(PWM9::GPIO, LeftEyeGreen::SLICE_CHANNEL);
+    check(PWM10::GPIO, RightEyeRed::SLICE_CHANNEL);
+    check(PWM11::GPIO, RightEyeBlue::SLICE_CHANNEL);
+    check(PWM12::GPIO, RightEyeGreen::SLICE_CHANNEL);
+    check(PWM13::GPIO, HeartRed::SLICE_CHANNEL);
+    check(PWM14::GPIO, HeartBlue::SLICE_CHANNEL);
+    check(PWM15::GPIO, HeartGreen::SLICE_CHANNEL);
+};
@@ -1,3 +1,129 @@
 pub use crate::bsp::hal::entry;
 pub use rp2040_hal as hal;
 pub use hal::pac;
+
+use core::sync::atomic::Ordering;
+
+use embedded_hal::PwmPin;
+
+use crate::animations::transition::{CrossfadeTransition, Rgb, LED_COUNT};
+use crate::bsp;
+use crate::calibration::{self, CHANNEL_COUNT};
+use crate::led_dimmer::LedDimmer;
+use crate::power;
+
+// All nine LED channels in one place
+pub struct PwmChannels<'a> {
+    pub left_r: &'a mut bsp::LeftEyeRed,
+    pub left_g: &'a mut bsp::LeftEyeGreen,
+    pub left_b: &'a mut bsp::LeftEyeBlue,
+    pub right_r: &'a mut bsp::RightEyeRed,
+    pub right_g: &'a mut bsp::RightEyeGreen,
+    pub right_b: &'a mut bsp::RightEyeBlue,
+    pub heart_r: &'a mut bsp::HeartRed,
+    pub heart_g: &'a mut bsp::HeartGreen,
+    pub heart_b: &'a mut bsp::HeartBlue,
+    // percent per channel in the order above, see calibration
+    pub gains: [u8; CHANNEL_COUNT],
+    // brightness and thermal limits, full until someone sets them
+    pub dimmer: LedDimmer,
+    // per LED as fractions of 0xffff on top of the dimmer, see SleepFadeAnimation
+    pub fade: [u16; LED_COUNT],
+    // what each LED shows right now, before gains
+    shown: [Rgb; LED_COUNT],
+    // set_* blend into the new values while this runs
+    pub transition: Option<CrossfadeTransition>,
+}
+
+impl<'a> PwmChannels<'a> {
+    // Same wiring as bsp's channel types, slices have to be set up already
+    pub const fn from_slices(slices: &'a mut hal::pwm::Slices) -> Self {
+        Self {
+            left_r: &mut slices.pwm3.channel_b,
+            left_g: &mut slices.pwm4.channel_b,
+            left_b: &mut slices.pwm4.channel_a,
+            right_r: &mut slices.pwm5.channel_a,
+            right_g: &mut slices.pwm6.channel_a,
+            right_b: &mut slices.pwm5.channel_b,
+            heart_r: &mut slices.pwm6.channel_b,
+            heart_g: &mut slices.pwm7.channel_b,
+            heart_b: &mut slices.pwm7.channel_a,
+            gains: [calibration::DEFAULT_GAIN_PERCENT; CHANNEL_COUNT],
+            dimmer: LedDimmer::new(),
+            fade: [u16::MAX; LED_COUNT],
+            shown: [(0, 0, 0); LED_COUNT],
+            transition: None,
+        }
+    }
+
+    // Through the crossfade if there is one, remembers what ends up shown
+    fn blend(&mut self, led: usize, rgb: Rgb) -> Rgb {
+        let rgb = self.transition.as_mut().map_or(rgb, |transition| transition.blend(led, rgb));
+        self.shown[led] = rgb;
+        rgb
+    }
+
+    // Fade, dimmer, battery scale, the channel's gain and the slice's wrap, animations never
+    // see any of them
+    #[allow(clippy::cast_possible_truncation)]
+    fn output(&self, duty: u16, channel: usize) -> u16 {
+        let faded = (u32::from(duty) * u32::from(self.fade[channel / 3]) / 0xffff) as u16;
+        let battery = u32::from(power::BRIGHTNESS_SCALE.load(Ordering::Relaxed).min(100));
+        let scaled = (u32::from(self.dimmer.apply(faded)) * battery / 100) as u16;
+        // all LED slices share the wrap, read each time since it follows clk_sys, see
+        // power::set_sys_clock_slow
+        let top = self.left_r.get_max_duty();
+        bsp::scale_duty(calibration::apply_gain(scaled, self.gains[channel]), top)
+    }
+
+    pub fn set_left_eye(&mut self, r: u16, g: u16, b: u16) {
+        let (r, g, b) = self.blend(0, (r, g, b));
+        self.left_r.set_duty(self.output(r, 0));
+        self.left_g.set_duty(self.output(g, 1));
+        self.left_b.set_duty(self.output(b, 2));
+    }
+
+    pub fn set_right_eye(&mut self, r: u16, g: u16, b: u16) {
+        let (r, g, b) = self.blend(1, (r, g, b));
+        self.right_r.set_duty(self.output(r, 3));
+        self.right_g.set_duty(self.output(g, 4));
+        self.right_b.set_duty(self.output(b, 5));
+    }
+
+    pub fn set_heart(&mut self, r: u16, g: u16, b: u16) {
+        let (r, g, b) = self.blend(2, (r, g, b));
+        self.heart_r.set_duty(self.output(r, 6));
+        self.heart_g.set_duty(self.output(g, 7));
+        self.heart_b.set_duty(self.output(b, 8));
+    }
+
+    // From whatever is shown now, even halfway through another crossfade
+    pub const fn start_crossfade(&mut self) {
+        self.transition = Some(CrossfadeTransition::new(self.shown));
+    }
+
+    // Once per frame, drops the transition when it's over
+    pub fn advance_crossfade(&mut self, delta_ms: u32) {
+        if let Some(transition) = self.transition.as_mut() {
+            transition.advance(delta_ms);
+            if transition.is_done() {
+                self.transition = None;
+            }
+        }
+    }
+
+    // Only this one on, by its index in `gains`, for calibration
+    pub fn set_only_channel(&mut self, channel: usize, duty: u16) {
+        let mut duties = [0; CHANNEL_COUNT];
+        duties[channel] = duty;
+        self.set_left_eye(duties[0], duties[1], duties[2]);
+        self.set_right_eye(duties[3], duties[4], duties[5]);
+        self.set_heart(duties[6], duties[7], duties[8]);
+    }
+
+    pub fn set_all_off(&mut self) {
+        self.set_left_eye(0, 0, 0);
+        self.set_right_eye(0, 0, 0);
+        self.set_heart(0, 0, 0);
+    }
+}
@@ -0,0 +1,86 @@
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_1::spi::{self, ErrorKind, ErrorType, Operation};
+
+#[derive(Debug)]
+pub enum Error {
+    Bus,
+    ChipSelect,
+}
+
+impl spi::Error for Error {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+// SPI bus plus its chip select, so embedded-hal 1.0 driver crates can be used as is.
+// CS is low for the whole transaction.
+pub struct SpiDevice<BUS, CS> {
+    bus: BUS,
+    cs: CS,
+    cycles_per_us: u32,
+}
+
+impl<BUS, CS> SpiDevice<BUS, CS>
+where
+    BUS: Transfer<u8> + Write<u8>,
+    CS: OutputPin,
+{
+    // system_clock_hz is only needed for the delays drivers ask for in between
+    pub fn new(bus: BUS, mut cs: CS, system_clock_hz: u32) -> Self {
+        cs.set_high().ok();
+        Self {
+            bus,
+            cs,
+            cycles_per_us: system_clock_hz / 1_000_000,
+        }
+    }
+
+    fn run(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error> {
+        for operation in operations {
+            match operation {
+                Operation::Read(words) => {
+                    words.fill(0);
+                    self.bus.transfer(words).map_err(|_| Error::Bus)?;
+                }
+                Operation::Write(words) => self.bus.write(words).map_err(|_| Error::Bus)?,
+                Operation::Transfer(read, write) => {
+                    // shorter one is padded, with zeros going out or bytes dropped coming in
+                    for i in 0..read.len().max(write.len()) {
+                        let mut word = [write.get(i).copied().unwrap_or(0)];
+                        self.bus.transfer(&mut word).map_err(|_| Error::Bus)?;
+                        if let Some(r) = read.get_mut(i) {
+                            *r = word[0];
+                        }
+                    }
+                }
+                Operation::TransferInPlace(words) => {
+                    self.bus.transfer(words).map_err(|_| Error::Bus)?;
+                }
+                Operation::DelayNs(ns) => {
+                    cortex_m::asm::delay(ns.saturating_mul(self.cycles_per_us) / 1000 + 1);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<BUS, CS> ErrorType for SpiDevice<BUS, CS> {
+    type Error = Error;
+}
+
+impl<BUS, CS> spi::SpiDevice<u8> for SpiDevice<BUS, CS>
+where
+    BUS: Transfer<u8> + Write<u8>,
+    CS: OutputPin,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Error> {
+        self.cs.set_low().map_err(|_| Error::ChipSelect)?;
+        let result = self.run(operations);
+        // always let go of the bus, even if something failed
+        self.cs.set_high().map_err(|_| Error::ChipSelect)?;
+        result
+    }
+}
@@ -0,0 +1,124 @@
+use embedded_hal::digital::v2::InputPin;
+
+use crate::bsp::prelude::PwmChannels;
+use crate::input::{ButtonEvent, Debouncer};
+use crate::storage;
+
+// Eye LED gains. Relative luminosity of each color (Rec. 709: 0.2126 R, 0.7152 G,
+// 0.0722 B) says how bright it looks at the same duty, so each channel is scaled down to
+// match blue, the dimmest one.
+const LUMA_R: f32 = 0.2126;
+const LUMA_G: f32 = 0.7152;
+const LUMA_B: f32 = 0.0722;
+
+// Override at build time for LEDs that don't follow the book, e.g.
+// BADGE_EYE_G_SCALE=0.3 cargo build
+pub const EYE_R_SCALE: f32 = parse_scale(option_env!("BADGE_EYE_R_SCALE"), LUMA_B / LUMA_R);
+pub const EYE_G_SCALE: f32 = parse_scale(option_env!("BADGE_EYE_G_SCALE"), LUMA_B / LUMA_G);
+pub const EYE_B_SCALE: f32 = parse_scale(option_env!("BADGE_EYE_B_SCALE"), 1.0);
+
+// Plain decimals like "0.34". Anything else, or a scale outside 0..=1, fails the build.
+const fn parse_scale(value: Option<&str>, default: f32) -> f32 {
+    let Some(text) = value else {
+        return default;
+    };
+
+    let bytes = text.as_bytes();
+    let mut scale = 0.0;
+    let mut divisor = 1.0;
+    let mut seen_dot = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'.' if !seen_dot => seen_dot = true,
+            digit @ b'0'..=b'9' => {
+                let digit = (digit - b'0') as f32;
+                if seen_dot {
+                    divisor *= 10.0;
+                    scale += digit / divisor;
+                } else {
+                    scale = scale * 10.0 + digit;
+                }
+            }
+            _ => panic!("eye scale has to be a plain decimal number"),
+        }
+        i += 1;
+    }
+    assert!(scale > 0.0 && scale <= 1.0, "eye scale has to be above 0 and at most 1");
+    scale
+}
+
+// Per LED gains on top of the color scales, for LEDs of the same bin that still don't
+// look the same. Percent, set with the calibration routine and kept in flash.
+pub const CHANNEL_COUNT: usize = 9;
+pub const DEFAULT_GAIN_PERCENT: u8 = 100;
+pub const MIN_GAIN_PERCENT: u8 = 50;
+pub const MAX_GAIN_PERCENT: u8 = 150;
+const GAIN_STEP_PERCENT: u8 = 10;
+
+// Each LED is shown at this while it's being calibrated
+const TEST_DUTY: u16 = u16::MAX / 2;
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn apply_gain(duty: u16, gain_percent: u8) -> u16 {
+    (u32::from(duty) * u32::from(gain_percent) / 100).min(u32::from(u16::MAX)) as u16
+}
+
+// Lights one channel at a time. Short press: looks right, on to the next one.
+// Long press: a step brighter, from the brightest it wraps around to the dimmest.
+pub struct CalibrationRoutine {
+    channel: usize,
+    gains: [u8; CHANNEL_COUNT],
+}
+
+impl CalibrationRoutine {
+    pub const fn new(gains: [u8; CHANNEL_COUNT]) -> Self {
+        Self { channel: 0, gains }
+    }
+
+    // Some with all the gains once the last channel is confirmed
+    pub fn update(&mut self, event: ButtonEvent, channels: &mut PwmChannels) -> Option<[u8; CHANNEL_COUNT]> {
+        match event {
+            ButtonEvent::ShortPress => self.channel += 1,
+            ButtonEvent::LongPress(_) => {
+                let gain = &mut self.gains[self.channel];
+                *gain = if *gain >= MAX_GAIN_PERCENT {
+                    MIN_GAIN_PERCENT
+                } else {
+                    (*gain + GAIN_STEP_PERCENT).min(MAX_GAIN_PERCENT)
+                };
+            }
+            ButtonEvent::Held(_) | ButtonEvent::Released | ButtonEvent::None => {}
+        }
+
+        channels.gains = self.gains;
+        if self.channel == CHANNEL_COUNT {
+            channels.set_all_off();
+            return Some(self.gains);
+        }
+        channels.set_only_channel(self.channel, TEST_DUTY);
+        None
+    }
+}
+
+// Hold the button through power on to get here. Core 1 isn't running yet, so the
+// LEDs are ours until all nine are done.
+pub fn run<B: InputPin>(channels: &mut PwmChannels, button: &B, delay: &mut cortex_m::delay::Delay) {
+    const TICK_MS: u32 = 10;
+
+    let mut debouncer = Debouncer::new();
+    // the press that got us here doesn't count
+    while button.is_low().unwrap_or(false) {
+        delay.delay_ms(TICK_MS);
+    }
+
+    let mut routine = CalibrationRoutine::new(storage::load_calibration());
+    loop {
+        let event = debouncer.update(button.is_low().unwrap_or(false), TICK_MS);
+        if let Some(gains) = routine.update(event, channels) {
+            storage::save_calibration(&gains).ok();
+            return;
+        }
+        delay.delay_ms(TICK_MS);
+    }
+}
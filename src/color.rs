@@ -0,0 +1,209 @@
+// HSV without floats, the M0+ has no FPU and palette's conversion did everything in
+// soft float. Hue in hundredths of a degree, saturation and value 0..=255.
+
+pub const HUE_CIRCLE: u32 = 36_000;
+const SECTOR: u32 = HUE_CIRCLE / 6;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Hsv {
+    pub hue_centidegrees: u32,
+    pub saturation: u8,
+    pub value: u8,
+}
+
+impl Hsv {
+    pub const WHITE: Self = Self::new(0, 0, u8::MAX);
+
+    pub const fn new(hue_centidegrees: u32, saturation: u8, value: u8) -> Self {
+        Self {
+            hue_centidegrees: hue_centidegrees % HUE_CIRCLE,
+            saturation,
+            value,
+        }
+    }
+
+    // Full saturation and value
+    pub const fn from_degrees(degrees: u32) -> Self {
+        Self::new(degrees * 100, u8::MAX, u8::MAX)
+    }
+
+    // For animations that work out their colors in f32 anyway, hue wraps and the
+    // rest is clamped to 0.0..=1.0
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn from_f32(hue_degrees: f32, saturation: f32, value: f32) -> Self {
+        let hue = hue_degrees % 360.0;
+        let hue = if hue < 0.0 { hue + 360.0 } else { hue };
+        let unit = |x: f32| (x.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+        Self::new((hue * 100.0) as u32, unit(saturation), unit(value))
+    }
+
+    pub const fn degrees(self) -> u32 {
+        self.hue_centidegrees / 100
+    }
+
+    pub const fn to_rgb_u16(self) -> (u16, u16, u16) {
+        hsv_to_rgb_u16(self.hue_centidegrees, self.saturation, self.value)
+    }
+}
+
+// 8 bits a channel like everywhere else, what set_solid takes as hex
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Srgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Srgb {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+
+    // 0xrrggbb
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn from_rgb24(rgb: u32) -> Self {
+        Self::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+
+    // Six hex digits like ff8000, a leading # is fine too
+    pub fn from_hex(text: &str) -> Option<Self> {
+        let digits = text.strip_prefix('#').unwrap_or(text);
+        if digits.len() != 6 || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None;
+        }
+        u32::from_str_radix(digits, 16).ok().map(Self::from_rgb24)
+    }
+
+    pub const fn to_bytes(self) -> [u8; 3] {
+        [self.red, self.green, self.blue]
+    }
+
+    pub const fn from_bytes([red, green, blue]: [u8; 3]) -> Self {
+        Self::new(red, green, blue)
+    }
+
+    // Full scale duties, 0xff is 0xffff
+    pub const fn to_rgb_u16(self) -> (u16, u16, u16) {
+        (self.red as u16 * 257, self.green as u16 * 257, self.blue as u16 * 257)
+    }
+}
+
+// Full saturation and value, the six sector one: one channel full, one off and one
+// ramping between them
+#[allow(clippy::cast_possible_truncation)]
+pub const fn hue_to_rgb_u16(hue_centidegrees: u32) -> (u16, u16, u16) {
+    let hue = hue_centidegrees % HUE_CIRCLE;
+    let rising = (hue % SECTOR * 0xffff / SECTOR) as u16;
+    let falling = u16::MAX - rising;
+    match hue / SECTOR {
+        0 => (u16::MAX, rising, 0),
+        1 => (falling, u16::MAX, 0),
+        2 => (0, u16::MAX, rising),
+        3 => (0, falling, u16::MAX),
+        4 => (rising, 0, u16::MAX),
+        _ => (u16::MAX, 0, falling),
+    }
+}
+
+// Each step stays under 2^32: 255 * 0xffff and 0xffff * 0xffff
+#[allow(clippy::cast_possible_truncation)]
+const fn saturate_channel(duty: u16, sat: u32, val: u32) -> u16 {
+    let mixed = ((255 - sat) * 0xffff + sat * duty as u32) / 255;
+    (mixed * val / 0xffff) as u16
+}
+
+// Saturation mixes in white, value scales it all
+pub const fn saturate_and_scale((r, g, b): (u16, u16, u16), sat: u8, val: u8) -> (u16, u16, u16) {
+    let (sat, val) = (sat as u32, val as u32 * 257);
+    (
+        saturate_channel(r, sat, val),
+        saturate_channel(g, sat, val),
+        saturate_channel(b, sat, val),
+    )
+}
+
+pub const fn hsv_to_rgb_u16(hue_centidegrees: u32, sat: u8, val: u8) -> (u16, u16, u16) {
+    saturate_and_scale(hue_to_rgb_u16(hue_centidegrees), sat, val)
+}
+
+pub const MIN_KELVIN: u16 = 1000;
+pub const MAX_KELVIN: u16 = 12_000;
+
+// Blackbody color, Tanner Helland's fit to Mitchell Charity's table. Good to a few
+// percent in 1000..=12000 K, outside that it's clamped. Soft float logs and powers,
+// three a frame is still nothing next to the rest of the frame.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn kelvin_to_rgb(kelvin: u16) -> (u16, u16, u16) {
+    let temp = f32::from(kelvin.clamp(MIN_KELVIN, MAX_KELVIN)) / 100.0;
+    let (r, g, b) = if temp <= 66.0 {
+        let g = 99.470_8 * libm::logf(temp) - 161.119_57;
+        let b = if temp <= 19.0 { 0.0 } else { 138.517_73 * libm::logf(temp - 10.0) - 305.044_8 };
+        (255.0, g, b)
+    } else {
+        let r = 329.698_73 * libm::powf(temp - 60.0, -0.133_204_76);
+        let g = 288.122_17 * libm::powf(temp - 60.0, -0.075_514_85);
+        (r, g, 255.0)
+    };
+    let duty = |x: f32| (x.clamp(0.0, 255.0) * 257.0) as u16;
+    (duty(r), duty(g), duty(b))
+}
+
+// D65 white, what sRGB is defined against
+const WHITE_X: f32 = 0.950_47;
+const WHITE_Z: f32 = 1.088_83;
+
+// Where L*a*b*'s cube root gives way to a straight line near black
+const LAB_DELTA: f32 = 6.0 / 29.0;
+
+// sRGB's transfer curve, 0.0..=1.0 both ways
+fn srgb_to_linear(channel: u8) -> f32 {
+    let c = f32::from(channel) / 255.0;
+    if c <= 0.040_45 { c / 12.92 } else { libm::powf((c + 0.055) / 1.055, 2.4) }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn linear_to_srgb(linear: f32) -> u8 {
+    let c = linear.clamp(0.0, 1.0);
+    let c = if c <= 0.003_130_8 { c * 12.92 } else { 1.055 * libm::powf(c, 1.0 / 2.4) - 0.055 };
+    (c * 255.0 + 0.5) as u8
+}
+
+fn lab_f(t: f32) -> f32 {
+    if t > LAB_DELTA * LAB_DELTA * LAB_DELTA {
+        libm::cbrtf(t)
+    } else {
+        t / (3.0 * LAB_DELTA * LAB_DELTA) + 4.0 / 29.0
+    }
+}
+
+fn lab_f_inv(t: f32) -> f32 {
+    if t > LAB_DELTA {
+        t * t * t
+    } else {
+        3.0 * LAB_DELTA * LAB_DELTA * (t - 4.0 / 29.0)
+    }
+}
+
+// CIE L*a*b* through XYZ, L* 0..=100. Equal steps in a* and b* look about equally far
+// apart, which HSV's hue wheel doesn't: its yellow and green look much brighter than
+// its blue. Colors outside sRGB get each channel clamped.
+pub fn lab_to_rgb(l: f32, a: f32, b: f32) -> Srgb {
+    let fy = (l + 16.0) / 116.0;
+    let cie_x = WHITE_X * lab_f_inv(fy + a / 500.0);
+    let cie_y = lab_f_inv(fy);
+    let cie_z = WHITE_Z * lab_f_inv(fy - b / 200.0);
+    Srgb::new(
+        linear_to_srgb(3.240_454_2 * cie_x - 1.537_138_5 * cie_y - 0.498_531_4 * cie_z),
+        linear_to_srgb(-0.969_266 * cie_x + 1.876_010_8 * cie_y + 0.041_556 * cie_z),
+        linear_to_srgb(0.055_643_4 * cie_x - 0.204_025_9 * cie_y + 1.057_225_2 * cie_z),
+    )
+}
+
+// (L*, a*, b*) of an sRGB color
+pub fn rgb_to_lab(rgb: Srgb) -> (f32, f32, f32) {
+    let (red, green, blue) = (srgb_to_linear(rgb.red), srgb_to_linear(rgb.green), srgb_to_linear(rgb.blue));
+    let fx = lab_f((0.412_456_4 * red + 0.357_576_1 * green + 0.180_437_5 * blue) / WHITE_X);
+    let fy = lab_f(0.212_672_9 * red + 0.715_152_2 * green + 0.072_175 * blue);
+    let fz = lab_f((0.019_333_9 * red + 0.119_192 * green + 0.950_304_1 * blue) / WHITE_Z);
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
@@ -0,0 +1,561 @@
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use critical_section::Mutex;
+use rp2040_hal::multicore::{Multicore, Stack};
+use rp2040_hal::pac;
+
+use crate::animations::easing::{self, Interpolator};
+use crate::animations::binary::BinaryBlinker;
+use crate::animations::boop::NoseBoopAnimation;
+use crate::animations::sleep::SleepFadeAnimation;
+use crate::animations::{self, AnimationMode, AnimationState};
+use crate::bsp::prelude::*;
+use crate::color::Srgb;
+use crate::filter::TempTrend;
+use crate::gamma::gamma_correct;
+use crate::led_config::{self, LedConfig};
+use crate::morse::{self, MorseBlinker, MorseEncoder};
+use crate::power;
+use crate::storage;
+use crate::timer;
+
+// Core 1 draws the LEDs, core 0 only tells it what to draw
+const CORE1_STACK_WORDS: usize = 2048;
+
+// Core 0 tells core 1 what to draw, one FIFO word per message
+#[derive(Clone, Copy, PartialEq)]
+pub enum CoreMessage {
+    SetCold(bool),
+    SetHot(bool),
+    SetTrend(TempTrend),
+    SetMode(AnimationMode),
+    // percent, see LedDimmer
+    SetBrightness(u8),
+    // play the meet-a-badge blink
+    Ack,
+    // all off and stay off, the rail is about to go
+    LightsOut,
+    // LED gains in flash changed
+    ReloadCalibration,
+    // blink out what's waiting in MORSE, the text doesn't fit in a FIFO word
+    Morse,
+    // true: play the shutdown animation and stay dark, false: back to drawing
+    Sleep(bool),
+    // somebody touched the nose, see NoseBoopAnimation
+    Boop,
+    // how far the fade before sleep is, 0 none and 255 dark, see SleepFadeAnimation
+    SleepFade(u8),
+}
+
+// Top four bits say which message it is, the rest is payload
+const TAG_SHIFT: u32 = 28;
+const TAG_SET_COLD: u32 = 1;
+const TAG_SET_MODE: u32 = 2;
+const TAG_SET_BRIGHTNESS: u32 = 3;
+const TAG_ACK: u32 = 4;
+const TAG_LIGHTS_OUT: u32 = 5;
+const TAG_RELOAD_CALIBRATION: u32 = 6;
+const TAG_MORSE: u32 = 7;
+const TAG_SET_TREND: u32 = 8;
+const TAG_SLEEP: u32 = 9;
+const TAG_BOOP: u32 = 10;
+const TAG_SLEEP_FADE: u32 = 11;
+const TAG_SET_HOT: u32 = 12;
+
+// SetMode: mode index in the low five bits, pack()'s 23 bits of parameters above
+const MODE_PARAMS_SHIFT: u32 = 5;
+
+impl CoreMessage {
+    pub fn encode(self) -> u32 {
+        let (tag, payload) = match self {
+            Self::SetCold(cold) => (TAG_SET_COLD, u32::from(cold)),
+            Self::SetHot(hot) => (TAG_SET_HOT, u32::from(hot)),
+            Self::SetMode(mode) => {
+                if let Some(colors) = mode.solid_colors() {
+                    critical_section::with(|cs| *SOLID_COLORS.borrow_ref_mut(cs) = colors);
+                }
+                if let Some(speeds) = mode.spectral_speeds() {
+                    critical_section::with(|cs| *SPECTRAL_SPEEDS.borrow_ref_mut(cs) = speeds);
+                }
+                let (index, params) = mode.pack();
+                (TAG_SET_MODE, u32::from(index) | params << MODE_PARAMS_SHIFT)
+            }
+            Self::SetBrightness(percent) => (TAG_SET_BRIGHTNESS, u32::from(percent)),
+            Self::Ack => (TAG_ACK, 0),
+            Self::LightsOut => (TAG_LIGHTS_OUT, 0),
+            Self::ReloadCalibration => (TAG_RELOAD_CALIBRATION, 0),
+            Self::Morse => (TAG_MORSE, 0),
+            Self::SetTrend(trend) => (TAG_SET_TREND, trend as u32),
+            Self::Sleep(sleep) => (TAG_SLEEP, u32::from(sleep)),
+            Self::Boop => (TAG_BOOP, 0),
+            Self::SleepFade(progress) => (TAG_SLEEP_FADE, u32::from(progress)),
+        };
+        tag << TAG_SHIFT | payload
+    }
+
+    // None for anything we don't know, better to skip it than draw garbage
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn decode(word: u32) -> Option<Self> {
+        let payload = word & ((1 << TAG_SHIFT) - 1);
+        match word >> TAG_SHIFT {
+            TAG_SET_COLD => Some(Self::SetCold(payload != 0)),
+            TAG_SET_HOT => Some(Self::SetHot(payload != 0)),
+            TAG_SET_MODE => AnimationMode::unpack(payload as u8 & 0x1f, payload >> MODE_PARAMS_SHIFT).map(|mode| {
+                let (colors, speeds) =
+                    critical_section::with(|cs| (*SOLID_COLORS.borrow_ref(cs), *SPECTRAL_SPEEDS.borrow_ref(cs)));
+                Self::SetMode(mode.with_solid_colors(colors).with_spectral_speeds(speeds))
+            }),
+            TAG_SET_BRIGHTNESS => Some(Self::SetBrightness(payload as u8)),
+            TAG_ACK => Some(Self::Ack),
+            TAG_LIGHTS_OUT => Some(Self::LightsOut),
+            TAG_RELOAD_CALIBRATION => Some(Self::ReloadCalibration),
+            TAG_MORSE => Some(Self::Morse),
+            TAG_SLEEP => Some(Self::Sleep(payload != 0)),
+            TAG_BOOP => Some(Self::Boop),
+            TAG_SLEEP_FADE => Some(Self::SleepFade(payload as u8)),
+            TAG_SET_TREND => match payload {
+                0 => Some(Self::SetTrend(TempTrend::Rising)),
+                1 => Some(Self::SetTrend(TempTrend::Falling)),
+                2 => Some(Self::SetTrend(TempTrend::Stable)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// Core 0's end of the FIFO. Only changes are sent and a full FIFO never blocks,
+// whatever didn't fit goes out on a later frame. Messages without a payload to
+// compare against get a pending flag each.
+#[allow(clippy::struct_excessive_bools)]
+pub struct Core1Link {
+    fifo: hal::sio::SioFifo,
+    sent_mode: Option<AnimationMode>,
+    sent_cold: Option<bool>,
+    sent_hot: Option<bool>,
+    sent_trend: Option<TempTrend>,
+    sent_brightness: Option<u8>,
+    sent_sleep_fade: Option<u8>,
+    pending_ack: bool,
+    pending_calibration: bool,
+    pending_morse: bool,
+    pending_boop: bool,
+}
+
+// Morse text on its way to core 1, whoever gets the Morse message takes it
+static MORSE: Mutex<RefCell<Option<MorseBlinker>>> = Mutex::new(RefCell::new(None));
+
+// Colors of the last Solid sent, they don't fit in the SetMode word. A newer Solid
+// can overwrite them before core 1 reads, it'd get those next anyway.
+static SOLID_COLORS: Mutex<RefCell<[Srgb; 3]>> = Mutex::new(RefCell::new([animations::DEFAULT_SOLID_COLOR; 3]));
+
+// Same for Spectral's speeds
+static SPECTRAL_SPEEDS: Mutex<RefCell<[f32; 3]>> = Mutex::new(RefCell::new(animations::spectral::DEFAULT_SPEEDS));
+
+impl Core1Link {
+    fn try_send(&mut self, message: CoreMessage) -> bool {
+        if !self.fifo.is_write_ready() {
+            return false;
+        }
+        self.fifo.write(message.encode());
+        true
+    }
+
+    // sleep_fade is SleepFadeAnimation's progress
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn sync(
+        &mut self,
+        mode: AnimationMode,
+        cold: bool,
+        hot: bool,
+        trend: TempTrend,
+        brightness_percent: u8,
+        sleep_fade: f32,
+    ) {
+        if self.sent_mode != Some(mode) && self.try_send(CoreMessage::SetMode(mode)) {
+            self.sent_mode = Some(mode);
+        }
+        if self.sent_cold != Some(cold) && self.try_send(CoreMessage::SetCold(cold)) {
+            self.sent_cold = Some(cold);
+        }
+        if self.sent_hot != Some(hot) && self.try_send(CoreMessage::SetHot(hot)) {
+            self.sent_hot = Some(hot);
+        }
+        if self.sent_trend != Some(trend) && self.try_send(CoreMessage::SetTrend(trend)) {
+            self.sent_trend = Some(trend);
+        }
+        if self.sent_brightness != Some(brightness_percent)
+            && self.try_send(CoreMessage::SetBrightness(brightness_percent))
+        {
+            self.sent_brightness = Some(brightness_percent);
+        }
+        let sleep_fade = (sleep_fade.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+        if self.sent_sleep_fade != Some(sleep_fade) && self.try_send(CoreMessage::SleepFade(sleep_fade)) {
+            self.sent_sleep_fade = Some(sleep_fade);
+        }
+        if self.pending_ack && self.try_send(CoreMessage::Ack) {
+            self.pending_ack = false;
+        }
+        if self.pending_calibration && self.try_send(CoreMessage::ReloadCalibration) {
+            self.pending_calibration = false;
+        }
+        if self.pending_morse && self.try_send(CoreMessage::Morse) {
+            self.pending_morse = false;
+        }
+        if self.pending_boop && self.try_send(CoreMessage::Boop) {
+            self.pending_boop = false;
+        }
+    }
+
+    // Goes out with the next sync()
+    pub const fn ack(&mut self) {
+        self.pending_ack = true;
+    }
+
+    // Goes out with the next sync()
+    pub const fn boop(&mut self) {
+        self.pending_boop = true;
+    }
+
+    // Goes out with the next sync()
+    pub const fn reload_calibration(&mut self) {
+        self.pending_calibration = true;
+    }
+
+    // Goes out with the next sync(), replaces a message core 1 hasn't picked up yet
+    pub fn morse(&mut self, encoder: MorseEncoder) {
+        let blinker = MorseBlinker::new(encoder, morse::DEFAULT_UNIT_MS);
+        critical_section::with(|cs| MORSE.borrow_ref_mut(cs).replace(blinker));
+        self.pending_morse = true;
+    }
+
+    // The one message that waits for room, nothing else matters anymore
+    pub fn lights_out(&mut self) {
+        self.fifo.write_blocking(CoreMessage::LightsOut.encode());
+    }
+
+    // These wait for room too, going to sleep without telling core 1 would leave it
+    // dark or drawing the shutdown forever. The animation takes SHUTDOWN_MS.
+    pub fn sleep(&mut self) {
+        self.fifo.write_blocking(CoreMessage::Sleep(true).encode());
+    }
+
+    pub fn wake(&mut self) {
+        self.fifo.write_blocking(CoreMessage::Sleep(false).encode());
+    }
+}
+
+// Flash can't be read while it's being written, core 1 waits in RAM meanwhile
+static STARTED: AtomicBool = AtomicBool::new(false);
+static PARK_REQUEST: AtomicBool = AtomicBool::new(false);
+static PARKED: AtomicBool = AtomicBool::new(false);
+
+// PWM slices have to be set up already, core 1 only sets duties from here on.
+// The FIFO is only needed for the launch, after that it's how we talk to core 1.
+pub fn spawn(
+    psm: &mut pac::PSM,
+    ppb: &mut pac::PPB,
+    mut fifo: hal::sio::SioFifo,
+    slices: hal::pwm::Slices,
+    seed: u32,
+) -> Core1Link {
+    let stack = cortex_m::singleton!(: Stack<CORE1_STACK_WORDS> = Stack::new()).unwrap();
+    let mut multicore = Multicore::new(psm, ppb, &mut fifo);
+    let core1 = &mut multicore.cores()[1];
+    core1.spawn(&mut stack.mem, move || run(slices, seed)).unwrap();
+    STARTED.store(true, Ordering::Release);
+
+    Core1Link {
+        fifo,
+        sent_mode: None,
+        sent_cold: None,
+        sent_hot: None,
+        sent_trend: None,
+        sent_brightness: None,
+        sent_sleep_fade: None,
+        pending_ack: false,
+        pending_calibration: false,
+        pending_morse: false,
+        pending_boop: false,
+    }
+}
+
+// Runs `f` with core 1 stuck in RAM, for flash writes
+pub fn parked<T>(f: impl FnOnce() -> T) -> T {
+    if !STARTED.load(Ordering::Acquire) {
+        return f();
+    }
+
+    PARK_REQUEST.store(true, Ordering::Release);
+    while !PARKED.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+    let result = f();
+    PARK_REQUEST.store(false, Ordering::Release);
+    while PARKED.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+    result
+}
+
+fn park_if_requested() {
+    if PARK_REQUEST.load(Ordering::Acquire) {
+        // SAFETY: both point to statics that live forever
+        unsafe { park_in_ram(PARK_REQUEST.as_ptr().cast(), PARKED.as_ptr().cast()) };
+    }
+}
+
+// Plain loads and stores only, everything else might be a call into flash
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn park_in_ram(request: *const u8, parked: *mut u8) {
+    core::arch::asm!(
+        "movs {tmp}, #1",
+        "strb {tmp}, [{parked}]",
+        "dmb",
+        "2:",
+        "ldrb {tmp}, [{request}]",
+        "cmp {tmp}, #0",
+        "bne 2b",
+        "dmb",
+        "movs {tmp}, #0",
+        "strb {tmp}, [{parked}]",
+        request = in(reg) request,
+        parked = in(reg) parked,
+        tmp = out(reg) _,
+    );
+}
+
+// Until `frames` frame ticks have gone by since `last_frame`. Running late doesn't
+// get made up for, the next wait counts from now.
+fn wait_frames(last_frame: &mut u32, frames: u32) {
+    while timer::frame_count().wrapping_sub(*last_frame) < frames {
+        park_if_requested();
+    }
+    *last_frame = timer::frame_count();
+}
+
+// Core 1's copy of what to draw, only ever changed by messages from core 0
+#[allow(clippy::struct_excessive_bools)]
+struct RenderState {
+    mode: AnimationMode,
+    // what's being drawn, a crossfade starts when `mode` moves on
+    shown_mode: AnimationMode,
+    cold: bool,
+    hot: bool,
+    trend: TempTrend,
+    // in percent, fades towards the last SetBrightness
+    brightness: Interpolator,
+    ack_ms: Option<u32>,
+    boop: Option<NoseBoopAnimation>,
+    // how far into the shutdown animation, it holds at the end until woken up
+    shutdown_ms: Option<u32>,
+    sleep_fade: SleepFadeAnimation,
+    morse: Option<MorseBlinker>,
+    // temperature in binary on the heart, see binary.rs
+    binary: Option<BinaryBlinker>,
+    binary_ms: u32,
+    lights_out: bool,
+    reload_calibration: bool,
+}
+
+impl RenderState {
+    fn apply(&mut self, message: CoreMessage) {
+        match message {
+            CoreMessage::SetCold(cold) => self.cold = cold,
+            CoreMessage::SetHot(hot) => self.hot = hot,
+            CoreMessage::SetTrend(trend) => self.trend = trend,
+            CoreMessage::SetMode(mode) => self.mode = mode,
+            CoreMessage::SetBrightness(percent) => {
+                let from = self.brightness.value();
+                let to = f32::from(percent);
+                // brightening starts fast, dimming ends fast
+                let easing = if to > from { easing::ease_out_quad } else { easing::ease_in_quad };
+                self.brightness = Interpolator::new(from, to, animations::BRIGHTNESS_FADE_MS, easing);
+            }
+            CoreMessage::Ack => self.ack_ms = Some(0),
+            // can't be interrupted, not even by another boop
+            CoreMessage::Boop => {
+                if self.boop.is_none() {
+                    self.boop = Some(NoseBoopAnimation::new());
+                }
+            }
+            CoreMessage::Sleep(sleep) => self.shutdown_ms = sleep.then_some(0),
+            CoreMessage::SleepFade(progress) => self.sleep_fade.progress = f32::from(progress) / 255.0,
+            CoreMessage::LightsOut => self.lights_out = true,
+            CoreMessage::ReloadCalibration => self.reload_calibration = true,
+            CoreMessage::Morse => {
+                if let Some(blinker) = critical_section::with(|cs| MORSE.borrow_ref_mut(cs).take()) {
+                    self.morse = Some(blinker);
+                }
+            }
+        }
+    }
+
+    // Over whatever the heart was showing, the animation underneath keeps going
+    fn blink_temperature(&mut self, channels: &mut PwmChannels, duty: u16) {
+        let delta_ms = animations::frame_ms(self.cold);
+        self.binary_ms += delta_ms;
+        if self.binary_ms >= animations::binary::INTERVAL_MS {
+            self.binary_ms = 0;
+            if self.shown_mode != AnimationMode::Off {
+                self.binary = Some(BinaryBlinker::from_celsius(animations::thermometer::celsius()));
+            }
+        }
+        if let Some(blinker) = self.binary.as_mut() {
+            if !blinker.render(channels, delta_ms, duty) {
+                self.binary = None;
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn brightness_percent(&mut self, delta_ms: u32) -> u8 {
+        self.brightness.tick(delta_ms) as u8
+    }
+}
+
+// Everything core 0 sent since last time, never waits for more
+fn read_messages(fifo: &mut hal::sio::SioFifo, state: &mut RenderState) {
+    while let Some(word) = fifo.read() {
+        if let Some(message) = CoreMessage::decode(word) {
+            state.apply(message);
+        }
+    }
+}
+
+fn run(mut slices: hal::pwm::Slices, seed: u32) -> ! {
+    // SAFETY: only the FIFO is used, and each core has its own end of it
+    let mut fifo = hal::Sio::new(unsafe { pac::Peripherals::steal() }.SIO).fifo;
+    let mut channels = PwmChannels::from_slices(&mut slices);
+    channels.gains = storage::load_calibration();
+    let mut animation = AnimationState::new(led_config::DEFAULT_LED_CONFIG, crate::HEART_MODE, seed);
+    let mut state = RenderState {
+        mode: crate::ANIMATION_MODE,
+        shown_mode: crate::ANIMATION_MODE,
+        cold: false,
+        hot: false,
+        trend: TempTrend::Stable,
+        brightness: Interpolator::new(0.0, 100.0, animations::BOOT_FADE_MS, easing::ease_in_out_cubic),
+        ack_ms: None,
+        boop: None,
+        shutdown_ms: None,
+        sleep_fade: SleepFadeAnimation::new(),
+        morse: None,
+        binary: None,
+        binary_ms: 0,
+        lights_out: false,
+        reload_calibration: false,
+    };
+
+    let mut last_frame = timer::frame_count();
+    loop {
+        for time in 0u16..65_500 {
+            park_if_requested();
+            read_messages(&mut fifo, &mut state);
+            if core::mem::take(&mut state.reload_calibration) {
+                channels.gains = storage::load_calibration();
+            }
+            if state.shown_mode != state.mode {
+                state.shown_mode = state.mode;
+                channels.start_crossfade();
+            }
+
+            if state.lights_out {
+                // a crossfade would keep half of the old frame lit
+                channels.transition = None;
+                channels.set_all_off();
+                wait_frames(&mut last_frame, 1);
+                continue;
+            }
+
+            if power::BATTERY_PERCENT.load(Ordering::Relaxed) < crate::LOW_BATTERY_PERCENT {
+                run_low_battery(&mut channels, &mut fifo, &mut state);
+            }
+
+            channels
+                .dimmer
+                .set_global_brightness(state.brightness_percent(animations::frame_ms(state.cold)));
+            channels.dimmer.set_thermal_throttle(power::THERMAL_SCALE.load(Ordering::Relaxed));
+            state.sleep_fade.apply(&mut channels);
+            animation.trend = state.trend;
+            animation.hot = state.hot;
+            channels.advance_crossfade(animations::frame_ms(state.cold));
+            if let Some(elapsed) = state.shutdown_ms {
+                animations::shutdown::render(&animation, &mut channels, elapsed);
+                let elapsed = elapsed + animations::frame_ms(state.cold);
+                state.shutdown_ms = Some(elapsed.min(animations::shutdown::SHUTDOWN_MS));
+                wait_frames(&mut last_frame, animations::frames_per_step(state.cold));
+                continue;
+            }
+
+            if let Some(boop) = state.boop.as_mut() {
+                boop.render(&animation, &mut channels, animations::frame_ms(state.cold));
+                if boop.is_done() {
+                    state.boop = None;
+                }
+                wait_frames(&mut last_frame, animations::frames_per_step(state.cold));
+                continue;
+            }
+
+            // white heart, eyes dark so it's easy to read, then back to the animation
+            if let Some(lit) = state.morse.as_mut().and_then(|blinker| blinker.tick(animations::frame_ms(state.cold))) {
+                let duty = if lit { gamma_correct(animation.led_config.max_heart_duty) } else { 0 };
+                channels.set_left_eye(0, 0, 0);
+                channels.set_right_eye(0, 0, 0);
+                channels.set_heart(duty, duty, duty);
+                wait_frames(&mut last_frame, animations::frames_per_step(state.cold));
+                continue;
+            }
+            state.morse = None;
+
+            match state.ack_ms {
+                Some(elapsed) if elapsed < animations::ack::ACK_MS => {
+                    animations::ack::render(&animation, &mut channels, elapsed);
+                    state.ack_ms = Some(elapsed + animations::frame_ms(state.cold));
+                }
+                _ => {
+                    state.ack_ms = None;
+                    animations::advance_animation(
+                        &state.shown_mode,
+                        &mut animation,
+                        &mut channels,
+                        u32::from(time),
+                        state.cold,
+                    );
+                }
+            }
+
+            state.blink_temperature(&mut channels, gamma_correct(animation.led_config.max_heart_duty));
+
+            // cold animations take two frames per step
+            wait_frames(&mut last_frame, animations::frames_per_step(state.cold));
+        }
+    }
+}
+
+// Cold check is suspended until there's charge again, core 0 keeps measuring
+fn run_low_battery(channels: &mut PwmChannels, fifo: &mut hal::sio::SioFifo, state: &mut RenderState) {
+    channels.set_heart(0, 0, 0);
+    let mut low_battery = power::LowBatteryMode::new(
+        [
+            &mut *channels.left_r,
+            &mut *channels.left_g,
+            &mut *channels.left_b,
+            &mut *channels.right_r,
+            &mut *channels.right_g,
+            &mut *channels.right_b,
+        ],
+        &mut *channels.heart_r,
+        // straight to the pins, past the dimmer, so its limit goes in here
+        LedConfig::from_brightness_percent(channels.dimmer.effective_scale()).max_heart_duty,
+    );
+    let mut last_frame = timer::frame_count();
+    while power::BATTERY_PERCENT.load(Ordering::Relaxed) < crate::LOW_BATTERY_PERCENT && !state.lights_out {
+        low_battery.tick();
+        wait_frames(&mut last_frame, crate::LOW_BATTERY_TICK_MS / timer::FRAME_MS);
+        // keep up, so the right mode is there once the battery is back
+        read_messages(fifo, state);
+    }
+}
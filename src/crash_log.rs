@@ -0,0 +1,204 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use rp2040_hal::pac;
+use rp2040_hal::Sio;
+
+use crate::storage::{self, FlashError, PAGE_SIZE};
+use crate::timer;
+
+// "CRSH" marks a crash log nobody has read yet
+const CRASH_MAGIC: u32 = 0x4352_5348;
+
+// Watchdog scratch registers survive the reset, the main loop leaves a trail there
+const BREADCRUMB_MAGIC: u32 = 0xa1fa_cafe;
+
+// Crash page layout: magic, cause, pc, lr, sp, uptime, temperature, failed channels,
+// then a CRC-16
+const CAUSE_OFFSET: usize = 4;
+const PC_OFFSET: usize = 8;
+const LR_OFFSET: usize = 12;
+const SP_OFFSET: usize = 16;
+const UPTIME_OFFSET: usize = 20;
+const TEMPERATURE_OFFSET: usize = 24;
+const FAILED_CHANNELS_OFFSET: usize = 26;
+const CRC_OFFSET: usize = 28;
+
+// Last whole celsius the main loop saw, for the panic handler
+static LAST_TEMPERATURE: AtomicU16 = AtomicU16::new(0);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CrashCause {
+    Panic,
+    // registers are all zero for these, the watchdog doesn't tell where we were
+    Watchdog,
+    // LED channels that failed the boot self test, registers are zero here too
+    SelfTest,
+}
+
+#[derive(Clone, Copy)]
+pub struct CrashLog {
+    pub cause: CrashCause,
+    pub pc: u32,
+    pub lr: u32,
+    pub sp: u32,
+    pub uptime_ms: u32,
+    pub temperature: u16,
+    // one bit per LED channel, see self_test
+    pub failed_channels: u16,
+}
+
+impl fmt::Display for CrashLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cause = match self.cause {
+            CrashCause::Panic => "panic",
+            CrashCause::Watchdog => "watchdog",
+            CrashCause::SelfTest => {
+                return write!(
+                    f,
+                    "self test at {} ms, failed channels {:#011b}",
+                    self.uptime_ms, self.failed_channels
+                );
+            }
+        };
+        write!(
+            f,
+            "{cause} at {} ms, pc {:#010x}, lr {:#010x}, sp {:#010x}, temperature {} C",
+            self.uptime_ms, self.pc, self.lr, self.sp, self.temperature
+        )
+    }
+}
+
+const fn read_u32(page: &[u8; PAGE_SIZE], offset: usize) -> u32 {
+    u32::from_le_bytes([page[offset], page[offset + 1], page[offset + 2], page[offset + 3]])
+}
+
+// None when the last reset was a clean one, or the log has been read already
+pub fn load() -> Option<CrashLog> {
+    let mut page = [0u8; PAGE_SIZE];
+    storage::read(storage::CRASH_LOG_SECTOR_OFFSET, &mut page);
+
+    let crc = u16::from_le_bytes([page[CRC_OFFSET], page[CRC_OFFSET + 1]]);
+    if read_u32(&page, 0) != CRASH_MAGIC || crc != storage::crc16(&page[..CRC_OFFSET]) {
+        return None;
+    }
+
+    Some(CrashLog {
+        cause: match page[CAUSE_OFFSET] {
+            0 => CrashCause::Panic,
+            1 => CrashCause::Watchdog,
+            _ => CrashCause::SelfTest,
+        },
+        pc: read_u32(&page, PC_OFFSET),
+        lr: read_u32(&page, LR_OFFSET),
+        sp: read_u32(&page, SP_OFFSET),
+        uptime_ms: read_u32(&page, UPTIME_OFFSET),
+        temperature: u16::from_le_bytes([page[TEMPERATURE_OFFSET], page[TEMPERATURE_OFFSET + 1]]),
+        failed_channels: u16::from_le_bytes([page[FAILED_CHANNELS_OFFSET], page[FAILED_CHANNELS_OFFSET + 1]]),
+    })
+}
+
+pub fn save(log: &CrashLog) -> Result<(), FlashError> {
+    storage::erase_and_program(storage::CRASH_LOG_SECTOR_OFFSET, &encode(log))
+}
+
+fn encode(log: &CrashLog) -> [u8; PAGE_SIZE] {
+    let mut page = [0xffu8; PAGE_SIZE];
+    page[..4].copy_from_slice(&CRASH_MAGIC.to_le_bytes());
+    page[CAUSE_OFFSET] = match log.cause {
+        CrashCause::Panic => 0,
+        CrashCause::Watchdog => 1,
+        CrashCause::SelfTest => 2,
+    };
+    page[PC_OFFSET..PC_OFFSET + 4].copy_from_slice(&log.pc.to_le_bytes());
+    page[LR_OFFSET..LR_OFFSET + 4].copy_from_slice(&log.lr.to_le_bytes());
+    page[SP_OFFSET..SP_OFFSET + 4].copy_from_slice(&log.sp.to_le_bytes());
+    page[UPTIME_OFFSET..UPTIME_OFFSET + 4].copy_from_slice(&log.uptime_ms.to_le_bytes());
+    page[TEMPERATURE_OFFSET..TEMPERATURE_OFFSET + 2].copy_from_slice(&log.temperature.to_le_bytes());
+    page[FAILED_CHANNELS_OFFSET..FAILED_CHANNELS_OFFSET + 2].copy_from_slice(&log.failed_channels.to_le_bytes());
+    let crc = storage::crc16(&page[..CRC_OFFSET]);
+    page[CRC_OFFSET..CRC_OFFSET + 2].copy_from_slice(&crc.to_le_bytes());
+    page
+}
+
+// Once it has been read out, so the same crash isn't reported on every boot
+pub fn clear() -> Result<(), FlashError> {
+    storage::erase_and_program(storage::CRASH_LOG_SECTOR_OFFSET, &[0xff; PAGE_SIZE])
+}
+
+// A failed self test goes in the same page, it's just as much worth telling someone about
+pub fn record_self_test(failed_channels: u16) -> CrashLog {
+    let log = CrashLog {
+        cause: CrashCause::SelfTest,
+        pc: 0,
+        lr: 0,
+        sp: 0,
+        uptime_ms: timer::uptime_ms(),
+        temperature: LAST_TEMPERATURE.load(Ordering::Relaxed),
+        failed_channels,
+    };
+    save(&log).ok();
+    log
+}
+
+// Called every frame from core 0, the watchdog can bite any time
+pub fn breadcrumb(uptime_ms: u32) {
+    // SAFETY: scratch registers are ours, nothing else in the firmware uses 0..2
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    watchdog.scratch0.write(|w| unsafe { w.bits(BREADCRUMB_MAGIC) });
+    watchdog.scratch1.write(|w| unsafe { w.bits(uptime_ms) });
+    watchdog
+        .scratch2
+        .write(|w| unsafe { w.bits(u32::from(LAST_TEMPERATURE.load(Ordering::Relaxed))) });
+}
+
+pub fn note_temperature(celsius: u16) {
+    LAST_TEMPERATURE.store(celsius, Ordering::Relaxed);
+}
+
+// Early in boot: if the watchdog reset us, turn the trail it left into a crash log.
+// Stores the log in flash, so it doesn't matter how long it takes until someone reads it.
+#[allow(clippy::cast_possible_truncation)]
+pub fn record_watchdog_reset() {
+    // SAFETY: read only, plus our own scratch registers
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    let timed_out = watchdog.reason.read().timer().bit_is_set();
+    let has_trail = watchdog.scratch0.read().bits() == BREADCRUMB_MAGIC;
+    // a second watchdog reset before the main loop starts shouldn't log again
+    watchdog.scratch0.write(|w| unsafe { w.bits(0) });
+
+    if timed_out && has_trail {
+        save(&CrashLog {
+            cause: CrashCause::Watchdog,
+            pc: 0,
+            lr: 0,
+            sp: 0,
+            uptime_ms: watchdog.scratch1.read().bits(),
+            temperature: watchdog.scratch2.read().bits() as u16,
+            failed_channels: 0,
+        })
+        .ok();
+    }
+}
+
+// From the panic handler, inlined so pc and lr point at the handler and its caller.
+// Only core 0 can get the other core out of flash, core 1 panics aren't stored. Core 1
+// has to be forced off already, it can't be asked to park: it might be stuck waiting for
+// the critical section the panicking code held. That's also why nothing here takes one.
+#[allow(clippy::inline_always)]
+#[inline(always)]
+pub fn record_panic() {
+    let log = CrashLog {
+        cause: CrashCause::Panic,
+        pc: cortex_m::register::pc::read(),
+        lr: cortex_m::register::lr::read(),
+        sp: cortex_m::register::msp::read(),
+        uptime_ms: timer::uptime_ms_unlocked(),
+        temperature: LAST_TEMPERATURE.load(Ordering::Relaxed),
+        failed_channels: 0,
+    };
+
+    if Sio::core() == 0 {
+        storage::erase_and_program_core1_off(storage::CRASH_LOG_SECTOR_OFFSET, &encode(&log)).ok();
+    }
+}
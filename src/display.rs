@@ -0,0 +1,185 @@
+use core::fmt::{self, Write as _};
+
+use embedded_graphics::mono_font::ascii::FONT_6X9;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{PrimitiveStyle, Rectangle};
+use embedded_graphics::text::{Baseline, Text};
+use embedded_hal::blocking::i2c::Write;
+use ssd1306::mode::BufferedGraphicsMode;
+use ssd1306::prelude::*;
+use ssd1306::{I2CDisplayInterface, Ssd1306};
+
+use crate::animations::AnimationMode;
+use crate::qr::{self, QrBitmap};
+use crate::storage::OwnerName;
+
+// 128 / 6 px, what fits on a line
+const LINE_LEN: usize = 21;
+
+// Three rows of 6x9, the closest embedded-graphics has to 6x8, with a bit of room between
+const NAME_Y: i32 = 0;
+const MODE_Y: i32 = 11;
+const STATUS_Y: i32 = 22;
+
+// QR code sits on a lit square at the left, modules dark like on paper, the name goes next to it
+const QR_BOX: u32 = 32;
+const QR_NAME_X: i32 = 36;
+
+#[derive(Debug)]
+pub enum Error {
+    // NAK, no display or anything else on the bus, the details aren't interesting
+    I2c,
+}
+
+// Text for one row, longer is cut
+struct Line {
+    bytes: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; LINE_LEN],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // only whole ASCII strs get in, see write_str
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl fmt::Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes().filter(u8::is_ascii) {
+            if self.len == LINE_LEN {
+                break;
+            }
+            self.bytes[self.len] = byte;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+type Display<I2C> = Ssd1306<I2CInterface<I2C>, DisplaySize128x32, BufferedGraphicsMode<DisplaySize128x32>>;
+
+// SSD1306 128x32 at 0x3C. show_* only remember what to show, flush() draws it all,
+// about once a second is plenty and keeps the bus free for the sensors.
+pub struct BadgeDisplay<I2C> {
+    display: Display<I2C>,
+    name: OwnerName,
+    mode: &'static str,
+    celsius: Option<f32>,
+    battery_percent: Option<u8>,
+    qr: Option<QrBitmap>,
+    // QR code instead of the status lines
+    showing_qr: bool,
+}
+
+impl<I2C: Write> BadgeDisplay<I2C> {
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            display: Ssd1306::new(I2CDisplayInterface::new(i2c), DisplaySize128x32, DisplayRotation::Rotate0)
+                .into_buffered_graphics_mode(),
+            name: OwnerName::empty(),
+            mode: "",
+            celsius: None,
+            battery_percent: None,
+            qr: None,
+            showing_qr: false,
+        }
+    }
+
+    // Fails when nothing answers, there's no display then
+    pub fn init(&mut self) -> Result<(), Error> {
+        self.display.init().map_err(|_| Error::I2c)
+    }
+
+    pub const fn show_temperature(&mut self, celsius: f32) {
+        self.celsius = Some(celsius);
+    }
+
+    pub const fn show_animation_mode(&mut self, mode: &AnimationMode) {
+        self.mode = mode.name();
+    }
+
+    pub const fn show_battery_percent(&mut self, pct: u8) {
+        self.battery_percent = Some(pct);
+    }
+
+    pub fn show_owner_name(&mut self, name: &str) {
+        self.name = OwnerName::new(name);
+    }
+
+    // Encodes `url` and shows it from now on, the old code stays if it doesn't fit
+    pub fn show_qr(&mut self, url: &str) -> Result<(), qr::Error> {
+        self.qr = Some(qr::encode(url)?);
+        self.showing_qr = true;
+        Ok(())
+    }
+
+    // Between the QR code and the status lines, false if there's no code to show
+    pub const fn toggle_qr(&mut self) -> bool {
+        if self.qr.is_none() {
+            return false;
+        }
+        self.showing_qr = !self.showing_qr;
+        true
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.display.clear_buffer();
+        match self.qr {
+            Some(code) if self.showing_qr => self.draw_qr(&code),
+            _ => self.draw_status(),
+        }
+        self.display.flush().map_err(|_| Error::I2c)
+    }
+
+    // drawing into the buffer can't fail, the results are ignored
+    fn draw_qr(&mut self, code: &QrBitmap) {
+        Rectangle::new(Point::zero(), Size::new(QR_BOX, QR_BOX))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(&mut self.display)
+            .ok();
+        let offset = (QR_BOX - u32::from(code.size())) / 2;
+        for y in 0..code.size() {
+            for x in 0..code.size() {
+                if code.is_dark(x, y) {
+                    self.display.set_pixel(offset + u32::from(x), offset + u32::from(y), false);
+                }
+            }
+        }
+        let style = MonoTextStyle::new(&FONT_6X9, BinaryColor::On);
+        Text::with_baseline(self.name.as_str(), Point::new(QR_NAME_X, NAME_Y), style, Baseline::Top)
+            .draw(&mut self.display)
+            .ok();
+    }
+
+    fn draw_status(&mut self) {
+        let style = MonoTextStyle::new(&FONT_6X9, BinaryColor::On);
+
+        let mut name = Line::new();
+        name.write_str(self.name.as_str()).ok();
+        let mut mode = Line::new();
+        write!(mode, "mode: {}", self.mode).ok();
+        let mut status = Line::new();
+        if let Some(celsius) = self.celsius {
+            write!(status, "{celsius:.1} C  ").ok();
+        }
+        if let Some(pct) = self.battery_percent {
+            write!(status, "bat {pct}%").ok();
+        }
+
+        for (line, y) in [(&name, NAME_Y), (&mode, MODE_Y), (&status, STATUS_Y)] {
+            Text::with_baseline(line.as_str(), Point::new(0, y), style, Baseline::Top)
+                .draw(&mut self.display)
+                .ok();
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// Ring buffer moving average over the last N ADC samples
+pub struct TemperatureFilter<const N: usize> {
+    samples: [u16; N],
+    next: usize,
+    count: usize,
+}
+
+impl<const N: usize> TemperatureFilter<N> {
+    pub const fn new() -> Self {
+        Self {
+            samples: [0; N],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    pub const fn push(&mut self, sample: u16) {
+        self.samples[self.next] = sample;
+        self.next = (self.next + 1) % N;
+        if self.count < N {
+            self.count += 1;
+        }
+    }
+
+    // Until the buffer has filled up, average only what we have
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn average(&self) -> u16 {
+        if self.count == 0 {
+            return 0;
+        }
+        let sum: u32 = self.samples[..self.count].iter().map(|&s| u32::from(s)).sum();
+        (sum / self.count as u32) as u16
+    }
+}
+
+// Median of the last N samples, so a spike shorter than half the window never gets
+// through at all, where an average would smear it over every sample. Keeps them
+// in arrival order to know which one drops out, and sorted for the middle one.
+pub struct MedianFilter<const N: usize> {
+    history: [u16; N],
+    sorted: [u16; N],
+    next: usize,
+    count: usize,
+}
+
+impl<const N: usize> MedianFilter<N> {
+    pub const fn new() -> Self {
+        Self {
+            history: [0; N],
+            sorted: [0; N],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    // Takes the oldest sample out of the sorted ones once full, then insertion sorts
+    // the new one in, N is small enough that shifting beats anything clever
+    pub const fn push(&mut self, sample: u16) {
+        if self.count == N {
+            let oldest = self.history[self.next];
+            let mut i = 0;
+            while self.sorted[i] != oldest {
+                i += 1;
+            }
+            while i + 1 < N {
+                self.sorted[i] = self.sorted[i + 1];
+                i += 1;
+            }
+            self.count -= 1;
+        }
+        self.history[self.next] = sample;
+        self.next = (self.next + 1) % N;
+
+        let mut i = self.count;
+        while i > 0 && self.sorted[i - 1] > sample {
+            self.sorted[i] = self.sorted[i - 1];
+            i -= 1;
+        }
+        self.sorted[i] = sample;
+        self.count += 1;
+    }
+
+    // Until then median() is of fewer samples and a spike can still get through
+    pub const fn is_ready(&self) -> bool {
+        self.count == N
+    }
+
+    // Upper one of the middle two for an even count
+    pub const fn median(&self) -> u16 {
+        if self.count == 0 {
+            return 0;
+        }
+        self.sorted[self.count / 2]
+    }
+}
+
+// One sample a second, so this is the last minute
+pub const TREND_SAMPLES: usize = 60;
+
+// 2 C a minute, in millidegrees per second
+pub const TREND_THRESHOLD_MDEG_PER_S: i16 = 2000 / 60;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TempTrend {
+    Rising,
+    Falling,
+    Stable,
+}
+
+impl TempTrend {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Rising => "rising",
+            Self::Falling => "falling",
+            Self::Stable => "stable",
+        }
+    }
+
+    pub const fn from_slope(mdeg_per_s: i16) -> Self {
+        if mdeg_per_s > TREND_THRESHOLD_MDEG_PER_S {
+            Self::Rising
+        } else if mdeg_per_s < -TREND_THRESHOLD_MDEG_PER_S {
+            Self::Falling
+        } else {
+            Self::Stable
+        }
+    }
+}
+
+// Least squares slope over millidegree samples one second apart, oldest first, in
+// millidegrees per second
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub fn temperature_trend(samples: &[i32]) -> i16 {
+    let n = samples.len() as i64;
+    if n < 2 {
+        return 0;
+    }
+
+    let (mut total, mut weighted) = (0i64, 0i64);
+    for (x, &y) in samples.iter().enumerate() {
+        total += i64::from(y);
+        weighted += x as i64 * i64::from(y);
+    }
+    // x runs 0..n, its sums have closed forms
+    let sum_x = n * (n - 1) / 2;
+    let sum_x_squared = (n - 1) * n * (2 * n - 1) / 6;
+    let spread = n * sum_x_squared - sum_x.pow(2);
+
+    let slope = (n * weighted - sum_x * total) / spread;
+    slope.clamp(i64::from(i16::MIN), i64::from(i16::MAX)) as i16
+}
+
+// Last TREND_SAMPLES readings in millidegrees, whole degrees would hide a slow drift
+pub struct TemperatureHistory {
+    samples: [i32; TREND_SAMPLES],
+    next: usize,
+    count: usize,
+}
+
+impl TemperatureHistory {
+    pub const fn new() -> Self {
+        Self {
+            samples: [0; TREND_SAMPLES],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn push(&mut self, celsius: f32) {
+        self.samples[self.next] = (celsius * 1000.0) as i32;
+        self.next = (self.next + 1) % TREND_SAMPLES;
+        if self.count < TREND_SAMPLES {
+            self.count += 1;
+        }
+    }
+
+    // Stable until there's a whole minute to go by
+    pub fn trend(&self) -> TempTrend {
+        if self.count < TREND_SAMPLES {
+            return TempTrend::Stable;
+        }
+        // oldest first, the ring starts at `next` once it's full
+        let mut ordered = self.samples;
+        ordered.rotate_left(self.next);
+        TempTrend::from_slope(temperature_trend(&ordered))
+    }
+}
@@ -0,0 +1,49 @@
+// Eyes see brightness logarithmically, so duty goes through a 2.2 gamma curve
+// before it hits the PWM. Table is built at compile time and lives in flash.
+
+// x^(1/5) by Newton's method, good enough for 0..=1 and works in const context
+const fn fifth_root(x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let mut r = 1.0;
+    let mut i = 0;
+    while i < 32 {
+        let r4 = r * r * r * r;
+        r -= (r4 * r - x) / (5.0 * r4);
+        i += 1;
+    }
+    r
+}
+
+// x^2.2 = x^2 * x^(1/5)
+const fn gamma_curve(x: f64) -> f64 {
+    x * x * fifth_root(x)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+const fn build_lut() -> [u16; 256] {
+    let mut lut = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let x = i as f64 / 255.0;
+        lut[i] = (gamma_curve(x) * 65535.0 + 0.5) as u16;
+        i += 1;
+    }
+    lut
+}
+
+const GAMMA_LUT: [u16; 256] = build_lut();
+
+// Linear 0-65535 in, perceptually corrected 0-65535 out. Interpolates between
+// table entries so slow fades stay smooth.
+#[allow(clippy::cast_possible_truncation)]
+pub fn gamma_correct(linear: u16) -> u16 {
+    let scaled = u32::from(linear) * 255;
+    let index = (scaled / 65535) as usize;
+    let frac = scaled % 65535;
+
+    let low = u32::from(GAMMA_LUT[index]);
+    let high = u32::from(GAMMA_LUT[(index + 1).min(255)]);
+    (low + (high - low) * frac / 65535) as u16
+}
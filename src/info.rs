@@ -0,0 +1,130 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::storage::{self, NAME_LEN, UNIQUE_ID_LEN};
+
+pub const FIRMWARE_VERSION: (u8, u8, u8) = parse_version(env!("CARGO_PKG_VERSION"));
+
+// Unix seconds, see build.rs
+pub const BUILD_TIMESTAMP: u64 = parse_u64(env!("BUILD_TIMESTAMP").as_bytes());
+
+// From the flash chip's unique id at boot, see init
+static BADGE_ID: AtomicU32 = AtomicU32::new(0);
+
+// Plain digits, anything else fails the build
+const fn parse_u64(digits: &[u8]) -> u64 {
+    assert!(!digits.is_empty(), "not a number");
+    let mut value = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        assert!(digits[i].is_ascii_digit(), "not a number");
+        value = value * 10 + (digits[i] - b'0') as u64;
+        i += 1;
+    }
+    value
+}
+
+// "major.minor.patch", pre-release and build suffixes aren't used here
+#[allow(clippy::cast_possible_truncation)]
+const fn parse_version(version: &str) -> (u8, u8, u8) {
+    let bytes = version.as_bytes();
+    let mut parts = [0u64; 3];
+    let (mut part, mut start, mut i) = (0, 0, 0);
+    while i <= bytes.len() {
+        if i == bytes.len() || bytes[i] == b'.' {
+            assert!(part < 3, "version has more than three parts");
+            let (digits, _) = bytes.split_at(i);
+            let (_, digits) = digits.split_at(start);
+            parts[part] = parse_u64(digits);
+            assert!(parts[part] <= u8::MAX as u64, "version part over 255");
+            part += 1;
+            start = i + 1;
+        }
+        i += 1;
+    }
+    assert!(part == 3, "version needs three parts");
+    (parts[0] as u8, parts[1] as u8, parts[2] as u8)
+}
+
+#[derive(Clone, Copy)]
+pub struct BadgeInfo {
+    // ASCII, zero padded
+    pub owner_name: [u8; NAME_LEN],
+    pub firmware_version: (u8, u8, u8),
+    pub build_timestamp: u64,
+    // other badges see this over IR, see ir_id
+    pub badge_id: u32,
+}
+
+impl BadgeInfo {
+    pub fn owner_name(&self) -> &str {
+        let len = self.owner_name.iter().position(|&byte| byte == 0).unwrap_or(NAME_LEN);
+        core::str::from_utf8(&self.owner_name[..len]).unwrap_or("")
+    }
+
+    // NEC only has 8 bits for it, all four bytes of the id go in
+    pub const fn ir_id(&self) -> u8 {
+        let [a, b, c, d] = self.badge_id.to_le_bytes();
+        a ^ b ^ c ^ d
+    }
+}
+
+// "2026-10-14 09:30 UTC"
+struct Utc(u64);
+
+impl fmt::Display for Utc {
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = (self.0 / 86_400) as i64;
+        let seconds = self.0 % 86_400;
+        // civil_from_days from Howard Hinnant's date algorithms
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        write!(
+            f,
+            "{year}-{month:02}-{day:02} {:02}:{:02} UTC",
+            seconds / 3600,
+            seconds % 3600 / 60
+        )
+    }
+}
+
+impl fmt::Display for BadgeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (major, minor, patch) = self.firmware_version;
+        write!(
+            f,
+            "owner: {}, firmware {major}.{minor}.{patch}, built {}, badge id {:#010x}",
+            self.owner_name(),
+            Utc(self.build_timestamp),
+            self.badge_id
+        )
+    }
+}
+
+// Once at boot, reading the id takes flash out from under both cores for a moment
+pub fn init() {
+    let id: [u8; UNIQUE_ID_LEN] = storage::read_unique_id();
+    let [low, high] = [0, 4].map(|start| u32::from_le_bytes([id[start], id[start + 1], id[start + 2], id[start + 3]]));
+    BADGE_ID.store(low ^ high, Ordering::Relaxed);
+}
+
+// A copy, not a reference: the owner's name can change under it with set_name
+pub fn get_badge_info() -> BadgeInfo {
+    let owner = storage::load_owner_name();
+    let mut owner_name = [0; NAME_LEN];
+    owner_name[..owner.as_str().len()].copy_from_slice(owner.as_str().as_bytes());
+    BadgeInfo {
+        owner_name,
+        firmware_version: FIRMWARE_VERSION,
+        build_timestamp: BUILD_TIMESTAMP,
+        badge_id: BADGE_ID.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,452 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use rp2040_hal::gpio::Interrupt;
+use rp2040_hal::pac;
+
+use crate::bsp;
+
+// Holding the button longer than this is a long press
+pub const LONG_PRESS_MS: u32 = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    // let go before LONG_PRESS_MS
+    ShortPress,
+    // held for LONG_PRESS_MS, fires once per press
+    LongPress(u32),
+    // still down, for this many ms
+    Held(u32),
+    // let go after a long press
+    Released,
+    None,
+}
+
+// Button has to read the same for 8 ticks in a row before we believe it
+pub struct Debouncer {
+    history: u8,
+    pressed: bool,
+    press_duration: u32,
+}
+
+impl Debouncer {
+    pub const fn new() -> Self {
+        Self {
+            history: 0,
+            pressed: false,
+            press_duration: 0,
+        }
+    }
+
+    // `raw` is true while the button reads pressed, `delta_ms` is time since last update
+    pub const fn update(&mut self, raw: bool, delta_ms: u32) -> ButtonEvent {
+        self.history = (self.history << 1) | raw as u8;
+
+        match (self.pressed, self.history) {
+            (false, 0xff) => {
+                self.pressed = true;
+                self.press_duration = 0;
+                ButtonEvent::Held(0)
+            }
+            (true, 0x00) => {
+                self.pressed = false;
+                if self.press_duration < LONG_PRESS_MS {
+                    ButtonEvent::ShortPress
+                } else {
+                    ButtonEvent::Released
+                }
+            }
+            (true, _) => {
+                let was_long = self.press_duration >= LONG_PRESS_MS;
+                self.press_duration = self.press_duration.saturating_add(delta_ms);
+                if !was_long && self.press_duration >= LONG_PRESS_MS {
+                    ButtonEvent::LongPress(self.press_duration)
+                } else {
+                    ButtonEvent::Held(self.press_duration)
+                }
+            }
+            (false, _) => ButtonEvent::None,
+        }
+    }
+}
+
+// The button on an interrupt instead, so a slow main loop doesn't miss a short press.
+// thumbv6m has no atomic read-modify-write, so only the interrupt (or the main loop with
+// interrupts off) writes these and the main loop remembers how many presses it has taken
+// already.
+const BUTTON_GPIO: u32 = <bsp::Button as bsp::GpioNum>::GPIO as u32;
+const BUTTON_EDGES: u32 = 0b1100 << ((BUTTON_GPIO % 8) * 4); // edge low and edge high
+
+// Contacts bounce for a few ms, edges this close to the last one we believed are ignored
+const BUTTON_DEBOUNCE_US: u32 = 10_000;
+
+// Short presses so far, wraps around
+pub static BUTTON_PRESS_COUNT: AtomicU32 = AtomicU32::new(0);
+// When the current press started, in timer us, 0 while the button is up
+static BUTTON_DOWN_AT_US: AtomicU32 = AtomicU32::new(0);
+static BUTTON_LAST_EDGE_US: AtomicU32 = AtomicU32::new(0);
+// An edge the debounce ignored, 0 if none. The level is looked at again once the window
+// is over, otherwise a press shorter than the window never comes up again.
+static BUTTON_PENDING_US: AtomicU32 = AtomicU32::new(0);
+
+// Edge interrupts on both edges, old latched ones cleared. IO_IRQ_BANK0 itself is
+// shared with the IR receiver, see on_button_edge().
+pub fn init_button_interrupt(pin: &bsp::Button, io: &pac::io_bank0::RegisterBlock) {
+    // SAFETY: write one to clear, only the button's edge bits are set
+    io.intr[(BUTTON_GPIO / 8) as usize].write(|w| unsafe { w.bits(BUTTON_EDGES) });
+    pin.set_interrupt_enabled(Interrupt::EdgeLow, true);
+    pin.set_interrupt_enabled(Interrupt::EdgeHigh, true);
+
+    // SAFETY: only this interrupt's priority is touched. Zero is the highest (and the
+    // default), so nothing else can hold the press back. Edges are latched anyway, one
+    // that comes in during a critical section is only timed a bit late.
+    unsafe {
+        let mut core = cortex_m::Peripherals::steal();
+        core.NVIC.set_priority(pac::Interrupt::IO_IRQ_BANK0, 0);
+        pac::NVIC::unmask(pac::Interrupt::IO_IRQ_BANK0);
+    }
+}
+
+fn button_pressed() -> bool {
+    // SAFETY: the input level is read only
+    let sio = unsafe { &*pac::SIO::ptr() };
+    // pulls low when pressed
+    sio.gpio_in.read().bits() & (1 << BUTTON_GPIO) == 0
+}
+
+fn clear_latched_edges() {
+    // SAFETY: write one to clear, only the button's edge bits are set
+    let io = unsafe { &*pac::IO_BANK0::ptr() };
+    io.intr[(BUTTON_GPIO / 8) as usize].write(|w| unsafe { w.bits(BUTTON_EDGES) });
+}
+
+// The button is `pressed` as of `time_us`
+fn apply_level(pressed: bool, time_us: u32) {
+    let down_at = BUTTON_DOWN_AT_US.load(Ordering::Relaxed);
+    match (pressed, down_at) {
+        // 0 means up, so a press that starts right at 0 us is a microsecond late
+        (true, 0) => BUTTON_DOWN_AT_US.store(time_us.max(1), Ordering::Relaxed),
+        (false, down_at) if down_at != 0 => {
+            BUTTON_DOWN_AT_US.store(0, Ordering::Relaxed);
+            if time_us.wrapping_sub(down_at) < LONG_PRESS_MS * 1000 {
+                let count = BUTTON_PRESS_COUNT.load(Ordering::Relaxed);
+                BUTTON_PRESS_COUNT.store(count.wrapping_add(1), Ordering::Relaxed);
+            }
+        }
+        // a bounce we didn't catch, nothing changed. Still starts a window, so the
+        // bounces after it don't get read as a new press.
+        _ => {}
+    }
+    BUTTON_LAST_EDGE_US.store(time_us, Ordering::Relaxed);
+}
+
+// Called from IO_IRQ_BANK0 with the interrupt's timestamp
+pub fn on_button_edge(time_us: u32) {
+    // SAFETY: the latched edges are only cleared in here, init_button_interrupt and
+    // clear_button_edges
+    let io = unsafe { &*pac::IO_BANK0::ptr() };
+    if io.intr[(BUTTON_GPIO / 8) as usize].read().bits() & BUTTON_EDGES == 0 {
+        return;
+    }
+    clear_latched_edges();
+
+    if time_us.wrapping_sub(BUTTON_LAST_EDGE_US.load(Ordering::Relaxed)) < BUTTON_DEBOUNCE_US {
+        BUTTON_PENDING_US.store(time_us.max(1), Ordering::Relaxed);
+        return;
+    }
+    BUTTON_PENDING_US.store(0, Ordering::Relaxed);
+    apply_level(button_pressed(), time_us);
+}
+
+// The last edge fell into the debounce window and none came after it was over, so
+// nothing told us where the button settled. Look now.
+fn settle_button(now_us: u32) {
+    critical_section::with(|_| {
+        let pending = BUTTON_PENDING_US.load(Ordering::Relaxed);
+        let last_edge = BUTTON_LAST_EDGE_US.load(Ordering::Relaxed);
+        if pending == 0 || now_us.wrapping_sub(last_edge) < BUTTON_DEBOUNCE_US {
+            return;
+        }
+        BUTTON_PENDING_US.store(0, Ordering::Relaxed);
+        apply_level(button_pressed(), pending);
+    });
+}
+
+// Forgets the press that woke us from dormant, with interrupts off so the interrupt
+// never sees its edge. The release that follows finds the button already up and isn't
+// counted.
+pub fn clear_button_edges() {
+    clear_latched_edges();
+    // SAFETY: only reading the free running counter
+    let now_us = unsafe { (*pac::TIMER::ptr()).timerawl.read().bits() };
+    BUTTON_DOWN_AT_US.store(0, Ordering::Relaxed);
+    BUTTON_PENDING_US.store(0, Ordering::Relaxed);
+    BUTTON_LAST_EDGE_US.store(now_us, Ordering::Relaxed);
+}
+
+// Same events as Debouncer, out of what the interrupt saw
+pub struct ButtonPresses {
+    taken: u32,
+    long_press_fired: bool,
+}
+
+impl ButtonPresses {
+    pub fn new() -> Self {
+        Self {
+            taken: BUTTON_PRESS_COUNT.load(Ordering::Relaxed),
+            long_press_fired: false,
+        }
+    }
+
+    // One short press per call, more than one queued come out on the next calls
+    pub fn update(&mut self) -> ButtonEvent {
+        // SAFETY: only reading the free running counter
+        let now_us = unsafe { (*pac::TIMER::ptr()).timerawl.read().bits() };
+        settle_button(now_us);
+        if BUTTON_PRESS_COUNT.load(Ordering::Relaxed).wrapping_sub(self.taken) > 0 {
+            self.taken = self.taken.wrapping_add(1);
+            return ButtonEvent::ShortPress;
+        }
+        let down_at = BUTTON_DOWN_AT_US.load(Ordering::Relaxed);
+        if down_at == 0 {
+            return if core::mem::take(&mut self.long_press_fired) {
+                ButtonEvent::Released
+            } else {
+                ButtonEvent::None
+            };
+        }
+        let held_ms = now_us.wrapping_sub(down_at) / 1000;
+        if held_ms >= LONG_PRESS_MS && !self.long_press_fired {
+            self.long_press_fired = true;
+            ButtonEvent::LongPress(held_ms)
+        } else {
+            ButtonEvent::Held(held_ms)
+        }
+    }
+}
+
+// RMS of acceleration over the window above this is a shake. Includes gravity, so
+// a badge lying still reads about 1000 mg.
+#[cfg(feature = "accel")]
+pub const SHAKE_THRESHOLD_MG: u32 = 1800;
+
+#[cfg(feature = "accel")]
+pub const SHAKE_WINDOW_MS: u32 = 200;
+
+// Shaking tends to go on for a while, one event per this is plenty
+#[cfg(feature = "accel")]
+pub const SHAKE_COOLDOWN_MS: u32 = 1000;
+
+#[cfg(feature = "accel")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ShakeEvent {
+    pub rms_mg: u32,
+}
+
+#[cfg(feature = "accel")]
+pub struct ShakeDetector {
+    threshold_mg: u32,
+    sum_of_squares: u64,
+    samples: u32,
+    window_ms: u32,
+    cooldown_ms: u32,
+}
+
+#[cfg(feature = "accel")]
+impl ShakeDetector {
+    pub const fn new(threshold_mg: u32) -> Self {
+        Self {
+            threshold_mg,
+            sum_of_squares: 0,
+            samples: 0,
+            window_ms: 0,
+            cooldown_ms: 0,
+        }
+    }
+
+    // One accelerometer sample in mg, `delta_ms` since the previous one
+    pub fn update(&mut self, (x, y, z): (i16, i16, i16), delta_ms: u32) -> Option<ShakeEvent> {
+        let square = |v: i16| u64::from(v.unsigned_abs()).pow(2);
+        self.sum_of_squares += square(x) + square(y) + square(z);
+        self.samples += 1;
+        self.window_ms += delta_ms;
+        self.cooldown_ms = self.cooldown_ms.saturating_sub(delta_ms);
+
+        if self.window_ms < SHAKE_WINDOW_MS {
+            return None;
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        let rms_mg = (self.sum_of_squares / u64::from(self.samples)).isqrt() as u32;
+        self.sum_of_squares = 0;
+        self.samples = 0;
+        self.window_ms = 0;
+
+        if rms_mg > self.threshold_mg && self.cooldown_ms == 0 {
+            self.cooldown_ms = SHAKE_COOLDOWN_MS;
+            Some(ShakeEvent { rms_mg })
+        } else {
+            None
+        }
+    }
+}
+
+// Axis has to carry at least this much of gravity to count as pointing down
+#[cfg(feature = "accel")]
+pub const ORIENTATION_THRESHOLD_MG: i16 = 700;
+
+// Same orientation this many samples in a row before we believe it
+#[cfg(feature = "accel")]
+pub const ORIENTATION_DEBOUNCE_SAMPLES: u8 = 10;
+
+#[cfg(feature = "accel")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    FaceUp,
+    FaceDown,
+    TiltedLeft,
+    TiltedRight,
+}
+
+#[cfg(feature = "accel")]
+impl Orientation {
+    // None while the badge is somewhere in between
+    pub const fn from_xyz((x, _y, z): (i16, i16, i16)) -> Option<Self> {
+        if z > ORIENTATION_THRESHOLD_MG {
+            Some(Self::FaceUp)
+        } else if z < -ORIENTATION_THRESHOLD_MG {
+            Some(Self::FaceDown)
+        } else if x < -ORIENTATION_THRESHOLD_MG {
+            Some(Self::TiltedLeft)
+        } else if x > ORIENTATION_THRESHOLD_MG {
+            Some(Self::TiltedRight)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "accel")]
+pub struct OrientationDetector {
+    current: Option<Orientation>,
+    candidate: Option<Orientation>,
+    count: u8,
+}
+
+#[cfg(feature = "accel")]
+impl OrientationDetector {
+    pub const fn new() -> Self {
+        Self {
+            current: None,
+            candidate: None,
+            count: 0,
+        }
+    }
+
+    // Some only when the badge has settled into a new orientation
+    pub fn update(&mut self, sample: (i16, i16, i16)) -> Option<Orientation> {
+        let orientation = Orientation::from_xyz(sample);
+        if orientation != self.candidate {
+            self.candidate = orientation;
+            self.count = 0;
+        }
+        self.count = self.count.saturating_add(1);
+
+        if self.count >= ORIENTATION_DEBOUNCE_SAMPLES && self.candidate.is_some() && self.candidate != self.current {
+            self.current = self.candidate;
+            return self.current;
+        }
+        None
+    }
+}
+
+// One detent each, see pio::encoder
+#[cfg(feature = "encoder")]
+pub struct RotaryEncoder {
+    pio: crate::pio::encoder::EncoderPio,
+    // what the PIO said last, it counts from 0 at boot and wraps
+    last: u32,
+    // clockwise is up
+    pub position: i32,
+}
+
+#[cfg(feature = "encoder")]
+impl RotaryEncoder {
+    pub const fn new(pio: crate::pio::encoder::EncoderPio) -> Self {
+        Self {
+            pio,
+            last: 0,
+            position: 0,
+        }
+    }
+
+    // Detents turned since last time, once a frame is plenty
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn update(&mut self) -> i32 {
+        let Some(raw) = self.pio.read() else {
+            return 0;
+        };
+        let delta = raw.wrapping_sub(self.last) as i32;
+        self.last = raw;
+        self.position = self.position.wrapping_add(delta);
+        delta
+    }
+}
+
+// Charge times averaged for the untouched baseline
+pub const TOUCH_BASELINE_SAMPLES: u32 = 16;
+
+// Give up on the baseline after this long, a pad without its resistor never charges
+pub const TOUCH_BASELINE_TIMEOUT_MS: u32 = 50;
+
+// Alpaca nose pad, see pio::touch. Whatever it reads at boot counts as untouched, so
+// nobody should be holding the nose while the badge starts.
+pub struct TouchSensor {
+    pad: crate::pio::touch::TouchPad,
+    // None when the pad didn't give enough samples at boot, it never reads touched then
+    baseline: Option<u32>,
+    count: u32,
+    // clk_sys divider the last counts were taken at, see power::set_sys_clock_slow
+    divider: u32,
+}
+
+impl TouchSensor {
+    pub fn new(mut pad: crate::pio::touch::TouchPad) -> Self {
+        let started_ms = crate::timer::uptime_ms();
+        let (mut sum, mut samples) = (0u64, 0);
+        while samples < TOUCH_BASELINE_SAMPLES
+            && crate::timer::uptime_ms().wrapping_sub(started_ms) < TOUCH_BASELINE_TIMEOUT_MS
+        {
+            if let Some(count) = pad.read() {
+                sum += u64::from(count);
+                samples += 1;
+            }
+        }
+        let baseline = (samples == TOUCH_BASELINE_SAMPLES)
+            .then(|| sum / u64::from(samples))
+            .and_then(|average| u32::try_from(average).ok());
+        Self {
+            pad,
+            baseline,
+            count: baseline.unwrap_or(0),
+            divider: crate::power::sys_clock_divider(),
+        }
+    }
+
+    pub const fn baseline(&self) -> Option<u32> {
+        self.baseline
+    }
+
+    // A finger takes the charge time over 1.2 times the baseline
+    pub fn is_touched(&mut self) -> bool {
+        // the PIO counts clk_sys cycles at whatever speed it runs, the baseline is from full
+        // speed. What's still queued from before a change would be off by the whole divider.
+        let divider = crate::power::sys_clock_divider();
+        if let Some(count) = self.pad.read() {
+            if divider == self.divider {
+                self.count = count * divider;
+            }
+            self.divider = divider;
+        }
+        self.baseline.is_some_and(|baseline| u64::from(self.count) * 5 > u64::from(baseline) * 6)
+    }
+}
@@ -0,0 +1,37 @@
+// Brightness limits for the LEDs. Lower these to save power.
+#[derive(Clone, Copy)]
+pub struct LedConfig {
+    pub max_eye_duty: u16,
+    pub max_heart_duty: u16,
+}
+
+impl LedConfig {
+    // u16 can't go over u16::MAX, zero would just mean dark LEDs so refuse it.
+    // Used in const context the asserts fail the build.
+    pub const fn new(max_eye_duty: u16, max_heart_duty: u16) -> Self {
+        assert!(max_eye_duty > 0, "max_eye_duty can't be zero");
+        assert!(max_heart_duty > 0, "max_heart_duty can't be zero");
+        Self {
+            max_eye_duty,
+            max_heart_duty,
+        }
+    }
+}
+
+impl LedConfig {
+    // Both limits at `percent` of full, never all the way to zero
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn from_brightness_percent(percent: u8) -> Self {
+        let duty = (u32::from(u16::MAX) * u32::from(percent.min(100)) / 100).max(1) as u16;
+        Self::new(duty, duty)
+    }
+}
+
+// Compile time fallback, stored in flash
+pub const DEFAULT_LED_CONFIG: LedConfig = LedConfig::new(u16::MAX, u16::MAX);
+
+// Long press on the button drops to this to save power
+pub const LOW_POWER_BRIGHTNESS_PERCENT: u8 = 25;
+
+// Second heart color glows at this share of max
+pub const HEART_GLOW_DIVISOR: u16 = 16;
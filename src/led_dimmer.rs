@@ -0,0 +1,52 @@
+use crate::gamma::gamma_correct;
+
+// The one place brightness limits are applied, every duty PwmChannels writes goes
+// through here. Animations always draw at full brightness and never see it.
+//
+// Percent is perceived brightness, the same thing set_brightness has always meant, so
+// the factor goes through the gamma curve: with x^2.2 scaling after the curve looks
+// exactly like scaling the duty before it did.
+#[derive(Clone, Copy)]
+pub struct LedDimmer {
+    global_brightness: u8,
+    thermal_throttle: u8,
+    // gamma corrected effective_scale(), 0..=u16::MAX
+    factor: u16,
+}
+
+impl LedDimmer {
+    pub const fn new() -> Self {
+        Self {
+            global_brightness: 100,
+            thermal_throttle: 100,
+            factor: u16::MAX,
+        }
+    }
+
+    pub fn set_global_brightness(&mut self, pct: u8) {
+        self.global_brightness = pct.min(100);
+        self.update_factor();
+    }
+
+    // See power::thermal_throttle
+    pub fn set_thermal_throttle(&mut self, pct: u8) {
+        self.thermal_throttle = pct.min(100);
+        self.update_factor();
+    }
+
+    // Whichever limit is lower wins
+    pub fn effective_scale(self) -> u8 {
+        self.global_brightness.min(self.thermal_throttle)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn update_factor(&mut self) {
+        let linear = (u32::from(u16::MAX) * u32::from(self.effective_scale()) / 100) as u16;
+        self.factor = gamma_correct(linear);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn apply(self, duty: u16) -> u16 {
+        (duty as u32 * self.factor as u32 / u16::MAX as u32) as u16
+    }
+}
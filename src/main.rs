@@ -2,23 +2,90 @@
 #![no_main]
 #![warn(clippy::all, clippy::pedantic, clippy::nursery)]
 
-use panic_halt as _;
-
+mod adc_dma;
+mod adc_utils;
+mod animations;
+mod audio;
 mod bsp;
+mod calibration;
+mod color;
+mod core1;
+mod crash_log;
+#[cfg(feature = "oled")]
+mod display;
+mod filter;
+mod gamma;
+mod info;
+mod input;
+mod led_config;
+mod led_dimmer;
+mod morse;
+mod noise;
+mod panic_led;
+mod pio;
+mod power;
+#[cfg(feature = "oled")]
+mod qr;
+mod rng;
+mod self_test;
+mod sensors;
+mod storage;
+mod timer;
+#[cfg(feature = "uart-log")]
+mod uart_log;
+mod usb_cmd;
+mod usb_hid;
+mod usb_log;
 use bsp::prelude::*;
 
+use core::cell::RefCell;
+use core::fmt::Write; // for writeln!(logger)
+use core::sync::atomic::Ordering;
+
 // Traits
+use embedded_hal::digital::v2::InputPin; // for button.is_low()
 use embedded_hal::digital::v2::OutputPin; // for pin.toggle()
-use embedded_hal::PwmPin;
+use embedded_hal::PwmPin; // for set_duty() during setup
+use embedded_hal::watchdog::{Watchdog as _, WatchdogEnable as _}; // for watchdog.feed()
+use fugit::ExtU32; // for WATCHDOG_TIMEOUT_US.micros()
 use hal::clocks::Clock; // for system_clock.freq()
+use hal::pio::PIOExt; // for PIO0.split()
 
-use palette::{IntoColor, Srgb, Hsv};
-use embedded_hal::adc::OneShot;
-use rp2040_hal::{adc::Adc, pac};
+use animations::heart::HeartMode;
+use animations::AnimationMode;
+use color::Srgb;
+use rp2040_hal::adc::Adc;
 
-fn convert_to_celsius(raw_temp: u16) -> u16 {
+#[derive(Clone, Copy, PartialEq)]
+enum TempSensorError {
+    // outside what the sensor can physically read, see adc_utils::check_temperature
+    InvalidRaw(u16),
+    // VSYS too low for the regulator, the ADC reference isn't 3.3 V any more
+    Unregulated(f32),
+}
+
+// convert_to_celsius_f32(), but anything that would come out as nonsense is an error instead
+fn convert_to_celsius_checked(raw_temp: u16) -> Result<f32, TempSensorError> {
+    adc_utils::check_temperature(raw_temp).map_err(|_| TempSensorError::InvalidRaw(raw_temp))?;
+    Ok(convert_to_celsius_f32(raw_temp))
+}
+
+// raw_temp is oversampled, see adc_utils::oversample_temperature. Unrounded, anything
+// deciding on temperature wants this one. Doesn't check anything, the filter only
+// gets readings convert_to_celsius_checked() was happy with.
+fn convert_to_celsius_f32(raw_temp: u16) -> f32 {
     // According to chapter 4.9.5. Temperature Sensor in RP2040 datasheet
-    let temp = 27.0 - (raw_temp as f32 * 3.3 / 4096.0 - 0.706) / 0.001721;
+    27.0 - (f32::from(raw_temp) * adc_utils::NOMINAL_VREF / adc_utils::OVERSAMPLED_FULL_SCALE - 0.706) / 0.001_721
+}
+
+// Whole degrees for showing and logging
+fn convert_to_celsius(raw_temp: u16) -> u16 {
+    round_celsius(convert_to_celsius_f32(raw_temp))
+}
+
+// Whole degrees the way the rest of the badge wants them
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn round_celsius(temp: f32) -> u16 {
     let sign = if temp < 0.0 { -1.0 } else { 1.0 };
     let rounded_temp_x10: i16 = ((temp * 10.0) + 0.5 * sign) as i16;
     (rounded_temp_x10 as u16) / 10
@@ -37,14 +104,205 @@ fn convert_to_celsius(raw_temp: u16) -> u16 {
 // ...Let's adjust temperature lower to adjust almost yearly Finnish weather :)
 pub const MY_ALPACCA_FEELS_COLD_WHEN_CELSIUS_HITS_UNDER: u16 = 10;
 
+// ...and don't warm up until clearly above it, otherwise eye and heart strobe at the threshold.
+// Same goes for every band below.
+pub const MY_ALPACCA_WARMS_UP_THIS_MUCH_OVER_COLD: u16 = 2;
+
+// Between cold and this it's just cool
+pub const MY_ALPACCA_FEELS_COOL_WHEN_CELSIUS_HITS_UNDER: u16 = 15;
+
+// and over this it's hot, fur isn't made for that. The rainbow goes frantic, see rainbow.rs.
+pub const MY_ALPACCA_FEELS_HOT_WHEN_CELSIUS_HITS_OVER: u16 = 35;
+
+// Battery is checked roughly once per minute, time is counted from uptime
+pub const BATTERY_CHECK_INTERVAL_MS: u32 = 60_000;
+
+// Below this charge the badge goes into low battery look
+pub const LOW_BATTERY_PERCENT: u8 = 15;
+
+// Low battery animation runs slower than the normal one, see core1
+pub const LOW_BATTERY_TICK_MS: u32 = 20;
+
+// WS2812 pixels soldered to GPIO16, nothing bad happens if there are fewer
+const NEOPIXEL_COUNT: usize = 8;
+
+// Nothing has happened for this long, go dormant. Can be changed over USB and kept in flash.
+pub const SLEEP_AFTER_MS: u32 = 300_000;
+
+// Temperature moving this much from where it was counts as something happening
+const ACTIVITY_TEMPERATURE_DELTA: u16 = 2;
+
+// On-board LED blinks at this period while the die is too hot
+const OVERHEAT_BLINK_MS: u32 = 1000;
+
+// Other badges nearby hear who we are this often
+const IR_BROADCAST_INTERVAL_MS: u32 = 1000;
+
+// If a frame takes longer than this something is stuck, say an I2C device holding the
+// bus, and the watchdog resets the badge. Flash writes fit in it too.
+pub const WATCHDOG_TIMEOUT_US: u32 = 500_000;
+
+// How many temperature samples are averaged, one sample a second
+pub const TEMPERATURE_FILTER_SAMPLES: usize = 8;
+
+// Ahead of the average, 5 s of median drops a spike of up to two readings
+const TEMPERATURE_MEDIAN_SAMPLES: usize = 5;
+
+// Temperature is measured this many frames apart, once a second, and logged less often
+const TEMPERATURE_INTERVAL_FRAMES: u16 = 100;
+const TEMPERATURE_LOG_FRAMES: u16 = 1000;
+
+// clk_adc is gated between measurements and comes back this many frames early,
+// 64 ms to fill the sample ring plus a frame to spare
+const ADC_WARMUP_FRAMES: u16 = 7;
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn volts_to_mv(volts: f32) -> u32 {
+    (volts * 1000.0) as u32
+}
+
+// Beating (spring, keyframed waveform or the lub-dub rhythm) or breathing <3
+pub const HEART_MODE: HeartMode = HeartMode::Pulse;
+
+// What the badge shows after boot, button cycles through the rest
+pub const ANIMATION_MODE: AnimationMode = AnimationMode::Rainbow;
+
+// Coldest first, so warmer bands compare greater
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TemperatureBand {
+    Cold,
+    Cool,
+    Comfortable,
+    Hot,
+    // no good reading yet, never compared with the others
+    Unknown,
+}
+
+impl TemperatureBand {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Cold => "cold",
+            Self::Cool => "cool",
+            Self::Comfortable => "comfortable",
+            Self::Hot => "hot",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    // What the badge switches to on entering the band
+    const fn animation_mode(self) -> AnimationMode {
+        match self {
+            Self::Cold => animations::DEFAULT_AURORA,
+            Self::Cool => AnimationMode::solid(COOL_BLUE),
+            // hot gets the rainbow too, core 1 speeds it up
+            Self::Comfortable | Self::Hot => AnimationMode::Rainbow,
+            Self::Unknown => crate::ANIMATION_MODE,
+        }
+    }
+}
+
+const COOL_BLUE: Srgb = Srgb::new(0, 0x80, 0xff); // hue 210
+
+// Band edges in celsius, can be changed over USB and kept in flash
+#[derive(Clone, Copy)]
+struct BandThresholds {
+    cold_under: u16,
+    cool_under: u16,
+    hot_over: u16,
+}
+
+const fn band_of(celsius: f32, thresholds: BandThresholds) -> TemperatureBand {
+    if celsius < thresholds.cold_under as f32 {
+        TemperatureBand::Cold
+    } else if celsius < thresholds.cool_under as f32 {
+        TemperatureBand::Cool
+    } else if celsius <= thresholds.hot_over as f32 {
+        TemperatureBand::Comfortable
+    } else {
+        TemperatureBand::Hot
+    }
+}
+
+// Hysteresis: going down a band happens right at its edge, going back up only once
+// clearly over it. A jump over several bands is taken as far as it clearly goes.
+// Bands compare by declaration order, `as u8` since Ord isn't const.
+const fn classify_temperature(current: TemperatureBand, celsius: f32, thresholds: BandThresholds) -> TemperatureBand {
+    let band = band_of(celsius, thresholds);
+    if (band as u8) < (current as u8) {
+        return band;
+    }
+    let warmed = band_of(celsius - MY_ALPACCA_WARMS_UP_THIS_MUCH_OVER_COLD as f32, thresholds);
+    if (warmed as u8) > (current as u8) {
+        warmed
+    } else {
+        current
+    }
+}
+
+// There's no test harness on thumbv6m, so the hysteresis is checked while compiling.
+// Cold under 5 C with the 2 C margin: drifting around 5 C stays cold, only 7 C warms it.
+const _: () = {
+    use TemperatureBand::{Cold, Cool};
+    let thresholds = BandThresholds {
+        cold_under: 5,
+        cool_under: 15,
+        hot_over: 35,
+    };
+    let readings = [6.0, 5.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+    let expected = [Cool, Cool, Cold, Cold, Cold, Cool, Cool];
+    let mut band = band_of(readings[0], thresholds);
+    let mut i = 0;
+    while i < readings.len() {
+        band = classify_temperature(band, readings[i], thresholds);
+        assert!(band as u8 == expected[i] as u8, "temperature hysteresis is off");
+        i += 1;
+    }
+};
+
+// Puts the URL from flash up as a QR code, if there is one
+#[cfg(feature = "oled")]
+fn show_stored_qr<I2C: embedded_hal::blocking::i2c::Write>(
+    display: &mut display::BadgeDisplay<I2C>,
+    logger: &mut impl Write,
+) {
+    let url = storage::load_qr_url();
+    if !url.is_empty() && display.show_qr(url.as_str()).is_err() {
+        writeln!(logger, "error: QR URL doesn't fit\r").ok();
+    }
+}
+
+// Face down is the badge's way of asking to be left alone
+#[cfg(feature = "accel")]
+const fn orientation_mode(orientation: input::Orientation) -> AnimationMode {
+    match orientation {
+        input::Orientation::FaceUp => AnimationMode::Rainbow,
+        input::Orientation::FaceDown => AnimationMode::Off,
+        input::Orientation::TiltedLeft => AnimationMode::Ice,
+        input::Orientation::TiltedRight => AnimationMode::Fire,
+    }
+}
+
+// Brightness moves this much per detent, pressing the knob goes back to the middle
+#[cfg(feature = "encoder")]
+const ENCODER_STEP_PERCENT: i32 = 5;
+#[cfg(feature = "encoder")]
+const ENCODER_RESET_BRIGHTNESS_PERCENT: u8 = 50;
+
+#[cfg(feature = "encoder")]
+fn encoder_brightness(percent: u8, steps: i32) -> u8 {
+    let turned = i32::from(percent).saturating_add(steps.saturating_mul(ENCODER_STEP_PERCENT));
+    u8::try_from(turned.clamp(0, 100)).unwrap_or(100)
+}
+
 #[entry]
+#[allow(clippy::too_many_lines)]
 fn main() -> ! {
     let mut pac = pac::Peripherals::take().unwrap();
     let core = pac::CorePeripherals::take().unwrap();
 
     let mut watchdog = hal::Watchdog::new(pac.WATCHDOG);
 
-    let clocks = hal::clocks::init_clocks_and_plls(
+    let mut clocks = hal::clocks::init_clocks_and_plls(
         bsp::XOSC_CRYSTAL_FREQ,
         pac.XOSC,
         pac.CLOCKS,
@@ -56,7 +314,12 @@ fn main() -> ! {
     .ok()
     .unwrap();
 
+    // before anything else gets a chance to go wrong again
+    crash_log::record_watchdog_reset();
+    let mut pending_crash_log = crash_log::load();
+
     let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
+
     let mut pwm_slices = hal::pwm::Slices::new(pac.PWM, &mut pac.RESETS);
 
     let sio = hal::Sio::new(pac.SIO);
@@ -67,6 +330,32 @@ fn main() -> ! {
         &mut pac.RESETS,
     );
 
+    // USB only comes up with VBUS, the PHY costs 10+ mA otherwise. Plugged in later
+    // it's brought up then, see the main loop.
+    let vbus_detect: bsp::VbusDetect = pins.vbus_detect.into_mode();
+    let mut power_profile = power::update_power_profile(vbus_detect.is_high().unwrap());
+    power::configure_low_power_clocks(&mut clocks, power_profile == power::USB_PROFILE);
+    let mut usb_parts = Some((pac.USBCTRL_REGS, pac.USBCTRL_DPRAM, clocks.usb_clock));
+    if power_profile == power::USB_PROFILE {
+        if let Some((regs, dpram, usb_clock)) = usb_parts.take() {
+            usb_log::init(regs, dpram, usb_clock, &mut pac.RESETS);
+        }
+    }
+
+    // free running microsecond counter, see timer::uptime_us, and the frame alarm
+    let mut timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS);
+    let frame_alarm = timer.alarm_0().unwrap();
+
+    #[cfg(not(feature = "uart-log"))]
+    let mut logger = usb_log::Logger;
+    #[cfg(feature = "uart-log")]
+    let mut logger = uart_log::UartLogger::new(
+        pac.UART0,
+        pins.uart_tx.into_mode(),
+        &mut pac.RESETS,
+        &clocks.peripheral_clock,
+    );
+
     let lr: bsp::PWM7 = pins.pwm7.into_mode();
     let lb: bsp::PWM8 = pins.pwm8.into_mode();
     let lg: bsp::PWM9 = pins.pwm9.into_mode();
@@ -78,19 +367,19 @@ fn main() -> ! {
     let hg: bsp::PWM15 = pins.pwm15.into_mode();
 
     let pwm3 = &mut pwm_slices.pwm3;
-    pwm3.set_ph_correct();
+    bsp::set_pwm_freq(pwm3, bsp::LED_PWM_MODE, bsp::LED_PWM_FREQ_HZ, clocks.system_clock.freq().to_Hz());
     pwm3.enable();
     let pwm4 = &mut pwm_slices.pwm4;
-    pwm4.set_ph_correct();
+    bsp::set_pwm_freq(pwm4, bsp::LED_PWM_MODE, bsp::LED_PWM_FREQ_HZ, clocks.system_clock.freq().to_Hz());
     pwm4.enable();
     let pwm5 = &mut pwm_slices.pwm5;
-    pwm5.set_ph_correct();
+    bsp::set_pwm_freq(pwm5, bsp::LED_PWM_MODE, bsp::LED_PWM_FREQ_HZ, clocks.system_clock.freq().to_Hz());
     pwm5.enable();
     let pwm6 = &mut pwm_slices.pwm6;
-    pwm6.set_ph_correct();
+    bsp::set_pwm_freq(pwm6, bsp::LED_PWM_MODE, bsp::LED_PWM_FREQ_HZ, clocks.system_clock.freq().to_Hz());
     pwm6.enable();
     let pwm7 = &mut pwm_slices.pwm7;
-    pwm7.set_ph_correct();
+    bsp::set_pwm_freq(pwm7, bsp::LED_PWM_MODE, bsp::LED_PWM_FREQ_HZ, clocks.system_clock.freq().to_Hz());
     pwm7.enable();
 
     let plr = &mut pwm3.channel_b;
@@ -134,88 +423,634 @@ fn main() -> ! {
     let mut led: bsp::Led = pins.led.into_mode();
     led.set_low().unwrap();
 
+    animations::boot::run_boot_animation(&mut PwmChannels::from_slices(&mut pwm_slices), &mut delay);
+
+    // green heart all good, red one means look at the crash log
+    let test = self_test::run_self_test(&mut PwmChannels::from_slices(&mut pwm_slices), &mut delay);
+    if test.passed() {
+        writeln!(logger, "self test: ok\r").ok();
+    } else {
+        writeln!(logger, "self test: failed channels {:#011b}\r", test.failed_channels).ok();
+        pending_crash_log = Some(crash_log::record_self_test(test.failed_channels));
+    }
+
+    // button held through power on: go through the LEDs one by one, see calibration
+    let mut button: bsp::Button = pins.button.into_mode();
+    if button.is_low().unwrap() {
+        calibration::run(&mut PwmChannels::from_slices(&mut pwm_slices), &button, &mut delay);
+        writeln!(logger, "calibration: {:?}\r", storage::load_calibration()).ok();
+    }
+    // from here on presses are counted by an interrupt, see input::ButtonPresses
+    // SAFETY: Pins took IO_BANK0, only the button's own interrupt bits are written
+    input::init_button_interrupt(&button, unsafe { &*pac::IO_BANK0::ptr() });
+
     // enable ADC with TempSense: https://docs.rs/rp2040-hal/0.7.0/rp2040_hal/adc/index.html
+    // expansion header, nothing on it is required
+    let i2c0 = RefCell::new(bsp::init_i2c0(
+        pac.I2C0,
+        pins.i2c0_sda.into_mode(),
+        pins.i2c0_scl.into_mode(),
+        &mut pac.RESETS,
+        &clocks.system_clock,
+        bsp::I2C0_DEFAULT_FREQ_HZ,
+    ));
+
+    // the encoder takes over the SPI header
+    #[cfg(not(feature = "encoder"))]
+    let _spi0 = bsp::SpiDevice::new(
+        bsp::init_spi0(
+            pac.SPI0,
+            pins.spi0_mosi.into_mode(),
+            pins.spi0_miso.into_mode(),
+            pins.spi0_sck.into_mode(),
+            &mut pac.RESETS,
+            &clocks.peripheral_clock,
+            bsp::SPI0_DEFAULT_FREQ_HZ,
+        ),
+        pins.spi0_cs.into_mode::<hal::gpio::PushPullOutput>(),
+        clocks.system_clock.freq().to_Hz(),
+    );
+
     let mut adc = Adc::new(pac.ADC, &mut pac.RESETS);
     let mut temperature_sensor = adc.enable_temp_sensor();
+    let mut vsys_sense: bsp::VsysSense = pins.vsys_sense.into_mode();
 
-    let mut heart1 = 0;
-    let mut heart2 = 0;
-    let mut pulse: u32; // pulse, will be set immediately, no need to set here.
-    let mut feeling_cold: bool = false;
+    // don't even start if the battery is already too low
+    let vsys = adc_utils::read_vsys(&mut adc, &mut vsys_sense);
+    if power::check_brownout(volts_to_mv(vsys)) == power::BrownoutStatus::Critical {
+        power::enter_dormant();
+    }
+
+    // nothing answering means there's no TMP102 and the internal sensor is all we have
+    let mut tmp102 = sensors::tmp102::Tmp102::new(sensors::SharedI2c::new(&i2c0), sensors::tmp102::DEFAULT_ADDRESS);
+    let has_tmp102 = tmp102.probe();
+    writeln!(logger, "tmp102: {has_tmp102}\r").ok();
+
+    let (mut pio0, pio0_sm0, pio0_sm1, pio0_sm2, _) = pac.PIO0.split(&mut pac.RESETS);
+    let mut strip = pio::ws2812::Ws2812::new(&mut pio0, pio0_sm0, pins.neopixel.into_mode(), &pac.RESETS);
+    let mut strip_pixels = [0u8; NEOPIXEL_COUNT * pio::ws2812::BYTES_PER_PIXEL];
+
+    // tapping the badge cycles modes, if there's an accelerometer on either address
+    let mut accel = sensors::lis3dh::Lis3dh::new(sensors::SharedI2c::new(&i2c0), sensors::lis3dh::ADDRESS_SDO_LOW);
+    let mut has_accel = accel.init().is_ok();
+    if !has_accel {
+        accel = sensors::lis3dh::Lis3dh::new(sensors::SharedI2c::new(&i2c0), sensors::lis3dh::ADDRESS_SDO_HIGH);
+        has_accel = accel.init().is_ok();
+    }
+    writeln!(logger, "lis3dh: {has_accel}\r").ok();
+    #[cfg(feature = "accel")]
+    let mut shake_detector = input::ShakeDetector::new(input::SHAKE_THRESHOLD_MG);
+    #[cfg(feature = "accel")]
+    let mut orientation_detector = input::OrientationDetector::new();
+
+    #[cfg(feature = "oled")]
+    let mut badge_display = display::BadgeDisplay::new(sensors::SharedI2c::new(&i2c0));
+    #[cfg(feature = "oled")]
+    let has_display = badge_display.init().is_ok();
+    #[cfg(feature = "oled")]
+    writeln!(logger, "ssd1306: {has_display}\r").ok();
+    // whatever was last set with show_qr comes back up
+    #[cfg(feature = "oled")]
+    show_stored_qr(&mut badge_display, &mut logger);
+
+    let mut temperature_median = filter::MedianFilter::<TEMPERATURE_MEDIAN_SAMPLES>::new();
+    let mut temperature_filter = filter::TemperatureFilter::<TEMPERATURE_FILTER_SAMPLES>::new();
+    let mut temperature_history = filter::TemperatureHistory::new();
+    let mut temperature_trend = filter::TempTrend::Stable;
+
+    // boot takes a slightly different number of microseconds every time, mix that in too
+    #[allow(clippy::cast_possible_truncation)]
+    let seed = rng::collect_entropy(&mut adc, &mut temperature_sensor) ^ timer::uptime_us() as u32;
+    if !self_test::check_entropy(&mut adc, &mut temperature_sensor) {
+        writeln!(logger, "error: ADC noise gave the same seed twice\r").ok();
+    }
+    // from here on temperature samples just keep coming in the background
+    adc_dma::start(&mut adc, &mut temperature_sensor, &pac.RESETS);
+    #[cfg(feature = "accel")]
+    let mut rng = rng::XorShift32::new(seed.rotate_left(16));
+
+    // flash goes away for a moment, easier before core 1 is running from it
+    match storage::migrate_config() {
+        Ok(true) => {
+            writeln!(logger, "config: migrated, owner name kept\r").ok();
+        }
+        Ok(false) => {}
+        Err(_) => {
+            writeln!(logger, "error: config migration didn't verify\r").ok();
+        }
+    }
+    info::init();
+
+    // both cores run on this, core 1 needs it ticking before it starts
+    timer::start_frame_tick(frame_alarm);
+    // the LEDs are core 1's from here on
+    let mut core1 = core1::spawn(&mut pac.PSM, &mut pac.PPB, sio.fifo, pwm_slices, seed);
+
+    let badge_info = info::get_badge_info();
+    let badge_id = badge_info.ir_id();
+    writeln!(logger, "{badge_info}, IR id {badge_id}\r").ok();
+    let mut ir_tx = pio::ir_tx::IrTx::new(&mut pio0, pio0_sm1, pins.ir_tx.into_mode(), &pac.RESETS);
+    let mut ms_since_ir_broadcast: u32 = 0;
+    pio::ir_rx::init(pins.ir_rx.into_mode(), &timer);
+    let mut seen_badges = pio::ir_rx::SeenBadges::new();
+
+    // nothing happens without a DAC on the pins, the PIO just clocks into the void
+    let mut i2s = pio::i2s::I2s::new(
+        &mut pio0,
+        pio0_sm2,
+        (pins.i2s_data.into_mode(), pins.i2s_bclk.into_mode(), pins.i2s_lrck.into_mode()),
+        &pac.RESETS,
+    );
+    let mut tones = pio::i2s::TonePlayer::new();
+    tones.start(pio::i2s::STARTUP_CHIME);
+    // the simpler option, a piezo on a PWM pin. Alerts go to both, whichever is fitted.
+    let mut buzzer = audio::buzzer::Buzzer::new(pins.buzzer.into_mode());
+
+    // PIO0 has no state machine left, the nose and the encoder get PIO1
+    #[cfg_attr(not(feature = "encoder"), allow(unused_variables))]
+    let (mut pio1, pio1_sm0, pio1_sm1, _, _) = pac.PIO1.split(&mut pac.RESETS);
+    let mut touch = input::TouchSensor::new(pio::touch::TouchPad::new(&mut pio1, pio1_sm0, pins.touch_pad.into_mode()));
+    match touch.baseline() {
+        Some(baseline) => writeln!(logger, "touch baseline: {baseline}\r").ok(),
+        None => writeln!(logger, "no touch pad\r").ok(),
+    };
+    let mut nose_touched = false;
+
+    #[cfg(feature = "encoder")]
+    let mut encoder = input::RotaryEncoder::new(pio::encoder::EncoderPio::new(
+        &mut pio1,
+        pio1_sm1,
+        (pins.spi0_sck.into_mode(), pins.spi0_mosi.into_mode()),
+    ));
+    #[cfg(feature = "encoder")]
+    let encoder_button: bsp::ENCSW = pins.spi0_miso.into_mode();
+    #[cfg(feature = "encoder")]
+    let mut encoder_debouncer = input::Debouncer::new();
+    let mut ms_since_boop = animations::boop::BOOP_MS;
+
+    // whatever save_config kept last time, compile time defaults on a fresh badge
+    let config = storage::load_config();
+    critical_section::with(|cs| {
+        let mut settings = usb_cmd::SETTINGS.borrow_ref_mut(cs);
+        settings.cold_threshold = config.cold_threshold;
+        settings.cool_threshold = config.cool_threshold;
+        settings.hot_threshold = config.hot_threshold;
+        settings.brightness_percent = config.brightness_percent;
+        settings.mode = config.animation_mode;
+        settings.sleep_after_ms = config.sleep_after_ms;
+    });
+    let mut animation_mode = config.animation_mode;
+
+    let mut button_presses = input::ButtonPresses::new();
+    let mut commands = usb_cmd::CommandParser::new();
+    let mut keyboard = usb_hid::HidKeyboard::new();
+
+    let mut low_power = false;
+    // Unknown until the first good measurement, that one doesn't override the saved mode
+    let mut temperature_band = TemperatureBand::Unknown;
+    let mut ms_since_battery_check: u32 = BATTERY_CHECK_INTERVAL_MS; // check on first round
+    let mut last_frame_ms = timer::uptime_ms();
+    #[cfg(feature = "accel")]
+    let mut last_accel_ms = last_frame_ms;
+    // button, orientation or temperature, see SLEEP_AFTER_MS
+    let mut last_activity_ms = last_frame_ms;
+    // since waking up from dormant, while the LEDs fade back in
+    let mut woke_at_ms: Option<u32> = None;
+    let mut activity_temperature: Option<u16> = None;
+    let mut frame_stats = timer::FrameStats::new();
+
+    // from here on a stalled main loop resets the badge, see crash_log for the trail it leaves
+    watchdog.pause_on_debug(true);
+    watchdog.start(WATCHDOG_TIMEOUT_US.micros());
 
     loop {
-        for time in 0u16..65500 {
-            let eyes: Srgb = Hsv::new(f32::from(time)/65500.0*360.0*40.0, 1.0, 1.0).into_color();
-            let eyes_components = eyes.into_components();
-            let eye_r = (eyes_components.0 * 20000.0) as u16;
-            let eye_g = (eyes_components.1 * 20000.0) as u16;
-            let eye_b = (eyes_components.2 * 65535.0) as u16;            
-            plr.set_duty(eye_r);
-            plg.set_duty(eye_g);
-            plb.set_duty(eye_b);
-            
-            // close right eye if cold, looks funny and saves power (if CR2032 used)
-            match feeling_cold {
-                true => {
-                    prr.set_duty(0);
-                    prg.set_duty(0);
-                    prb.set_duty(0);
-                },
-                false => {
-                    prr.set_duty(eye_r);
-                    prg.set_duty(eye_g);
-                    prb.set_duty(eye_b);
-                }
-            }
-
-            if time.wrapping_add(20) % 100 == 0 {
-                heart1 = 0xffff;
-            }
-
-            if time % 1000 == 0 {
-                let temperature_adc_counts: u16 = adc.read(&mut temperature_sensor).unwrap();
-                let temperature = convert_to_celsius(temperature_adc_counts);
-                match temperature {
-                    0 .. MY_ALPACCA_FEELS_COLD_WHEN_CELSIUS_HITS_UNDER => feeling_cold = true,
-                    MY_ALPACCA_FEELS_COLD_WHEN_CELSIUS_HITS_UNDER..=u16::MAX => feeling_cold = false,
-                }
-            }
-
-            // Change of <3
-            if time % 100 == 0 {
-                heart1 = 0xffff;
-                heart2 = 0x1000;
-            }
-
-            // Give either BLUE or RED <3
-            match feeling_cold {
-                true => {
-                            // Blue <3
-                            phr.set_duty(heart2); //heart red
-                            //phg.set_duty(0); //heart green
-                            phb.set_duty(heart1); //heart blue
-                            pulse = 20; // slower pulse
-                }
-                false => {
-                            // Red <3
-                            phr.set_duty(heart1); //heart red
-                            //phg.set_duty(heart1); //heart green
-                            phb.set_duty(heart2); //heart blue
-                            pulse = 10; // normal pulse
-                }
-            }
-
-            heart1 = match heart1.checked_sub(1000) {
-                Some(n) => n,
-                None => 0,
-            };
+        for time in 0u16..65_500 {
+            // once per frame, once per pass of the outer loop would be minutes apart
+            watchdog.feed();
+
+            // real time since last frame, the frame before might have run long
+            let now_ms = timer::uptime_ms();
+            let delta_ms = now_ms.wrapping_sub(last_frame_ms);
+            last_frame_ms = now_ms;
+            crash_log::breadcrumb(now_ms);
+            if let Some(report) = frame_stats.update(delta_ms) {
+                writeln!(
+                    logger,
+                    "fps: {}, frame {}..{} ms\r",
+                    report.fps, report.min_frame_ms, report.max_frame_ms
+                )
+                .ok();
+            }
+
+            // nobody hears it until there's a host, so it waits for one
+            if usb_log::is_connected() {
+                if let Some(crash) = pending_crash_log.take() {
+                    writeln!(usb_log::Logger, "crash: {crash}\r").ok();
+                    crash_log::clear().ok();
+                }
+            }
+
+            // LEDs are off for overheating, the on-board one says why
+            if power::THERMAL_SCALE.load(Ordering::Relaxed) == 0 {
+                led.set_state((now_ms % OVERHEAT_BLINK_MS < OVERHEAT_BLINK_MS / 5).into()).unwrap();
+            }
+
+            let profile = power::update_power_profile(vbus_detect.is_high().unwrap());
+            // back to full speed before USB comes up, and never in the middle of a note
+            let slow = profile == power::BATTERY_PROFILE && animation_mode.is_steady() && buzzer.is_quiet();
+            if slow != power::sys_clock_slow() {
+                power::set_sys_clock_slow(slow);
+                delay = cortex_m::delay::Delay::new(delay.free(), power::sys_clock_hz());
+                writeln!(logger, "clk_sys: {} kHz\r", power::sys_clock_hz() / 1000).ok();
+            }
+            if profile != power_profile {
+                power_profile = profile;
+                writeln!(logger, "power: {}\r", profile.name()).ok();
+                // once up it stays up, unplugged it just isn't enumerated
+                if let (power::USB_PROFILE, Some((regs, dpram, usb_clock))) = (profile, usb_parts.take()) {
+                    power::set_usb_clock(true);
+                    usb_log::init(regs, dpram, usb_clock, &mut pac.RESETS);
+                }
+            }
+
+            if time % TEMPERATURE_INTERVAL_FRAMES == TEMPERATURE_INTERVAL_FRAMES - ADC_WARMUP_FRAMES {
+                power::set_adc_clock(true);
+            }
+
+            if time % TEMPERATURE_INTERVAL_FRAMES == 0 {
+                let log_now = time % TEMPERATURE_LOG_FRAMES == 0;
+                // battery first, it also tells whether the regulator still holds 3.3 V
+                let vsys = adc_utils::read_vsys(&mut adc, &mut vsys_sense);
+                let regulated = adc_utils::check_regulation(vsys);
+                match power::check_brownout(volts_to_mv(vsys)) {
+                    power::BrownoutStatus::Ok => {}
+                    power::BrownoutStatus::Warning => {
+                        writeln!(logger, "error: brownout warning, VSYS {vsys:.2} V\r").ok();
+                        // go straight to low battery look
+                        power::BATTERY_PERCENT.store(0, Ordering::Relaxed);
+                    }
+                    power::BrownoutStatus::Critical => {
+                        // give core 1 a frame or two to actually turn them off
+                        core1.lights_out();
+                        delay.delay_ms(2 * animations::frame_ms(true));
+                        led.set_low().unwrap();
+                        power::enter_dormant();
+                    }
+                }
+                // the ring is full after 64 ms, only right after boot it needs a hand
+                let mut samples = [0u16; adc_utils::TEMPERATURE_OVERSAMPLE];
+                let mut count = 0;
+                loop {
+                    let drained = adc_dma::drain_adc_buffer();
+                    if drained.is_empty() {
+                        break;
+                    }
+                    for &sample in drained {
+                        samples[count % samples.len()] = sample;
+                        count += 1;
+                    }
+                }
+                let temperature_adc_counts = if count >= samples.len() {
+                    adc_utils::decimate(&samples)
+                } else {
+                    adc_utils::oversample_temperature::<{ adc_utils::TEMPERATURE_OVERSAMPLE }>(
+                        &mut adc,
+                        &mut temperature_sensor,
+                    )
+                };
+                // done with the ADC until the next warm up
+                power::set_adc_clock(false);
+                // a glitch would drag the average with it for a whole minute, and so would
+                // a reading taken while the reference sags with VSYS
+                let reading = if regulated {
+                    convert_to_celsius_checked(temperature_adc_counts)
+                } else {
+                    Err(TempSensorError::Unregulated(vsys))
+                };
+                if reading.is_ok() {
+                    // a median of fewer than a full window isn't much of one, until then
+                    // the readings go straight through
+                    temperature_median.push(temperature_adc_counts);
+                    temperature_filter.push(if temperature_median.is_ready() {
+                        temperature_median.median()
+                    } else {
+                        temperature_adc_counts
+                    });
+                }
+                // only the die's own sensor knows how hot the chip is, and not with a sagging reference
+                let die_valid = reading.is_ok();
+                // external sensor when it's there, internal one if it isn't or the read fails
+                let external = if has_tmp102 { tmp102.read_celsius().ok() } else { None };
+                let die_celsius = convert_to_celsius_f32(temperature_filter.average());
+                let die_temperature = convert_to_celsius(temperature_filter.average());
+                let celsius = external.unwrap_or(die_celsius);
+                let temperature = round_celsius(celsius);
+                if log_now {
+                    writeln!(
+                        logger,
+                        "temperature: {temperature} C, raw {temperature_adc_counts}, uptime {now_ms} ms\r"
+                    )
+                    .ok();
+                }
+                if log_now && has_accel {
+                    if let Ok((x, y, z)) = accel.read_xyz() {
+                        writeln!(logger, "accel: {x} {y} {z} mg\r").ok();
+                    }
+                }
+                match reading {
+                    Ok(_) => {}
+                    Err(TempSensorError::InvalidRaw(raw)) => {
+                        writeln!(logger, "error: temperature ADC out of range, raw {raw}\r").ok();
+                    }
+                    Err(TempSensorError::Unregulated(vsys)) => {
+                        writeln!(logger, "error: VSYS {vsys:.2} V too low to keep 3.3 V\r").ok();
+                    }
+                }
+                if die_valid {
+                    let throttle = power::thermal_throttle(die_temperature);
+                    if throttle != power::THERMAL_SCALE.load(Ordering::Relaxed) {
+                        power::THERMAL_SCALE.store(throttle, Ordering::Relaxed);
+                        writeln!(logger, "die temperature: {die_temperature} C, brightness {throttle}%\r").ok();
+                        if throttle != 0 {
+                            led.set_low().unwrap();
+                        }
+                    }
+                }
+                // keep the previous state if rail or sensor reading was garbage, TMP102 doesn't care
+                if external.is_some() || die_valid {
+                    crash_log::note_temperature(temperature);
+                    animations::thermometer::note_celsius(celsius);
+                    if activity_temperature
+                        .is_none_or(|then| then.abs_diff(temperature) >= ACTIVITY_TEMPERATURE_DELTA)
+                    {
+                        activity_temperature = Some(temperature);
+                        last_activity_ms = now_ms;
+                    }
+                    let thresholds = critical_section::with(|cs| {
+                        let mut settings = usb_cmd::SETTINGS.borrow_ref_mut(cs);
+                        settings.temperature = temperature;
+                        BandThresholds {
+                            cold_under: settings.cold_threshold,
+                            cool_under: settings.cool_threshold,
+                            hot_over: settings.hot_threshold,
+                        }
+                    });
+                    let band = if temperature_band == TemperatureBand::Unknown {
+                        band_of(celsius, thresholds)
+                    } else {
+                        classify_temperature(temperature_band, celsius, thresholds)
+                    };
+                    if temperature_band != TemperatureBand::Unknown && temperature_band != band {
+                        if band == TemperatureBand::Cold {
+                            tones.start(pio::i2s::COLD_ALERT_TONE);
+                            buzzer.play_melody(audio::buzzer::COLD_ENTRY);
+                        }
+                        // the thermometer already shows the temperature, better than any band
+                        if !matches!(animation_mode, AnimationMode::Thermometer { .. }) {
+                            animation_mode = band.animation_mode();
+                        }
+                        writeln!(logger, "temperature band: {}, mode: {}\r", band.name(), animation_mode.name())
+                            .ok();
+                    }
+                    temperature_band = band;
+
+                    temperature_history.push(celsius);
+                    let trend = temperature_history.trend();
+                    if trend != temperature_trend {
+                        temperature_trend = trend;
+                        writeln!(logger, "temperature trend: {}\r", trend.name()).ok();
+                    }
+                }
 
-            heart2 = match heart2.checked_sub(1000) {
-                Some(n) => n,
-                None => 0,
+                // once a second like the measurement, more would mostly be I2C traffic
+                #[cfg(feature = "oled")]
+                if has_display {
+                    badge_display.show_owner_name(storage::load_owner_name().as_str());
+                    badge_display.show_animation_mode(&animation_mode);
+                    badge_display.show_temperature(celsius);
+                    badge_display.show_battery_percent(power::BATTERY_PERCENT.load(Ordering::Relaxed));
+                    if badge_display.flush().is_err() {
+                        writeln!(logger, "error: ssd1306 not answering\r").ok();
+                    }
+                }
+            }
+
+            // turning it moves the same setting the brightness command does, so
+            // save_config keeps it too
+            #[cfg(feature = "encoder")]
+            {
+                let steps = encoder.update();
+                let pressed = encoder_debouncer.update(encoder_button.is_low().unwrap(), delta_ms)
+                    == input::ButtonEvent::ShortPress;
+                if steps != 0 || pressed {
+                    last_activity_ms = now_ms;
+                    critical_section::with(|cs| {
+                        let mut settings = usb_cmd::SETTINGS.borrow_ref_mut(cs);
+                        settings.brightness_percent = if pressed {
+                            ENCODER_RESET_BRIGHTNESS_PERCENT
+                        } else {
+                            encoder_brightness(settings.brightness_percent, steps)
+                        };
+                    });
+                }
+            }
+
+            // replies go back where the command came from
+            commands.poll(&mut usb_log::Logger);
+            let (requested_mode, brightness_percent, calibration_changed, morse, sleep_after_ms, qr_changed, melody) =
+                critical_section::with(|cs| {
+                    let mut settings = usb_cmd::SETTINGS.borrow_ref_mut(cs);
+                    let calibration_changed = core::mem::take(&mut settings.calibration_changed);
+                    let morse = settings.morse.take();
+                    (
+                        settings.requested_mode.take(),
+                        settings.brightness_percent,
+                        calibration_changed,
+                        morse,
+                        settings.sleep_after_ms,
+                        core::mem::take(&mut settings.qr_changed),
+                        settings.melody.take(),
+                    )
+                });
+            if calibration_changed {
+                core1.reload_calibration();
+            }
+            if let Some(encoder) = morse {
+                core1.morse(encoder);
+            }
+            if let Some(melody) = melody {
+                buzzer.play_rtttl(audio::rtttl::parse_rtttl(melody));
+            }
+            // stored either way, without a display it shows up once there is one
+            #[cfg(feature = "oled")]
+            if qr_changed && has_display {
+                show_stored_qr(&mut badge_display, &mut logger);
+                badge_display.flush().ok();
+            }
+            #[cfg(not(feature = "oled"))]
+            let _ = qr_changed;
+            if let Some(mode) = requested_mode {
+                animation_mode = mode;
+                writeln!(logger, "mode: {}\r", animation_mode.name()).ok();
+            }
+
+            // accelerometer samples at 10 Hz, no point asking more often
+            if has_accel
+                && time.is_multiple_of(10)
+                && accel.get_tap_status() == sensors::lis3dh::TapEvent::SingleTap
+            {
+                animation_mode = animation_mode.next();
+                writeln!(logger, "mode: {}\r", animation_mode.name()).ok();
+            }
+
+            #[cfg(feature = "accel")]
+            if has_accel && time.is_multiple_of(10) {
+                if let Ok(sample) = accel.read_xyz() {
+                    let accel_delta_ms = now_ms.wrapping_sub(last_accel_ms);
+                    last_accel_ms = now_ms;
+                    if let Some(shake) = shake_detector.update(sample, accel_delta_ms) {
+                        animation_mode = animation_mode.random(&mut rng);
+                        writeln!(logger, "shake: {} mg, mode: {}\r", shake.rms_mg, animation_mode.name()).ok();
+                    }
+                    // only on change, so the button still works while the badge stays put
+                    if let Some(orientation) = orientation_detector.update(sample) {
+                        animation_mode = orientation_mode(orientation);
+                        last_activity_ms = now_ms;
+                        writeln!(logger, "orientation mode: {}\r", animation_mode.name()).ok();
+                    }
+                }
+            }
+
+            let button_event = button_presses.update();
+            if button_event != input::ButtonEvent::None {
+                last_activity_ms = now_ms;
+            }
+            match button_event {
+                input::ButtonEvent::ShortPress => {
+                    animation_mode = animation_mode.next();
+                    writeln!(logger, "mode: {}\r", animation_mode.name()).ok();
+                }
+                // with USB connected, type owner's name as a keyboard
+                input::ButtonEvent::LongPress(_) if usb_log::is_connected() => {
+                    keyboard.type_text(storage::load_owner_name());
+                }
+                // with a QR code on the display it flips between that and the status
+                #[cfg(feature = "oled")]
+                input::ButtonEvent::LongPress(_) if has_display && badge_display.toggle_qr() => {
+                    badge_display.flush().ok();
+                }
+                input::ButtonEvent::LongPress(_) => low_power = !low_power,
+                input::ButtonEvent::Held(_) | input::ButtonEvent::Released | input::ButtonEvent::None => {}
+            }
+
+            // one boop per touch, holding on doesn't repeat it. Core 1 ignores boops while
+            // it's still drawing one, the melody shouldn't start over either.
+            let touched = touch.is_touched();
+            ms_since_boop = ms_since_boop.saturating_add(delta_ms);
+            if touched && !nose_touched && ms_since_boop >= animations::boop::BOOP_MS {
+                ms_since_boop = 0;
+                last_activity_ms = now_ms;
+                core1.boop();
+                buzzer.play_melody(audio::buzzer::HAPPY_BOOP);
+                writeln!(logger, "boop\r").ok();
+            }
+            nose_touched = touched;
+
+            keyboard.tick(delta_ms);
+
+            animations::strip::render(u32::from(time), &mut strip_pixels);
+            strip.write(&strip_pixels);
+
+            critical_section::with(|cs| usb_cmd::SETTINGS.borrow_ref_mut(cs).mode = animation_mode);
+            // on USB there's power to spare, and the host would see us vanish
+            let may_sleep = sleep_after_ms != 0 && !usb_log::is_connected();
+            let sleep_fade = match woke_at_ms {
+                Some(woke_at) if now_ms.wrapping_sub(woke_at) < animations::sleep::WAKE_FADE_MS => {
+                    animations::sleep::fade_in_progress(now_ms.wrapping_sub(woke_at))
+                }
+                _ if may_sleep => {
+                    woke_at_ms = None;
+                    animations::sleep::fade_out_progress(now_ms.wrapping_sub(last_activity_ms), sleep_after_ms)
+                }
+                _ => {
+                    woke_at_ms = None;
+                    0.0
+                }
             };
 
-            delay.delay_ms(pulse);
+            // cold band still slows everything down and closes an eye
+            let feeling_cold = temperature_band == TemperatureBand::Cold;
+            // hot band speeds everything up instead, only one band at a time so never both
+            let feeling_hot = temperature_band == TemperatureBand::Hot;
+            core1.sync(
+                animation_mode,
+                feeling_cold,
+                feeling_hot,
+                temperature_trend,
+                power_profile.limit(if low_power {
+                    led_config::LOW_POWER_BRIGHTNESS_PERCENT
+                } else {
+                    brightness_percent
+                }),
+                sleep_fade,
+            );
+
+            if ms_since_battery_check >= BATTERY_CHECK_INTERVAL_MS {
+                ms_since_battery_check = 0;
+                let volts = power::with_adc_clock(|| power::battery_voltage(&mut adc, &mut vsys_sense));
+                let percent = power::battery_percent(volts);
+                if percent < LOW_BATTERY_PERCENT && power::BATTERY_PERCENT.load(Ordering::Relaxed) >= LOW_BATTERY_PERCENT {
+                    tones.start(pio::i2s::LOW_BATTERY_BEEP);
+                    buzzer.play_melody(audio::buzzer::LOW_BATTERY_ALERT);
+                }
+                power::BATTERY_PERCENT.store(percent, Ordering::Relaxed);
+                let scale = power::compute_brightness_scale(u16::try_from(volts_to_mv(volts)).unwrap_or(u16::MAX));
+                power::BRIGHTNESS_SCALE.store(scale, Ordering::Relaxed);
+                writeln!(logger, "battery: {volts:.2} V, {percent}%, brightness {scale}%\r").ok();
+            }
+
+            // still sending the last one is fine, try again next frame
+            if ms_since_ir_broadcast >= IR_BROADCAST_INTERVAL_MS
+                && ir_tx.send_nec(pio::ir_tx::BADGE_NEC_ADDRESS, badge_id)
+            {
+                ms_since_ir_broadcast = 0;
+            }
+
+            tones.poll(&mut i2s);
+            buzzer.tick(delta_ms);
+
+            // rest of the frame goes to IR, a badge id is over in well under a frame
+            while !timer::take_frame() {
+                // our own broadcast bounces back too, that one doesn't count
+                if let Some((pio::ir_tx::BADGE_NEC_ADDRESS, id)) = pio::ir_rx::poll_received() {
+                    if id != badge_id && seen_badges.record(id) {
+                        writeln!(logger, "met badge: {id}\r").ok();
+                        core1.ack();
+                    }
+                }
+            }
+            ms_since_battery_check += delta_ms;
+            ms_since_ir_broadcast += delta_ms;
+
+            // faded out by now, see SleepFadeAnimation
+            if may_sleep && woke_at_ms.is_none() && sleep_fade >= 1.0 {
+                writeln!(logger, "sleeping\r").ok();
+                buzzer.stop();
+                core1.sleep();
+                // in steps, the watchdog is still running
+                for _ in 0..animations::shutdown::SHUTDOWN_MS / timer::FRAME_MS {
+                    watchdog.feed();
+                    delay.delay_ms(timer::FRAME_MS);
+                }
+                power::enter_dormant_mode(&mut power::WakePins {
+                    button: &mut button,
+                    accel_int_gpio: bsp::ACCEL_INT_GPIO,
+                });
+                core1.wake();
+                last_activity_ms = timer::uptime_ms();
+                woke_at_ms = Some(last_activity_ms);
+                writeln!(logger, "awake\r").ok();
+            }
         }
     }
 }
@@ -0,0 +1,141 @@
+// Longest message that fits, same as a USB command line
+pub const MAX_LEN: usize = 64;
+
+// One dit long, everything else is counted in these
+pub const DEFAULT_UNIT_MS: u32 = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Symbol {
+    Dit,
+    Dah,
+    // between letters of a word
+    LetterGap,
+    // between words
+    WordGap,
+}
+
+// A-Z and 0-9, anything else isn't sent
+fn code(byte: u8) -> Option<&'static [u8]> {
+    const LETTERS: [&[u8]; 26] = [
+        b".-", b"-...", b"-.-.", b"-..", b".", b"..-.", b"--.", b"....", b"..", b".---", b"-.-", b".-..", b"--",
+        b"-.", b"---", b".--.", b"--.-", b".-.", b"...", b"-", b"..-", b"...-", b".--", b"-..-", b"-.--", b"--..",
+    ];
+    const DIGITS: [&[u8]; 10] = [
+        b"-----", b".----", b"..---", b"...--", b"....-", b".....", b"-....", b"--...", b"---..", b"----.",
+    ];
+    match byte.to_ascii_uppercase() {
+        letter @ b'A'..=b'Z' => Some(LETTERS[usize::from(letter - b'A')]),
+        digit @ b'0'..=b'9' => Some(DIGITS[usize::from(digit - b'0')]),
+        _ => None,
+    }
+}
+
+// Keeps its own copy of the text, so it can be handed to the other core
+pub struct MorseEncoder {
+    text: [u8; MAX_LEN],
+    len: usize,
+    // next letter, and how far into its code we are
+    index: usize,
+    element: usize,
+    gap: Option<Symbol>,
+}
+
+impl MorseEncoder {
+    // Longer messages are cut
+    pub fn new(message: &str) -> Self {
+        let mut encoder = Self {
+            text: [0; MAX_LEN],
+            len: 0,
+            index: 0,
+            element: 0,
+            gap: None,
+        };
+        for byte in message.bytes().take(MAX_LEN) {
+            encoder.text[encoder.len] = byte;
+            encoder.len += 1;
+        }
+        encoder
+    }
+
+    // Gap between the letter that just ended and whatever gets sent next, if anything does
+    fn gap_after_letter(&self) -> Option<Symbol> {
+        let mut word_ends = false;
+        for &byte in &self.text[self.index..self.len] {
+            if code(byte).is_some() {
+                return Some(if word_ends { Symbol::WordGap } else { Symbol::LetterGap });
+            }
+            word_ends |= byte == b' ';
+        }
+        None
+    }
+}
+
+impl Iterator for MorseEncoder {
+    type Item = Symbol;
+
+    fn next(&mut self) -> Option<Symbol> {
+        if let Some(gap) = self.gap.take() {
+            return Some(gap);
+        }
+
+        loop {
+            let byte = self.text[..self.len].get(self.index).copied()?;
+            let Some(code) = code(byte) else {
+                // spaces are already taken care of by gap_after_letter
+                self.index += 1;
+                continue;
+            };
+
+            let element = code[self.element];
+            self.element += 1;
+            if self.element == code.len() {
+                self.element = 0;
+                self.index += 1;
+                self.gap = self.gap_after_letter();
+            }
+            return Some(if element == b'.' { Symbol::Dit } else { Symbol::Dah });
+        }
+    }
+}
+
+// Turns symbols into on and off: dit 1 unit lit, dah 3, then 1 dark after each.
+// Letter and word gaps make that 3 and 7 dark in total.
+pub struct MorseBlinker {
+    encoder: MorseEncoder,
+    unit_ms: u32,
+    lit: bool,
+    remaining_ms: u32,
+}
+
+impl MorseBlinker {
+    pub const fn new(encoder: MorseEncoder, unit_ms: u32) -> Self {
+        Self {
+            encoder,
+            unit_ms,
+            lit: false,
+            remaining_ms: 0,
+        }
+    }
+
+    // Whether the LEDs should be lit after `delta_ms`, None once the message is over
+    pub fn tick(&mut self, delta_ms: u32) -> Option<bool> {
+        self.remaining_ms = self.remaining_ms.saturating_sub(delta_ms);
+        if self.remaining_ms == 0 {
+            // every lit symbol is followed by one dark unit
+            if self.lit {
+                self.lit = false;
+                self.remaining_ms = self.unit_ms;
+            } else {
+                let (lit, units) = match self.encoder.next()? {
+                    Symbol::Dit => (true, 1),
+                    Symbol::Dah => (true, 3),
+                    Symbol::LetterGap => (false, 3 - 1),
+                    Symbol::WordGap => (false, 7 - 1),
+                };
+                self.lit = lit;
+                self.remaining_ms = units * self.unit_ms;
+            }
+        }
+        Some(self.lit)
+    }
+}
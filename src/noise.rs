@@ -0,0 +1,63 @@
+use crate::rng::XorShift32;
+
+// Any seed will do, it only has to stay the same from build to build
+const PERMUTATION_SEED: u32 = 0x5eed_a1fa;
+
+// 0..=255 shuffled, then the same again so lookups at i + 1 never need wrapping
+const PERMUTATION: [u8; 512] = permutation_table();
+
+#[allow(clippy::cast_possible_truncation)]
+const fn permutation_table() -> [u8; 512] {
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        table[i] = i as u8;
+        i += 1;
+    }
+
+    // Fisher-Yates
+    let mut rng = XorShift32::new(PERMUTATION_SEED);
+    let mut i = 255;
+    while i > 0 {
+        let j = rng.next_range(0, i as u32 + 1) as usize;
+        let swap = table[i];
+        table[i] = table[j];
+        table[j] = swap;
+        i -= 1;
+    }
+
+    let mut doubled = [0u8; 512];
+    let mut i = 0;
+    while i < 512 {
+        doubled[i] = table[i % 256];
+        i += 1;
+    }
+    doubled
+}
+
+// Smootherstep, so the noise has no kinks at whole numbers
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+// In 1D the gradient is just uphill or downhill
+fn gradient(hash: u8, distance: f32) -> f32 {
+    if hash & 1 == 0 {
+        distance
+    } else {
+        -distance
+    }
+}
+
+// Smooth noise in -1.0..=1.0, same x always gives the same value
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn perlin1d(x: f32) -> f32 {
+    let floor = libm::floorf(x);
+    let cell = (floor as i32 & 255) as usize;
+    let t = x - floor;
+
+    let left = gradient(PERMUTATION[cell], t);
+    let right = gradient(PERMUTATION[cell + 1], t - 1.0);
+    // the gradients only ever get it to half way
+    2.0 * (left + fade(t) * (right - left))
+}
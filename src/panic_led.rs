@@ -0,0 +1,119 @@
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+use rp2040_hal::pac;
+use rp2040_hal::Sio;
+
+use crate::bsp;
+use crate::crash_log;
+use crate::power;
+
+// One bit is this long lit, then this long dark
+const LONG_PULSE_MS: u32 = 400;
+const SHORT_PULSE_MS: u32 = 150;
+const GAP_MS: u32 = 250;
+
+// The whole code starts over this often, four long bits still fit
+const REPEAT_MS: u32 = 3000;
+const REPETITIONS: u32 = 10;
+
+// LED channels as (PWM slice, channel B), see bsp::LED_PWM_CHANNELS
+const RED_CHANNELS: [(u8, u8); 3] = [
+    bsp::LED_PWM_CHANNELS[0],
+    bsp::LED_PWM_CHANNELS[3],
+    bsp::LED_PWM_CHANNELS[6],
+];
+const LED_SLICES: core::ops::RangeInclusive<usize> = 3..=7;
+
+// FNV-1a over whatever the panic message formats to
+struct Hasher(u32);
+
+impl Write for Hasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0 = (self.0 ^ u32::from(byte)).wrapping_mul(0x0100_0193);
+        }
+        Ok(())
+    }
+}
+
+// Message and location both go in, so the same message from two places differs
+fn error_code(info: &PanicInfo) -> u8 {
+    let mut hasher = Hasher(0x811c_9dc5);
+    write!(hasher, "{info}").ok();
+    let hash = hasher.0;
+    // fold it all down to four bits
+    let folded = hash ^ (hash >> 16);
+    let folded = folded ^ (folded >> 8);
+    ((folded ^ (folded >> 4)) & 0xf) as u8
+}
+
+fn now_us() -> u32 {
+    // SAFETY: read only, the counter is free running
+    unsafe { (*pac::TIMER::ptr()).timerawl.read().bits() }
+}
+
+fn wait_ms(ms: u32) {
+    let start = now_us();
+    while now_us().wrapping_sub(start) < ms * 1000 {}
+}
+
+// Straight to the registers, the hal's channels belong to whoever was running
+fn set_red(on: bool) {
+    // SAFETY: nobody else is left to touch PWM
+    let pwm = unsafe { &*pac::PWM::ptr() };
+    for slice in LED_SLICES {
+        pwm.ch[slice].cc.write(|w| unsafe { w.a().bits(0).b().bits(0) });
+    }
+    if on {
+        for (slice, channel) in RED_CHANNELS {
+            pwm.ch[usize::from(slice)].cc.modify(|_, w| unsafe {
+                if channel == 1 {
+                    w.b().bits(u16::MAX)
+                } else {
+                    w.a().bits(u16::MAX)
+                }
+            });
+        }
+    }
+}
+
+// Highest bit first
+fn blink_code(code: u8) {
+    let mut elapsed = 0;
+    for bit in (0..4).rev() {
+        let pulse = if code & (1 << bit) != 0 { LONG_PULSE_MS } else { SHORT_PULSE_MS };
+        set_red(true);
+        wait_ms(pulse);
+        set_red(false);
+        wait_ms(GAP_MS);
+        elapsed += pulse + GAP_MS;
+    }
+    wait_ms(REPEAT_MS - elapsed);
+}
+
+// Red blinks tell which panic it was, no serial needed: long for 1, short for 0.
+// Then the badge goes dark until someone power cycles it.
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+
+    // SAFETY: nothing runs normally after this
+    unsafe {
+        // writing the crash log and blinking take way longer than the watchdog timeout
+        (*pac::WATCHDOG::ptr()).ctrl.modify(|_, w| w.enable().clear_bit());
+        // core 1 would keep drawing its animation over ours, and it has to be out of
+        // flash before the crash log is written
+        if Sio::core() == 0 {
+            (*pac::PSM::ptr()).frce_off.modify(|_, w| w.proc1().set_bit());
+        }
+    }
+    crash_log::record_panic();
+
+    let code = error_code(info);
+    for _ in 0..REPETITIONS {
+        blink_code(code);
+    }
+
+    power::enter_dormant()
+}
@@ -0,0 +1,49 @@
+use rp2040_hal::pac;
+use rp2040_hal::pio::{PIOBuilder, PinDir, Running, Rx, StateMachine, UninitStateMachine, PIO, SM1};
+
+use crate::bsp;
+
+type Sm = (pac::PIO1, SM1);
+
+// 32 cycles of the debounce wait make 1 ms: 32 kHz out of 125 MHz, 3906 + 64/256
+const CLOCK_DIVISOR_INT: u16 = 3906;
+const CLOCK_DIVISOR_FRAC: u8 = 64;
+
+// A and B on GPIO18 and 19, pulled up, the common pin to ground
+pub struct EncoderPio {
+    _sm: StateMachine<Sm, Running>,
+    rx: Rx<Sm>,
+}
+
+impl EncoderPio {
+    pub fn new(pio: &mut PIO<pac::PIO1>, sm: UninitStateMachine<Sm>, _pins: (bsp::ENCA, bsp::ENCB)) -> Self {
+        let program = pio_proc::pio_file!("src/pio/encoder.pio", select_program("encoder"));
+        let installed = pio.install(&program.program).unwrap();
+
+        let (a, b) = (18, 19); // bsp::ENCA, bsp::ENCB
+        // Function modes leave the pads unpulled and hal 0.7 has no way to change that
+        // SAFETY: only our own two pads are touched
+        let pads = unsafe { &*pac::PADS_BANK0::ptr() };
+        for pin in [a, b] {
+            pads.gpio[usize::from(pin)].modify(|_, w| w.pue().set_bit().pde().clear_bit());
+        }
+
+        let (mut sm, rx, _) = PIOBuilder::from_program(installed)
+            .in_pin_base(a)
+            .jmp_pin(b)
+            .clock_divisor_fixed_point(CLOCK_DIVISOR_INT, CLOCK_DIVISOR_FRAC)
+            .build(sm);
+        sm.set_pindirs([(a, PinDir::Input), (b, PinDir::Input)]);
+
+        Self { _sm: sm.start(), rx }
+    }
+
+    // Where the encoder is now, None if it hasn't moved since last time
+    pub fn read(&mut self) -> Option<u32> {
+        let mut latest = None;
+        while let Some(position) = self.rx.read() {
+            latest = Some(position);
+        }
+        latest
+    }
+}
@@ -0,0 +1,173 @@
+use rp2040_hal::pac;
+use rp2040_hal::pio::{
+    Buffers, PIOBuilder, PinDir, Running, ShiftDirection, StateMachine, Tx, UninitStateMachine, PIO, SM2,
+};
+
+use crate::bsp;
+
+pub const SAMPLE_RATE_HZ: u32 = 16_000;
+
+// DMA channel that feeds the state machine, 0..=3 belong to uart_log, ws2812, ir_tx and adc_dma
+const DMA_CHANNEL: usize = 4;
+
+// 16 kHz * 32 bits * 2 cycles per bit = 1.024 MHz out of 125 MHz: 122 + 18/256
+const CLOCK_DIVISOR_INT: u16 = 122;
+const CLOCK_DIVISOR_FRAC: u8 = 18;
+
+// DMA reads this round and round for as long as the tone lasts. Whole periods have to fit
+// in it, so tones come out in steps of 16 kHz / 512 = 31.25 Hz.
+const BUFFER_LEN: usize = 512;
+// wrap on 1 << 11 = 2048 bytes, the whole buffer
+const BUFFER_RING_BITS: u8 = 11;
+
+// A quarter of full scale, PCM5102 straight into a small speaker is plenty loud at that
+const AMPLITUDE: i32 = 8192;
+
+// One period of sine, full scale
+const SINE: [i16; 32] = [
+    0, 6393, 12539, 18204, 23170, 27245, 30273, 32137, 32767, 32137, 30273, 27245, 23170, 18204, 12539, 6393, 0,
+    -6393, -12539, -18204, -23170, -27245, -30273, -32137, -32767, -32137, -30273, -27245, -23170, -18204, -12539,
+    -6393,
+];
+
+// (frequency, duration) one after the other, 0 Hz is a pause
+pub type Tones = &'static [(u32, u32)];
+
+// C5 E5 G5 C6, going up
+pub const STARTUP_CHIME: Tones = &[(523, 120), (659, 120), (784, 120), (1047, 240)];
+// two short low ones
+pub const LOW_BATTERY_BEEP: Tones = &[(440, 150), (0, 100), (440, 150)];
+// a high one falling, like a shiver
+pub const COLD_ALERT_TONE: Tones = &[(1568, 100), (1319, 100), (1047, 200)];
+
+type Sm = (pac::PIO0, SM2);
+
+// DMA ring mode wraps on an address boundary, so the buffer has to be aligned to its size
+#[repr(C, align(2048))]
+struct ToneBuffer([u32; BUFFER_LEN]);
+
+// Sine at `phase` of 1 << 16 per period, linear between LUT entries
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn sine(phase: u32) -> i32 {
+    let index = (phase >> 11) as usize % SINE.len();
+    let frac = (phase & 0x7ff) as i32;
+    let (from, to) = (i32::from(SINE[index]), i32::from(SINE[(index + 1) % SINE.len()]));
+    from + (to - from) * frac / 0x800
+}
+
+// PCM5102 style DAC: data on GPIO1, BCLK on GPIO2, LRCK on GPIO3. The same sample goes
+// to both channels.
+pub struct I2s {
+    _sm: StateMachine<Sm, Running>,
+    tx: Tx<Sm>,
+    buffer: &'static mut ToneBuffer,
+}
+
+impl I2s {
+    pub fn new(
+        pio: &mut PIO<pac::PIO0>,
+        sm: UninitStateMachine<Sm>,
+        _pins: (bsp::I2SDATA, bsp::I2SBCLK, bsp::I2SLRCK),
+        resets: &pac::RESETS,
+    ) -> Self {
+        let program = pio_proc::pio_file!("src/pio/i2s.pio", select_program("i2s"));
+        let installed = pio.install(&program.program).unwrap();
+
+        let (data_pin, clock_pin) = (1, 2); // bsp::I2SDATA, bsp::I2SBCLK and LRCK right after it
+        let (mut sm, _, tx) = PIOBuilder::from_program(installed)
+            .out_pins(data_pin, 1)
+            .side_set_pin_base(clock_pin)
+            .out_shift_direction(ShiftDirection::Left)
+            .autopull(true)
+            .pull_threshold(32)
+            .buffers(Buffers::OnlyTx)
+            .clock_divisor_fixed_point(CLOCK_DIVISOR_INT, CLOCK_DIVISOR_FRAC)
+            .build(sm);
+        sm.set_pindirs([
+            (data_pin, PinDir::Output),
+            (clock_pin, PinDir::Output),
+            (clock_pin + 1, PinDir::Output),
+        ]);
+
+        resets.reset.modify(|_, w| w.dma().clear_bit());
+        while resets.reset_done.read().dma().bit_is_clear() {}
+
+        let buffer = cortex_m::singleton!(: ToneBuffer = ToneBuffer([0; BUFFER_LEN])).unwrap();
+
+        Self {
+            _sm: sm.start(),
+            tx,
+            buffer,
+        }
+    }
+
+    pub fn is_busy() -> bool {
+        // SAFETY: only our own channel is touched
+        let dma = unsafe { &*pac::DMA::ptr() };
+        dma.ch[DMA_CHANNEL].ch_ctrl_trig.read().busy().bit_is_set()
+    }
+
+    // Starts a sine at `freq_hz` for `duration_ms`, 0 Hz is silence. False if the last
+    // one is still playing, then nothing happens.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn play_tone(&mut self, freq_hz: u32, duration_ms: u32) -> bool {
+        if Self::is_busy() {
+            return false;
+        }
+
+        // whole periods per buffer, rounded
+        let periods = (freq_hz * BUFFER_LEN as u32 + SAMPLE_RATE_HZ / 2) / SAMPLE_RATE_HZ;
+        let step = (periods << 16) / BUFFER_LEN as u32;
+        for (i, word) in self.buffer.0.iter_mut().enumerate() {
+            let sample = (sine(step.wrapping_mul(i as u32)) * AMPLITUDE / 32767) as i16 as u16;
+            *word = u32::from(sample) << 16 | u32::from(sample);
+        }
+
+        // SAFETY: only our own channel is touched, buffer is 'static and not written
+        // to again until DMA is done with it
+        let dma = unsafe { &*pac::DMA::ptr() };
+        let ch = &dma.ch[DMA_CHANNEL];
+        unsafe {
+            ch.ch_read_addr.write(|w| w.bits(self.buffer.0.as_ptr() as u32));
+            ch.ch_write_addr.write(|w| w.bits(self.tx.fifo_address() as u32));
+            ch.ch_trans_count.write(|w| w.bits(duration_ms * SAMPLE_RATE_HZ / 1000));
+            ch.ch_ctrl_trig.write(|w| {
+                w.treq_sel().bits(self.tx.dreq_value());
+                w.data_size().size_word();
+                w.incr_read().set_bit();
+                w.incr_write().clear_bit();
+                // read address wraps around the buffer
+                w.ring_sel().clear_bit();
+                w.ring_size().bits(BUFFER_RING_BITS);
+                // chaining to itself means no chaining
+                w.chain_to().bits(DMA_CHANNEL as u8);
+                w.en().set_bit()
+            });
+        }
+        true
+    }
+}
+
+// Plays a list of tones one after the other, poll() once per frame
+pub struct TonePlayer {
+    tones: Tones,
+}
+
+impl TonePlayer {
+    pub const fn new() -> Self {
+        Self { tones: &[] }
+    }
+
+    // Replaces whatever was still to come
+    pub const fn start(&mut self, tones: Tones) {
+        self.tones = tones;
+    }
+
+    pub fn poll(&mut self, i2s: &mut I2s) {
+        if let Some((&(freq_hz, duration_ms), rest)) = self.tones.split_first() {
+            if i2s.play_tone(freq_hz, duration_ms) {
+                self.tones = rest;
+            }
+        }
+    }
+}
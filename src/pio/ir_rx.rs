@@ -0,0 +1,182 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use embedded_hal::digital::v2::InputPin;
+use rp2040_hal::gpio::Interrupt;
+use rp2040_hal::pac::{self, interrupt};
+use rp2040_hal::Timer;
+
+use crate::bsp;
+
+// Edges the interrupt can get ahead of poll_received(), a NEC frame is 68
+const EDGE_BUFFER_LEN: usize = 128;
+
+// Falling edge to falling edge, in us, with some slack for cheap receivers
+const LEADER_US: core::ops::Range<u32> = 12_500..14_500; // 9 ms mark + 4.5 ms space
+const ZERO_US: core::ops::Range<u32> = 800..1_500; // 1.125 ms
+const ONE_US: core::ops::Range<u32> = 1_800..2_700; // 2.25 ms
+
+// One edge: when, and what the pin reads after it
+#[derive(Clone, Copy)]
+struct Edge {
+    time_us: u32,
+    high: bool,
+}
+
+struct IrRx {
+    pin: bsp::IRRX,
+    edges: [Edge; EDGE_BUFFER_LEN],
+    // written by the interrupt, read by poll_received(), both only ever grow
+    written: usize,
+    read: usize,
+    decoder: NecDecoder,
+}
+
+static IR_RX: Mutex<RefCell<Option<IrRx>>> = Mutex::new(RefCell::new(None));
+
+// Receiver output is low while it sees a carrier. NEC only needs the time between
+// falling edges, how long a mark looks varies a lot between receivers.
+struct NecDecoder {
+    last_fall_us: Option<u32>,
+    bits: u32,
+    // None until a leader has been seen
+    count: Option<u8>,
+}
+
+impl NecDecoder {
+    const fn new() -> Self {
+        Self {
+            last_fall_us: None,
+            bits: 0,
+            count: None,
+        }
+    }
+
+    fn edge(&mut self, edge: Edge) -> Option<(u8, u8)> {
+        if edge.high {
+            return None;
+        }
+        let previous = self.last_fall_us.replace(edge.time_us)?;
+        let interval = edge.time_us.wrapping_sub(previous);
+
+        if LEADER_US.contains(&interval) {
+            self.bits = 0;
+            self.count = Some(0);
+            return None;
+        }
+
+        let count = self.count?;
+        let bit = if ZERO_US.contains(&interval) {
+            0
+        } else if ONE_US.contains(&interval) {
+            1
+        } else {
+            // noise or a repeat code, wait for the next leader
+            self.count = None;
+            return None;
+        };
+
+        self.bits |= bit << count;
+        if count < 31 {
+            self.count = Some(count + 1);
+            return None;
+        }
+
+        self.count = None;
+        let [address, address_inv, command, command_inv] = self.bits.to_le_bytes();
+        (address == !address_inv && command == !command_inv).then_some((address, command))
+    }
+}
+
+// `_timer` is only here to make sure TIMER is out of reset before edges get timed
+pub fn init(pin: bsp::IRRX, _timer: &Timer) {
+    pin.set_interrupt_enabled(Interrupt::EdgeLow, true);
+    pin.set_interrupt_enabled(Interrupt::EdgeHigh, true);
+
+    critical_section::with(|cs| {
+        IR_RX.borrow_ref_mut(cs).replace(IrRx {
+            pin,
+            edges: [Edge { time_us: 0, high: true }; EDGE_BUFFER_LEN],
+            written: 0,
+            read: 0,
+            decoder: NecDecoder::new(),
+        });
+    });
+
+    // SAFETY: handler is in place and state is set up
+    unsafe { pac::NVIC::unmask(pac::Interrupt::IO_IRQ_BANK0) };
+}
+
+// Decodes whatever edges came in since last time, (address, command) of a complete frame
+pub fn poll_received() -> Option<(u8, u8)> {
+    critical_section::with(|cs| {
+        let mut ir = IR_RX.borrow_ref_mut(cs);
+        let ir = ir.as_mut()?;
+
+        // fell too far behind, the oldest ones are overwritten already
+        if ir.written - ir.read > EDGE_BUFFER_LEN {
+            ir.read = ir.written - EDGE_BUFFER_LEN;
+            ir.decoder = NecDecoder::new();
+        }
+
+        let mut received = None;
+        while ir.read < ir.written {
+            let edge = ir.edges[ir.read % EDGE_BUFFER_LEN];
+            ir.read += 1;
+            if let Some(message) = ir.decoder.edge(edge) {
+                received = Some(message);
+            }
+        }
+        received
+    })
+}
+
+#[interrupt]
+fn IO_IRQ_BANK0() {
+    // SAFETY: only reading the free running counter
+    let time_us = unsafe { (*pac::TIMER::ptr()).timerawl.read().bits() };
+    // the button's edges come in on the same interrupt
+    crate::input::on_button_edge(time_us);
+
+    critical_section::with(|cs| {
+        if let Some(ir) = IR_RX.borrow_ref_mut(cs).as_mut() {
+            let fell = ir.pin.interrupt_status(Interrupt::EdgeLow);
+            let rose = ir.pin.interrupt_status(Interrupt::EdgeHigh);
+            ir.pin.clear_interrupt(Interrupt::EdgeLow);
+            ir.pin.clear_interrupt(Interrupt::EdgeHigh);
+            if fell || rose {
+                let high = ir.pin.is_high().unwrap();
+                ir.edges[ir.written % EDGE_BUFFER_LEN] = Edge { time_us, high };
+                ir.written += 1;
+            }
+        }
+    });
+}
+
+// Badge ids we've heard lately, oldest gets pushed out
+pub struct SeenBadges {
+    ids: [u8; 32],
+    next: usize,
+    count: usize,
+}
+
+impl SeenBadges {
+    pub const fn new() -> Self {
+        Self {
+            ids: [0; 32],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    // True if this one is new to us
+    pub fn record(&mut self, id: u8) -> bool {
+        if self.ids[..self.count].contains(&id) {
+            return false;
+        }
+        self.ids[self.next] = id;
+        self.next = (self.next + 1) % self.ids.len();
+        self.count = (self.count + 1).min(self.ids.len());
+        true
+    }
+}
@@ -0,0 +1,116 @@
+use rp2040_hal::pac;
+use rp2040_hal::pio::{
+    PIOBuilder, PinDir, Running, ShiftDirection, StateMachine, Tx, UninitStateMachine, PIO, SM1,
+};
+
+use crate::bsp;
+
+// NEC address all alpakka badges send with, so we can tell them from TV remotes
+pub const BADGE_NEC_ADDRESS: u8 = 0xa1;
+
+// DMA channel that feeds the state machine, 0 and 1 are taken by uart_log and ws2812
+const DMA_CHANNEL: usize = 2;
+
+// 38 kHz carrier * 8 cycles per period = 304 kHz out of 125 MHz: 411 + 46/256
+const CLOCK_DIVISOR_INT: u16 = 411;
+const CLOCK_DIVISOR_FRAC: u8 = 46;
+
+// NEC timings in 26.3 us carrier periods
+const LEADER_MARK: u32 = 342; // 9 ms
+const LEADER_SPACE: u32 = 171; // 4.5 ms
+const BIT_MARK: u32 = 21; // 562.5 us
+const ZERO_SPACE: u32 = 21; // 562.5 us
+const ONE_SPACE: u32 = 64; // 1687.5 us
+
+// leader, 32 bits as mark + space, stop mark
+const FRAME_WORDS: usize = 2 + 32 * 2 + 1;
+
+type Sm = (pac::PIO0, SM1);
+
+const fn mark(periods: u32) -> u32 {
+    ((periods - 1) << 1) | 1
+}
+
+const fn space(periods: u32) -> u32 {
+    (periods - 1) << 1
+}
+
+// IR LED on GPIO28. A NEC frame takes almost 70 ms, so it is built up front and
+// handed to DMA instead of feeding the FIFO from the main loop.
+pub struct IrTx {
+    _sm: StateMachine<Sm, Running>,
+    tx: Tx<Sm>,
+    frame: &'static mut [u32; FRAME_WORDS],
+}
+
+impl IrTx {
+    pub fn new(pio: &mut PIO<pac::PIO0>, sm: UninitStateMachine<Sm>, _pin: bsp::IRTX, resets: &pac::RESETS) -> Self {
+        let program = pio_proc::pio_file!("src/pio/ir_tx.pio", select_program("ir_tx"));
+        let installed = pio.install(&program.program).unwrap();
+
+        let pin_id = 28; // bsp::IRTX
+        let (mut sm, _, tx) = PIOBuilder::from_program(installed)
+            .set_pins(pin_id, 1)
+            // mark bit comes out first
+            .out_shift_direction(ShiftDirection::Right)
+            .clock_divisor_fixed_point(CLOCK_DIVISOR_INT, CLOCK_DIVISOR_FRAC)
+            .build(sm);
+        sm.set_pindirs([(pin_id, PinDir::Output)]);
+
+        resets.reset.modify(|_, w| w.dma().clear_bit());
+        while resets.reset_done.read().dma().bit_is_clear() {}
+
+        let frame = cortex_m::singleton!(: [u32; FRAME_WORDS] = [0; FRAME_WORDS]).unwrap();
+
+        Self {
+            _sm: sm.start(),
+            tx,
+            frame,
+        }
+    }
+
+    fn dma_busy() -> bool {
+        // SAFETY: only our own channel is touched
+        let dma = unsafe { &*pac::DMA::ptr() };
+        dma.ch[DMA_CHANNEL].ch_ctrl_trig.read().busy().bit_is_set()
+    }
+
+    // Starts sending a NEC frame, false if the previous one hasn't gone out yet
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn send_nec(&mut self, address: u8, command: u8) -> bool {
+        if Self::dma_busy() {
+            return false;
+        }
+
+        // bytes go out LSB first, each followed by its inverse
+        let bits = u32::from_le_bytes([address, !address, command, !command]);
+        self.frame[0] = mark(LEADER_MARK);
+        self.frame[1] = space(LEADER_SPACE);
+        for i in 0..32 {
+            let one = bits & (1 << i) != 0;
+            self.frame[2 + i * 2] = mark(BIT_MARK);
+            self.frame[3 + i * 2] = space(if one { ONE_SPACE } else { ZERO_SPACE });
+        }
+        self.frame[FRAME_WORDS - 1] = mark(BIT_MARK);
+
+        // SAFETY: only our own channel is touched, frame is 'static and not written
+        // to again until DMA is done with it
+        let dma = unsafe { &*pac::DMA::ptr() };
+        let ch = &dma.ch[DMA_CHANNEL];
+        unsafe {
+            ch.ch_read_addr.write(|w| w.bits(self.frame.as_ptr() as u32));
+            ch.ch_write_addr.write(|w| w.bits(self.tx.fifo_address() as u32));
+            ch.ch_trans_count.write(|w| w.bits(FRAME_WORDS as u32));
+            ch.ch_ctrl_trig.write(|w| {
+                w.treq_sel().bits(self.tx.dreq_value());
+                w.data_size().size_word();
+                w.incr_read().set_bit();
+                w.incr_write().clear_bit();
+                // chaining to itself means no chaining
+                w.chain_to().bits(DMA_CHANNEL as u8);
+                w.en().set_bit()
+            });
+        }
+        true
+    }
+}
@@ -0,0 +1,7 @@
+#[cfg(feature = "encoder")]
+pub mod encoder;
+pub mod i2s;
+pub mod ir_rx;
+pub mod ir_tx;
+pub mod touch;
+pub mod ws2812;
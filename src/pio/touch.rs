@@ -0,0 +1,38 @@
+use rp2040_hal::pac;
+use rp2040_hal::pio::{PIOBuilder, PinDir, Running, Rx, StateMachine, UninitStateMachine, PIO, SM0};
+
+use crate::bsp;
+
+type Sm = (pac::PIO1, SM0);
+
+// Copper pad on GPIO26, 1 MOhm up to 3V3. PIO1 is all ours, PIO0 is full.
+// Counts at the full 125 MHz, a pad charges in a few us so that's hundreds per sample.
+pub struct TouchPad {
+    _sm: StateMachine<Sm, Running>,
+    rx: Rx<Sm>,
+}
+
+impl TouchPad {
+    pub fn new(pio: &mut PIO<pac::PIO1>, sm: UninitStateMachine<Sm>, _pin: bsp::TouchPad) -> Self {
+        let program = pio_proc::pio_file!("src/pio/touch.pio", select_program("touch"));
+        let installed = pio.install(&program.program).unwrap();
+
+        let pin_id = 26; // bsp::TouchPad
+        let (mut sm, rx, _) = PIOBuilder::from_program(installed)
+            .set_pins(pin_id, 1)
+            .jmp_pin(pin_id)
+            .build(sm);
+        sm.set_pindirs([(pin_id, PinDir::Input)]);
+
+        Self { _sm: sm.start(), rx }
+    }
+
+    // Newest charge time, None if there hasn't been a new one since last time
+    pub fn read(&mut self) -> Option<u32> {
+        let mut latest = None;
+        while let Some(count) = self.rx.read() {
+            latest = Some(count);
+        }
+        latest
+    }
+}
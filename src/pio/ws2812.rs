@@ -0,0 +1,108 @@
+use rp2040_hal::pac;
+use rp2040_hal::pio::{
+    Buffers, PIOBuilder, Running, ShiftDirection, StateMachine, Tx, UninitStateMachine, PIO, SM0,
+};
+
+use crate::bsp;
+
+// Most strips can't do more than this in a 10 ms frame anyway
+pub const MAX_PIXELS: usize = 16;
+
+// GRB, one byte each
+pub const BYTES_PER_PIXEL: usize = 3;
+
+// DMA channel that feeds the state machine, uart_log has channel 0
+const DMA_CHANNEL: usize = 1;
+
+// 800 kHz * 10 cycles per bit = 8 MHz out of 125 MHz system clock: 15 + 160/256
+const CLOCK_DIVISOR_INT: u16 = 15;
+const CLOCK_DIVISOR_FRAC: u8 = 160;
+
+type Sm = (pac::PIO0, SM0);
+
+// WS2812 strip on GPIO16. The buffer is sent with DMA byte at a time, a byte written to
+// the TX FIFO ends up in every lane of the word and the state machine only takes the top 8 bits.
+pub struct Ws2812 {
+    _sm: StateMachine<Sm, Running>,
+    tx: Tx<Sm>,
+    buffer: &'static mut [u8; MAX_PIXELS * BYTES_PER_PIXEL],
+}
+
+impl Ws2812 {
+    pub fn new(
+        pio: &mut PIO<pac::PIO0>,
+        sm: UninitStateMachine<Sm>,
+        _pin: bsp::NEOPIXEL,
+        resets: &pac::RESETS,
+    ) -> Self {
+        let program = pio_proc::pio_file!("src/pio/ws2812.pio", select_program("ws2812"));
+        let installed = pio.install(&program.program).unwrap();
+
+        let pin_id = 16; // bsp::NEOPIXEL
+        let (mut sm, _, tx) = PIOBuilder::from_program(installed)
+            .side_set_pin_base(pin_id)
+            .out_shift_direction(ShiftDirection::Left)
+            .autopull(true)
+            .pull_threshold(8)
+            .buffers(Buffers::OnlyTx)
+            .clock_divisor_fixed_point(CLOCK_DIVISOR_INT, CLOCK_DIVISOR_FRAC)
+            .build(sm);
+        sm.set_pindirs([(pin_id, rp2040_hal::pio::PinDir::Output)]);
+
+        resets.reset.modify(|_, w| w.dma().clear_bit());
+        while resets.reset_done.read().dma().bit_is_clear() {}
+
+        let buffer = cortex_m::singleton!(: [u8; MAX_PIXELS * BYTES_PER_PIXEL] = [0; MAX_PIXELS * BYTES_PER_PIXEL]).unwrap();
+
+        Self {
+            _sm: sm.start(),
+            tx,
+            buffer,
+        }
+    }
+
+    fn dma_busy() -> bool {
+        // SAFETY: only our own channel is touched
+        let dma = unsafe { &*pac::DMA::ptr() };
+        dma.ch[DMA_CHANNEL].ch_ctrl_trig.read().busy().bit_is_set()
+    }
+
+    // Starts sending GRB `pixels`, anything past MAX_PIXELS is cut. False if the
+    // previous frame is still going out, then nothing happens.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn write(&mut self, pixels: &[u8]) -> bool {
+        if Self::dma_busy() {
+            return false;
+        }
+
+        let len = pixels.len().min(self.buffer.len());
+        self.buffer[..len].copy_from_slice(&pixels[..len]);
+
+        // SAFETY: only our own channel is touched, buffer is 'static and not written
+        // to again until DMA is done with it
+        let dma = unsafe { &*pac::DMA::ptr() };
+        let ch = &dma.ch[DMA_CHANNEL];
+        unsafe {
+            ch.ch_read_addr.write(|w| w.bits(self.buffer.as_ptr() as u32));
+            ch.ch_write_addr.write(|w| w.bits(self.tx.fifo_address() as u32));
+            ch.ch_trans_count.write(|w| w.bits(len as u32));
+            ch.ch_ctrl_trig.write(|w| {
+                w.treq_sel().bits(self.tx.dreq_value());
+                w.data_size().size_byte();
+                w.incr_read().set_bit();
+                w.incr_write().clear_bit();
+                // chaining to itself means no chaining
+                w.chain_to().bits(DMA_CHANNEL as u8);
+                w.en().set_bit()
+            });
+        }
+        true
+    }
+}
+
+// Puts one pixel in a GRB buffer, out of range is ignored
+pub fn set_pixel(pixels: &mut [u8], index: usize, (r, g, b): (u8, u8, u8)) {
+    if let Some(pixel) = pixels.chunks_exact_mut(BYTES_PER_PIXEL).nth(index) {
+        pixel.copy_from_slice(&[g, r, b]);
+    }
+}
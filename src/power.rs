@@ -0,0 +1,522 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use embedded_hal::PwmPin;
+use rp2040_hal::adc::Adc;
+use rp2040_hal::clocks::{Clock, ClocksManager, StoppableClock};
+use rp2040_hal::gpio::{Pin, PinId, PinMode, ValidPinMode};
+use rp2040_hal::pac;
+
+use crate::adc_utils;
+use crate::bsp;
+
+// Last measured charge, animations can read this to dim themselves
+pub static BATTERY_PERCENT: AtomicU8 = AtomicU8::new(100);
+
+// Coarse CR2032 discharge curve (volts, percent), highest voltage first.
+// The cell sits around 2.9 V most of its life and falls off quickly at the end.
+const CR2032_DISCHARGE_CURVE: [(f32, u8); 7] = [
+    (3.0, 100),
+    (2.9, 80),
+    (2.8, 50),
+    (2.7, 30),
+    (2.5, 15),
+    (2.2, 5),
+    (2.0, 0),
+];
+
+// LEDs get dimmer as the cell does, so the last of it lasts longer (millivolts, percent
+// of the configured brightness), highest voltage first. Linear in between.
+const CR2032_BRIGHTNESS_CURVE: [(u16, u8); 3] = [(3000, 100), (2400, 50), (2000, 20)];
+
+// What PwmChannels scales every duty by, core 0 updates it with the battery check
+pub static BRIGHTNESS_SCALE: AtomicU8 = AtomicU8::new(100);
+
+pub fn battery_voltage(adc: &mut Adc, vsys_sense: &mut bsp::VsysSense) -> f32 {
+    adc_utils::read_vsys(adc, vsys_sense)
+}
+
+// Piecewise-linear lookup from the discharge curve
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn battery_percent(volts: f32) -> u8 {
+    let (top_volts, top_percent) = CR2032_DISCHARGE_CURVE[0];
+    if volts >= top_volts {
+        return top_percent;
+    }
+
+    for pair in CR2032_DISCHARGE_CURVE.windows(2) {
+        let (hi_volts, hi_percent) = pair[0];
+        let (lo_volts, lo_percent) = pair[1];
+        if volts >= lo_volts {
+            let t = (volts - lo_volts) / (hi_volts - lo_volts);
+            let percent = f32::from(lo_percent) + t * f32::from(hi_percent - lo_percent);
+            return (percent + 0.5) as u8;
+        }
+    }
+
+    0
+}
+
+// Percent of the configured brightness to use at `battery_mv`, 100 on a fresh cell
+// (or USB) down to 20 near cutoff, never lower
+#[allow(clippy::cast_possible_truncation)]
+pub fn compute_brightness_scale(battery_mv: u16) -> u8 {
+    let (top_mv, top_scale) = CR2032_BRIGHTNESS_CURVE[0];
+    if battery_mv >= top_mv {
+        return top_scale;
+    }
+
+    for pair in CR2032_BRIGHTNESS_CURVE.windows(2) {
+        let (hi_mv, hi_scale) = pair[0];
+        let (lo_mv, lo_scale) = pair[1];
+        if battery_mv >= lo_mv {
+            let above = u32::from(battery_mv - lo_mv);
+            let scale = u32::from(lo_scale) + above * u32::from(hi_scale - lo_scale) / u32::from(hi_mv - lo_mv);
+            return scale as u8;
+        }
+    }
+
+    CR2032_BRIGHTNESS_CURVE[CR2032_BRIGHTNESS_CURVE.len() - 1].1
+}
+
+// RP2040 is rated for 85 C junction and the LEDs sit right on top of it, so they back
+// off well before that
+pub const THROTTLE_CELSIUS: u16 = 70;
+pub const OVERHEAT_CELSIUS: u16 = 80;
+
+// Goes on top of BRIGHTNESS_SCALE, core 0 updates it with every temperature measurement
+pub static THERMAL_SCALE: AtomicU8 = AtomicU8::new(100);
+
+// Percent of brightness the LEDs get at a die temperature of `temp_celsius`
+pub const fn thermal_throttle(temp_celsius: u16) -> u8 {
+    if temp_celsius > OVERHEAT_CELSIUS {
+        0
+    } else if temp_celsius > THROTTLE_CELSIUS {
+        50
+    } else {
+        100
+    }
+}
+
+// Low battery look: eyes fade down to 10% of where they were and the heart
+// does a slow single beat, so it's clear we're saving power and not just cold.
+pub struct LowBatteryMode<'a> {
+    eyes: [&'a mut dyn PwmPin<Duty = u16>; 6],
+    heart: &'a mut dyn PwmPin<Duty = u16>,
+    eye_start: [u16; 6],
+    max_heart_duty: u16,
+    ticks: u32,
+}
+
+impl<'a> LowBatteryMode<'a> {
+    // ticks for the eyes to fade down
+    const FADE_TICKS: u32 = 100;
+    // one heart beat per this many ticks
+    const BEAT_TICKS: u32 = 150;
+    // heart beat fades out over this many ticks
+    const BEAT_FADE_TICKS: u32 = 50;
+
+    pub fn new(
+        eyes: [&'a mut dyn PwmPin<Duty = u16>; 6],
+        heart: &'a mut dyn PwmPin<Duty = u16>,
+        max_heart_duty: u16,
+    ) -> Self {
+        // the heart is written straight to the pin, under the slice's wrap like PwmChannels does
+        let max_heart_duty = bsp::scale_duty(max_heart_duty, heart.get_max_duty());
+        let mut eye_start = [0; 6];
+        for (start, eye) in eye_start.iter_mut().zip(eyes.iter()) {
+            *start = eye.get_duty();
+        }
+        Self {
+            eyes,
+            heart,
+            eye_start,
+            max_heart_duty,
+            ticks: 0,
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn tick(&mut self) {
+        let fade = self.ticks.min(Self::FADE_TICKS);
+        for (eye, &start) in self.eyes.iter_mut().zip(self.eye_start.iter()) {
+            let target = u32::from(start) / 10;
+            let duty = u32::from(start) - (u32::from(start) - target) * fade / Self::FADE_TICKS;
+            eye.set_duty(duty as u16);
+        }
+
+        let beat = self.ticks % Self::BEAT_TICKS;
+        let remaining = Self::BEAT_FADE_TICKS.saturating_sub(beat);
+        self.heart
+            .set_duty((u32::from(self.max_heart_duty) * remaining / Self::BEAT_FADE_TICKS) as u16);
+
+        self.ticks = self.ticks.wrapping_add(1);
+    }
+}
+
+// Under ~1.8 V the RP2040 goes erratic, shut down before that happens
+pub const BROWNOUT_THRESHOLD_MV: u32 = 1900;
+
+// Getting close, time to save whatever power we can
+pub const BROWNOUT_WARNING_MV: u32 = 2100;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BrownoutStatus {
+    Ok,
+    Warning,
+    Critical,
+}
+
+pub const fn check_brownout(vsys_mv: u32) -> BrownoutStatus {
+    if vsys_mv < BROWNOUT_THRESHOLD_MV {
+        BrownoutStatus::Critical
+    } else if vsys_mv < BROWNOUT_WARNING_MV {
+        BrownoutStatus::Warning
+    } else {
+        BrownoutStatus::Ok
+    }
+}
+
+// Stops the crystal oscillator and everything clocked from it. No wake source
+// is set up, so the badge stays dark until it is power cycled.
+pub fn enter_dormant() -> ! {
+    // taken from the C SDK, "coma"
+    const XOSC_DORMANT_VALUE: u32 = 0x636f_6d61;
+
+    // SAFETY: nothing runs after this, clocks stop and we never come back
+    unsafe {
+        (*pac::XOSC::ptr()).dormant.write(|w| w.bits(XOSC_DORMANT_VALUE));
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+// What can get the badge out of dormant
+pub struct WakePins<'a> {
+    // falling edge, it pulls low when pressed
+    pub button: &'a mut bsp::Button,
+    // LIS3DH INT1 rising edge, only if the board has it wired to a GPIO
+    pub accel_int_gpio: Option<u8>,
+}
+
+// Per GPIO four bits in the wake and raw interrupt registers: level low, level high,
+// edge low, edge high
+const EDGE_LOW: u32 = 1 << 2;
+const EDGE_HIGH: u32 = 1 << 3;
+
+// LED PWM slices, see bsp
+const LED_SLICES: core::ops::RangeInclusive<usize> = 3..=7;
+
+// clk_sys_selected is one hot over the glitchless mux inputs
+const CLK_SYS_SELECTED_REF: u32 = 1 << 0;
+const CLK_SYS_SELECTED_AUX: u32 = 1 << 1;
+
+const fn gpio_num<I: PinId, M: PinMode + ValidPinMode<I>>(_pin: &Pin<I, M>) -> u8 {
+    I::DYN.num
+}
+
+fn set_dormant_wake(io: &pac::io_bank0::RegisterBlock, gpio: u8, edge: u32, enabled: bool) {
+    let (register, shift) = (usize::from(gpio / 8), u32::from(gpio % 8) * 4);
+    // edges are latched, an old one would wake us right away
+    // SAFETY: write one to clear, only this gpio's edge bit is set
+    io.intr[register].write(|w| unsafe { w.bits(edge << shift) });
+    io.dormant_wake_inte[register].modify(|r, w| unsafe {
+        w.bits(if enabled { r.bits() | edge << shift } else { r.bits() & !(edge << shift) })
+    });
+}
+
+fn pll_power(pll: &pac::pll_sys::RegisterBlock, on: bool) {
+    if on {
+        // VCO first and wait for lock, post dividers only then, same as at boot
+        pll.pwr.modify(|_, w| w.pd().clear_bit().vcopd().clear_bit());
+        while pll.cs.read().lock().bit_is_clear() {}
+        pll.pwr.modify(|_, w| w.postdivpd().clear_bit());
+    } else {
+        pll.pwr.modify(|_, w| w.pd().set_bit().vcopd().set_bit().postdivpd().set_bit());
+    }
+}
+
+// Sleeps at about 10 uA until the button, or the accelerometer if it's wired, wakes us.
+// Core 0 only, and not with USB connected, the host would see the device vanish.
+//
+// Going down:
+//   1. core 1 is parked in RAM, it can't run without clk_sys anyway
+//   2. all LEDs off at the PWM registers, core 1 repaints them after waking
+//   3. wake edges armed, old latched edges cleared
+//   4. clk_sys moved from PLL_SYS to clk_ref, which runs straight off the crystal,
+//      then both PLLs powered down
+//   5. XOSC to dormant, execution stops right here
+//
+// Coming back is not a boot: RAM, registers and every peripheral's setup are still there,
+// only the clocks stopped. So in this order:
+//   1. wait for XOSC to be stable, clk_ref and the microsecond tick run again
+//   2. PLLs back up and locked, clk_sys back on PLL_SYS, clk_peri and clk_usb/clk_adc
+//      follow since they kept their sources
+//   3. ADC waits for its clock to settle, free running conversions pick up on their own
+//   4. PWM slices kept their config and just carry on
+//   5. core 1 unparked
+// The timer was stopped meanwhile too, uptime doesn't count the time asleep.
+pub fn enter_dormant_mode(pins: &mut WakePins) {
+    // taken from the C SDK, "coma"
+    const XOSC_DORMANT_VALUE: u32 = 0x636f_6d61;
+
+    let button = gpio_num(pins.button);
+    // interrupts off throughout, the wake press has to be gone before IO_IRQ_BANK0 runs
+    crate::core1::parked(|| {
+        cortex_m::interrupt::free(|_| {
+            // SAFETY: core 1 is parked and core 0 is in here, nothing else touches these meanwhile
+            let (pwm, io, clocks, xosc, adc) = unsafe {
+                (
+                    &*pac::PWM::ptr(),
+                    &*pac::IO_BANK0::ptr(),
+                    &*pac::CLOCKS::ptr(),
+                    &*pac::XOSC::ptr(),
+                    &*pac::ADC::ptr(),
+                )
+            };
+            let (pll_sys, pll_usb) = unsafe { (&*pac::PLL_SYS::ptr(), &*pac::PLL_USB::ptr()) };
+
+            for slice in LED_SLICES {
+                pwm.ch[slice].cc.write(|w| unsafe { w.a().bits(0).b().bits(0) });
+            }
+
+            set_dormant_wake(io, button, EDGE_LOW, true);
+            if let Some(gpio) = pins.accel_int_gpio {
+                set_dormant_wake(io, gpio, EDGE_HIGH, true);
+            }
+
+            clocks.clk_sys_ctrl.modify(|_, w| w.src().clk_ref());
+            while clocks.clk_sys_selected.read().bits() & CLK_SYS_SELECTED_REF == 0 {}
+            pll_power(pll_sys, false);
+            pll_power(pll_usb, false);
+
+            xosc.dormant.write(|w| unsafe { w.bits(XOSC_DORMANT_VALUE) });
+            while xosc.status.read().stable().bit_is_clear() {}
+
+            pll_power(pll_sys, true);
+            pll_power(pll_usb, true);
+            clocks.clk_sys_ctrl.modify(|_, w| w.src().clksrc_clk_sys_aux());
+            while clocks.clk_sys_selected.read().bits() & CLK_SYS_SELECTED_AUX == 0 {}
+
+            set_dormant_wake(io, button, EDGE_LOW, false);
+            if let Some(gpio) = pins.accel_int_gpio {
+                set_dormant_wake(io, gpio, EDGE_HIGH, false);
+            }
+            crate::input::clear_button_edges();
+
+            // might have gone to sleep with it gated mid conversion
+            set_adc_clock(true);
+            while adc.cs.read().ready().bit_is_clear() {}
+        });
+    });
+}
+
+// After boot every clock generator runs whether anything uses it or not. Rough figures
+// from the RP2040 datasheet (2.15 Clocks and the power consumption tables in chapter 5),
+// not measured on the badge:
+//   - clk_usb, 48 MHz into the USB controller, around 1 mA with nothing plugged in
+//   - clk_adc, 48 MHz into the ADC, a bit under 1 mA while it converts
+//   - clk_rtc, nothing uses the RTC, next to nothing but free
+//   - clk_sys, 125 MHz into both cores, the bus and most peripherals. Dynamic current
+//     goes with frequency, at 18 MHz it's a seventh of what it was
+// The SoC alone would go from about 5 mA to somewhere near 3 mA with the first three
+// off, and to about 1 mA with clk_sys down as well.
+//
+// clk_usb and clk_rtc go at boot, clk_adc only runs around the ADC reads (see
+// set_adc_clock) and clk_sys comes down while nothing needs it fast, see
+// set_sys_clock_slow.
+//
+// Raw registers after boot since the hal's clock handles are spread over the drivers by
+// now, clk_usb went to the USB bus.
+const fn clock_regs() -> &'static pac::clocks::RegisterBlock {
+    // SAFETY: only the enables and clk_sys' divider are written after boot, all from core 0
+    unsafe { &*pac::CLOCKS::ptr() }
+}
+
+// What init_clocks_and_plls gives clk_sys on a Pico
+pub const NOMINAL_SYS_CLOCK_HZ: u32 = 125_000_000;
+
+// What init_clocks_and_plls made clk_sys, see configure_low_power_clocks
+static FULL_SYS_CLOCK_HZ: AtomicU32 = AtomicU32::new(NOMINAL_SYS_CLOCK_HZ);
+// 1 at full speed, SLOW_SYS_CLOCK_DIV slowed down
+static SYS_CLOCK_DIV: AtomicU32 = AtomicU32::new(1);
+
+// 125 MHz / 7 = 17.9 MHz. An integer, so clk_sys doesn't jitter between two periods.
+pub const SLOW_SYS_CLOCK_DIV: u32 = 7;
+
+pub fn sys_clock_divider() -> u32 {
+    SYS_CLOCK_DIV.load(Ordering::Relaxed)
+}
+
+pub fn sys_clock_hz() -> u32 {
+    FULL_SYS_CLOCK_HZ.load(Ordering::Relaxed) / sys_clock_divider()
+}
+
+// Turns off what isn't needed, `usb_in_use` keeps clk_usb going for the host.
+// USB only gets initialized with VBUS there, until then its clock can go too.
+// Before anything takes the clocks apart, clk_usb goes to the USB bus right after.
+pub fn configure_low_power_clocks(clocks: &mut ClocksManager, usb_in_use: bool) {
+    FULL_SYS_CLOCK_HZ.store(clocks.system_clock.freq().to_Hz(), Ordering::Relaxed);
+    clocks.rtc_clock.disable();
+    if !usb_in_use {
+        clocks.usb_clock.disable();
+    }
+}
+
+// Full speed dividers of all eight state machines, PIO0 first, kept while slowed down
+static PIO_FULL_SPEED_CLKDIV: [AtomicU32; 8] = [const { AtomicU32::new(0) }; 8];
+
+// clk_sys down to 1/SLOW_SYS_CLOCK_DIV, or back up. Only for modes that hardly need the
+// cores and never on USB, there's power to spare there and the host wants answers.
+//
+// Whatever got its divider from clk_sys at boot gets a new one:
+//   - LED PWM keeps LED_PWM_FREQ_HZ with a smaller wrap, duties are scaled along
+//   - the PIO state machines divide by 7 less, the strip, IR carrier and I2S keep their
+//     timing. The touch pad is already at 1, its counts get scaled instead, see
+//     TouchSensor
+//   - I2C0 and the UART get new SCL counts and baud divisors
+//   - SPI0 just runs slower and SpiDevice's delays get longer, neither hurts
+//   - the buzzer picks up sys_clock_hz() with the next note, main's SysTick delay gets
+//     made again
+// The timer and the watchdog tick run from clk_ref, they don't notice.
+pub fn set_sys_clock_slow(slow: bool) {
+    let div = if slow { SLOW_SYS_CLOCK_DIV } else { 1 };
+    if div == sys_clock_divider() {
+        return;
+    }
+    // what's still going out would come out garbled
+    #[cfg(feature = "uart-log")]
+    crate::uart_log::UartLogger::wait_idle();
+
+    // core 1 would write a duty for the old wrap in between
+    crate::core1::parked(|| {
+        cortex_m::interrupt::free(|_| {
+            // SAFETY: CLK_SYS_DIV may change on the fly, says its description in the datasheet
+            clock_regs().clk_sys_div.write(|w| unsafe { w.int().bits(div) });
+            SYS_CLOCK_DIV.store(div, Ordering::Relaxed);
+            let hz = sys_clock_hz();
+
+            rescale_led_pwm(hz);
+            rescale_pio(slow);
+            bsp::set_i2c0_clock(hz, bsp::I2C0_DEFAULT_FREQ_HZ);
+            // clk_peri runs from clk_sys undivided
+            #[cfg(feature = "uart-log")]
+            crate::uart_log::UartLogger::set_peripheral_clock(hz);
+        });
+    });
+}
+
+pub fn sys_clock_slow() -> bool {
+    sys_clock_divider() != 1
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn rescale_led_pwm(sys_clock_hz: u32) {
+    // SAFETY: core 1 is parked and the LED slices are only ever written by it otherwise
+    let pwm = unsafe { &*pac::PWM::ptr() };
+    let (div_int, div_frac, top) = bsp::pwm_dividers(bsp::LED_PWM_MODE, bsp::LED_PWM_FREQ_HZ, sys_clock_hz);
+    for slice in LED_SLICES {
+        let ch = &pwm.ch[slice];
+        let old_wrap = u32::from(ch.top.read().top().bits()) + 1;
+        // fully on is top + 1, that stays fully on
+        let scale = |duty: u16| (u32::from(duty) * (u32::from(top) + 1) / old_wrap).min(0xffff) as u16;
+        let cc = ch.cc.read();
+        let (a, b) = (scale(cc.a().bits()), scale(cc.b().bits()));
+        ch.div.write(|w| unsafe { w.int().bits(div_int).frac().bits(div_frac) });
+        ch.top.write(|w| unsafe { w.top().bits(top) });
+        ch.cc.write(|w| unsafe { w.a().bits(a).b().bits(b) });
+    }
+}
+
+fn rescale_pio(slow: bool) {
+    // SAFETY: only the dividers are written, the state machines keep running
+    let pios = unsafe { [&*pac::PIO0::ptr(), &*pac::PIO1::ptr()] };
+    let state_machines = pios.iter().flat_map(|pio| pio.sm.iter());
+    for (sm, full_speed) in state_machines.zip(&PIO_FULL_SPEED_CLKDIV) {
+        if slow {
+            let clkdiv = sm.sm_clkdiv.read().bits();
+            full_speed.store(clkdiv, Ordering::Relaxed);
+            // 16.8 fixed point in the top 24 bits, an integer part of 0 means 65536
+            let div = match clkdiv >> 8 {
+                0 => 0x1_0000 << 8,
+                div => div,
+            };
+            let slowed = (div / SLOW_SYS_CLOCK_DIV).max(1 << 8);
+            sm.sm_clkdiv.write(|w| unsafe { w.bits(slowed << 8) });
+        } else {
+            sm.sm_clkdiv.write(|w| unsafe { w.bits(full_speed.load(Ordering::Relaxed)) });
+        }
+    }
+}
+
+// Back on before USB gets initialized late, see PowerProfile
+pub fn set_usb_clock(on: bool) {
+    clock_regs().clk_usb_ctrl.modify(|_, w| w.enable().bit(on));
+}
+
+// clk_adc only runs while we sample, conversions just stall while it's off.
+// Free running needs 64 ms with it on to fill adc_dma's ring again.
+pub fn set_adc_clock(on: bool) {
+    clock_regs().clk_adc_ctrl.modify(|_, w| w.enable().bit(on));
+}
+
+pub fn adc_clock_enabled() -> bool {
+    clock_regs().clk_adc_ctrl.read().enable().bit_is_set()
+}
+
+// One-shot reads wait for the ADC, that never happens without its clock
+pub fn with_adc_clock<T>(f: impl FnOnce() -> T) -> T {
+    let was_on = adc_clock_enabled();
+    set_adc_clock(true);
+    let result = f();
+    set_adc_clock(was_on);
+    result
+}
+
+// Brightness ceiling in percent, on USB there's power to spare and the CR2032 needs
+// looking after
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PowerProfile {
+    Usb { max_brightness: u16 },
+    Battery { max_brightness: u16 },
+}
+
+pub const USB_PROFILE: PowerProfile = PowerProfile::Usb { max_brightness: 100 };
+pub const BATTERY_PROFILE: PowerProfile = PowerProfile::Battery { max_brightness: 50 };
+
+impl PowerProfile {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Usb { .. } => "usb",
+            Self::Battery { .. } => "battery",
+        }
+    }
+
+    // `percent` cut down to what this profile allows
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn limit(self, percent: u8) -> u8 {
+        let (Self::Usb { max_brightness } | Self::Battery { max_brightness }) = self;
+        if max_brightness < percent as u16 {
+            max_brightness as u8
+        } else {
+            percent
+        }
+    }
+}
+
+// Pico routes VBUS through a divider to GPIO24, high whenever a cable brings power
+static ON_USB: AtomicBool = AtomicBool::new(false);
+
+pub fn update_power_profile(vbus_present: bool) -> PowerProfile {
+    ON_USB.store(vbus_present, Ordering::Relaxed);
+    power_profile()
+}
+
+pub fn power_profile() -> PowerProfile {
+    if ON_USB.load(Ordering::Relaxed) {
+        USB_PROFILE
+    } else {
+        BATTERY_PROFILE
+    }
+}
@@ -0,0 +1,53 @@
+use qrcodegen_no_heap::{QrCode, QrCodeEcc, Version};
+
+// Version 3 is 29x29 modules, the biggest that fits 32 rows at one pixel a module
+const MAX_VERSION: Version = Version::new(3);
+// version 2, 25x25, is as small as it goes, below that the modules get too sparse to bother
+const MIN_VERSION: Version = Version::new(2);
+pub const MAX_SIZE: usize = 29;
+
+#[derive(Debug)]
+pub enum Error {
+    // more than version 3 can hold
+    TooLong,
+}
+
+// The encoded code, one bit per module, set is dark. Row y, bit x.
+#[derive(Clone, Copy)]
+pub struct QrBitmap {
+    rows: [u32; MAX_SIZE],
+    size: u8,
+}
+
+impl QrBitmap {
+    // Modules per side
+    pub const fn size(&self) -> u8 {
+        self.size
+    }
+
+    pub const fn is_dark(&self, x: u8, y: u8) -> bool {
+        self.rows[y as usize] & (1 << x) != 0
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn encode(url: &str) -> Result<QrBitmap, Error> {
+    let mut temp = [0u8; MAX_VERSION.buffer_len()];
+    let mut out = [0u8; MAX_VERSION.buffer_len()];
+    let code = QrCode::encode_text(url, &mut temp, &mut out, QrCodeEcc::Low, MIN_VERSION, MAX_VERSION, None, true)
+        .map_err(|_| Error::TooLong)?;
+
+    let size = code.size();
+    let mut bitmap = QrBitmap {
+        rows: [0; MAX_SIZE],
+        size: size as u8,
+    };
+    for y in 0..size {
+        for x in 0..size {
+            if code.get_module(x, y) {
+                bitmap.rows[y as usize] |= 1 << x;
+            }
+        }
+    }
+    Ok(bitmap)
+}
@@ -0,0 +1,87 @@
+use embedded_hal::adc::OneShot;
+use rp2040_hal::adc::{Adc, TempSense};
+use rp2040_hal::pac;
+
+use crate::bsp;
+
+// Marsaglia's xorshift32, plenty for LED flicker
+pub struct XorShift32 {
+    state: u32,
+}
+
+impl XorShift32 {
+    // zero state would only ever produce zeros
+    pub const fn new(seed: u32) -> Self {
+        Self {
+            state: if seed == 0 { 0x9e37_79b9 } else { seed },
+        }
+    }
+
+    pub const fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    // lo..hi, hi not included. Plain modulo would favor the low end a little, so
+    // values from the short last stretch of u32 get thrown away and drawn again.
+    pub const fn next_range(&mut self, lo: u32, hi: u32) -> u32 {
+        if hi <= lo {
+            return lo;
+        }
+        let span = hi - lo;
+        let threshold = rejection_threshold(span);
+        loop {
+            let x = self.next_u32();
+            if x >= threshold {
+                return lo + x % span;
+            }
+        }
+    }
+
+    // 0.0..1.0
+    #[allow(clippy::cast_precision_loss)]
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / 16_777_216.0
+    }
+}
+
+// 2^32 % span, draws under this would make the low end one too many
+const fn rejection_threshold(span: u32) -> u32 {
+    span.wrapping_neg() % span
+}
+
+// No test harness on thumbv6m, so these are checked while compiling. Marsaglia's own
+// example seed from the xorshift paper, then seed 1 through the rejection loop: half of
+// u32 plus one rejects everything under 2^31 - 1, the first two draws here.
+const _: () = {
+    let mut rng = XorShift32::new(2_463_534_242);
+    assert!(rng.next_u32() == 723_471_715, "xorshift32 test vector");
+    let mut rng = XorShift32::new(1);
+    assert!(rng.next_u32() == 270_369 && rng.next_u32() == 67_634_689 && rng.next_u32() == 2_647_435_461);
+
+    assert!(rejection_threshold(3) == 1 && rejection_threshold(6) == 4 && rejection_threshold(10) == 6);
+    assert!(rejection_threshold(1 << 31) == 0, "powers of two never reject");
+    let mut rng = XorShift32::new(1);
+    assert!(rng.next_range(0, (1 << 31) + 1) == 2_647_435_461 % ((1 << 31) + 1));
+    assert!(rng.next_u32() == 307_599_695, "three draws for that one");
+    assert!(rng.next_range(5, 5) == 5 && rng.next_range(7, 3) == 7, "empty ranges give lo");
+};
+
+// Lowest bit of the temperature ADC is mostly noise. 64 of them get rotated into one
+// word, so every bit of it sees two samples. Crystal frequency and CHIP_ID are the same
+// on every badge of a revision, they only keep a stuck ADC from giving a zero seed.
+pub fn collect_entropy(adc: &mut Adc, sensor: &mut TempSense) -> u32 {
+    let mut seed: u32 = 0;
+    for _ in 0..64 {
+        let raw: u16 = adc.read(sensor).unwrap();
+        seed = seed.rotate_left(1) ^ u32::from(raw & 1);
+    }
+
+    // SAFETY: read only
+    let chip_id = unsafe { (*pac::SYSINFO::ptr()).chip_id.read().bits() };
+    seed ^ bsp::XOSC_CRYSTAL_FREQ ^ chip_id
+}
@@ -0,0 +1,98 @@
+use cortex_m::delay::Delay;
+use embedded_hal::PwmPin;
+use rp2040_hal::adc::{Adc, TempSense};
+use rp2040_hal::pac;
+
+use crate::bsp::{self, prelude::PwmChannels};
+use crate::rng;
+
+// Nine channels one after the other, then the verdict on the heart, 3 s in all
+const CHANNEL_MS: u32 = 100;
+const RESULT_MS: u32 = 3000 - CHANNEL_MS * 9;
+const RESULT_FLASHES: u32 = 3;
+
+// Long enough for a running counter to have moved, whatever the divider
+const COUNTER_WAIT_US: u32 = 20;
+
+const LED_PIN: u32 = <bsp::Led as bsp::GpioNum>::GPIO as u32;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TestResult {
+    // one bit per channel, in the order of PwmChannels::gains
+    pub failed_channels: u16,
+}
+
+impl TestResult {
+    pub const fn passed(self) -> bool {
+        self.failed_channels == 0
+    }
+}
+
+fn toggle_led() {
+    // SAFETY: atomic XOR alias, only flips the on-board LED that's already an output
+    let sio = unsafe { &*pac::SIO::ptr() };
+    sio.gpio_out_xor.write(|w| unsafe { w.bits(1 << LED_PIN) });
+}
+
+// The duty has to read back as written and the slice's counter has to be running,
+// a channel that passes both is really putting out PWM. Whether the LED on it lights
+// is up to whoever watches.
+fn check_channel(channel: &mut dyn PwmPin<Duty = u16>, slice: usize, delay: &mut Delay) -> bool {
+    let duty = channel.get_max_duty() / 2;
+    channel.set_duty(duty);
+    // SAFETY: read only
+    let pwm = unsafe { &*pac::PWM::ptr() };
+    let before = pwm.ch[slice].ctr.read().bits();
+    delay.delay_us(COUNTER_WAIT_US);
+    let running = pwm.ch[slice].ctr.read().bits() != before;
+    channel.get_duty() == duty && running
+}
+
+// Boot time check of all nine LED channels, bypasses gains and scales so every one
+// gets the same 50 %. Slices have to be set up and enabled already.
+pub fn run_self_test(channels: &mut PwmChannels, delay: &mut Delay) -> TestResult {
+    channels.set_all_off();
+    let mut failed_channels = 0;
+    for (channel, &(slice, _)) in bsp::LED_PWM_CHANNELS.iter().enumerate() {
+        let pin: &mut dyn PwmPin<Duty = u16> = match channel {
+            0 => channels.left_r,
+            1 => channels.left_g,
+            2 => channels.left_b,
+            3 => channels.right_r,
+            4 => channels.right_g,
+            5 => channels.right_b,
+            6 => channels.heart_r,
+            7 => channels.heart_g,
+            _ => channels.heart_b,
+        };
+        if !check_channel(pin, usize::from(slice), delay) {
+            failed_channels |= 1 << channel;
+        }
+        toggle_led();
+        delay.delay_ms(CHANNEL_MS);
+        pin.set_duty(0);
+    }
+
+    let result = TestResult { failed_channels };
+    let flash_ms = RESULT_MS / RESULT_FLASHES / 2;
+    for _ in 0..RESULT_FLASHES {
+        if result.passed() {
+            channels.set_heart(0, u16::MAX, 0);
+        } else {
+            channels.set_heart(u16::MAX, 0, 0);
+        }
+        delay.delay_ms(flash_ms);
+        channels.set_heart(0, 0, 0);
+        delay.delay_ms(flash_ms);
+    }
+    // the LED toggled an odd number of times
+    toggle_led();
+    result
+}
+
+// Two seeds straight after each other have to differ, otherwise the ADC's low bit isn't
+// noise and every badge starts its random modes the same way. Crystal and CHIP_ID are
+// the same both times, only the ADC bits can tell the two apart.
+pub fn check_entropy(adc: &mut Adc, sensor: &mut TempSense) -> bool {
+    rng::collect_entropy(adc, sensor) != rng::collect_entropy(adc, sensor)
+}
@@ -0,0 +1,106 @@
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+// SDO/SA0 low is the usual breakout default, high moves it to 0x19
+pub const ADDRESS_SDO_LOW: u8 = 0x18;
+pub const ADDRESS_SDO_HIGH: u8 = 0x19;
+
+const WHO_AM_I: u8 = 0x0f;
+const WHO_AM_I_VALUE: u8 = 0x33;
+const CTRL_REG1: u8 = 0x20;
+const CTRL_REG4: u8 = 0x23;
+const OUT_X_L: u8 = 0x28;
+const CLICK_CFG: u8 = 0x38;
+const CLICK_SRC: u8 = 0x39;
+const CLICK_THS: u8 = 0x3a;
+const TIME_LIMIT: u8 = 0x3b;
+
+// Register address MSB makes multi-byte reads step through registers
+const AUTO_INCREMENT: u8 = 0x80;
+
+// 10 Hz ODR, low power mode (8 bit samples), X, Y and Z on
+const CTRL_REG1_10HZ_LOW_POWER: u8 = 0x2f;
+// Block data update so X/Y/Z are always from the same sample, ±2 g
+const CTRL_REG4_BDU_2G: u8 = 0x80;
+// Single tap on any axis
+const CLICK_CFG_SINGLE_XYZ: u8 = 0x15;
+// 16 mg per LSB at ±2 g, so about 0.8 g of jolt is a tap
+const CLICK_THRESHOLD: u8 = 0x30;
+// One sample at 10 Hz, tap has to be over by then
+const CLICK_TIME_LIMIT: u8 = 0x01;
+
+const CLICK_SRC_SINGLE: u8 = 0x10;
+
+// ±2 g in low power mode
+const MG_PER_LSB: i16 = 16;
+
+#[derive(Debug)]
+pub enum Error {
+    I2c,
+    // something answered but isn't a LIS3DH
+    WrongDevice,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TapEvent {
+    None,
+    SingleTap,
+}
+
+// ST LIS3DH, in low power mode to go easy on the battery
+pub struct Lis3dh<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C> Lis3dh<I2C>
+where
+    I2C: Write + WriteRead,
+{
+    pub const fn new(i2c: I2C, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), Error> {
+        self.i2c.write(self.address, &[register, value]).map_err(|_| Error::I2c)
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8, Error> {
+        let mut value = [0u8];
+        self.i2c
+            .write_read(self.address, &[register], &mut value)
+            .map_err(|_| Error::I2c)?;
+        Ok(value[0])
+    }
+
+    // Checks it's really a LIS3DH and sets up 10 Hz sampling plus single tap detection
+    pub fn init(&mut self) -> Result<(), Error> {
+        if self.read_register(WHO_AM_I)? != WHO_AM_I_VALUE {
+            return Err(Error::WrongDevice);
+        }
+        self.write_register(CTRL_REG1, CTRL_REG1_10HZ_LOW_POWER)?;
+        self.write_register(CTRL_REG4, CTRL_REG4_BDU_2G)?;
+        self.write_register(CLICK_CFG, CLICK_CFG_SINGLE_XYZ)?;
+        self.write_register(CLICK_THS, CLICK_THRESHOLD)?;
+        self.write_register(TIME_LIMIT, CLICK_TIME_LIMIT)
+    }
+
+    // Acceleration in mg
+    pub fn read_xyz(&mut self) -> Result<(i16, i16, i16), Error> {
+        let mut buf = [0u8; 6];
+        self.i2c
+            .write_read(self.address, &[OUT_X_L | AUTO_INCREMENT], &mut buf)
+            .map_err(|_| Error::I2c)?;
+
+        // low power samples are 8 bits, left aligned
+        let axis = |l: u8, h: u8| (i16::from_le_bytes([l, h]) >> 8) * MG_PER_LSB;
+        Ok((axis(buf[0], buf[1]), axis(buf[2], buf[3]), axis(buf[4], buf[5])))
+    }
+
+    // Reading CLICK_SRC clears it, so each tap is reported once. Bus errors count as no tap.
+    pub fn get_tap_status(&mut self) -> TapEvent {
+        match self.read_register(CLICK_SRC) {
+            Ok(src) if src & CLICK_SRC_SINGLE != 0 => TapEvent::SingleTap,
+            _ => TapEvent::None,
+        }
+    }
+}
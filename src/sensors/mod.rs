@@ -0,0 +1,31 @@
+use core::cell::RefCell;
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+pub mod lis3dh;
+pub mod tmp102;
+
+// Lets several drivers share one I2C bus, the main loop only ever uses one at a time
+pub struct SharedI2c<'a, I2C>(&'a RefCell<I2C>);
+
+impl<'a, I2C> SharedI2c<'a, I2C> {
+    pub const fn new(bus: &'a RefCell<I2C>) -> Self {
+        Self(bus)
+    }
+}
+
+impl<I2C: Write> Write for SharedI2c<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().write(address, bytes)
+    }
+}
+
+impl<I2C: WriteRead> WriteRead for SharedI2c<'_, I2C> {
+    type Error = I2C::Error;
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.borrow_mut().write_read(address, bytes, buffer)
+    }
+}
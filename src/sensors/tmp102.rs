@@ -0,0 +1,46 @@
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+// ADD0 to ground
+pub const DEFAULT_ADDRESS: u8 = 0x48;
+
+const TEMPERATURE_REGISTER: u8 = 0x00;
+
+// One LSB of the 12 bit reading
+const CELSIUS_PER_LSB: f32 = 0.0625;
+
+#[derive(Debug)]
+pub enum Error {
+    // NAK or any other bus trouble, the I2C error itself isn't interesting
+    I2c,
+}
+
+// TI TMP102, ±0.5 °C and doesn't care about VREF like the internal sensor does
+pub struct Tmp102<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C> Tmp102<I2C>
+where
+    I2C: Write + WriteRead,
+{
+    pub const fn new(i2c: I2C, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    // Something ACKs on our address
+    pub fn probe(&mut self) -> bool {
+        self.i2c.write(self.address, &[TEMPERATURE_REGISTER]).is_ok()
+    }
+
+    pub fn read_celsius(&mut self) -> Result<f32, Error> {
+        let mut buf = [0u8; 2];
+        self.i2c
+            .write_read(self.address, &[TEMPERATURE_REGISTER], &mut buf)
+            .map_err(|_| Error::I2c)?;
+
+        // left aligned two's complement, low nibble unused
+        let raw = i16::from_be_bytes(buf) >> 4;
+        Ok(f32::from(raw) * CELSIUS_PER_LSB)
+    }
+}
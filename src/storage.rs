@@ -0,0 +1,527 @@
+use rp2040_hal::rom_data;
+
+use crate::animations::{spectral, AnimationMode};
+use crate::calibration::{self, CHANNEL_COUNT};
+use crate::color::Srgb;
+
+// Flash is mapped here for reading
+const XIP_BASE: u32 = 0x1000_0000;
+
+pub const FLASH_SIZE: u32 = 2048 * 1024; // see memory.x
+pub const SECTOR_SIZE: u32 = 4096; // smallest thing that can be erased
+pub const PAGE_SIZE: usize = 256; // smallest thing that can be programmed
+
+// Last sector, far away from the firmware
+pub const CONFIG_SECTOR_OFFSET: u32 = FLASH_SIZE - SECTOR_SIZE;
+
+// Right below it, so saving the config can't eat a crash log. memory.x leaves both out of FLASH.
+pub const CRASH_LOG_SECTOR_OFFSET: u32 = CONFIG_SECTOR_OFFSET - SECTOR_SIZE;
+
+// 64 KiB block erase command, the bootrom falls back to sector erase for smaller ranges
+const BLOCK_SIZE: u32 = 1 << 16;
+const BLOCK_ERASE_CMD: u8 = 0xd8;
+
+pub const NAME_LEN: usize = 32;
+
+// Longest URL a version 3 QR code holds in byte mode at low error correction, see qr
+pub const QR_URL_LEN: usize = 53;
+
+// "ALPA" marks a sector that has been written by us
+const CONFIG_MAGIC: u32 = 0x414c_5041;
+
+// Badge owner's name, ASCII, at most NAME_LEN bytes
+#[derive(Clone, Copy)]
+pub struct OwnerName {
+    bytes: [u8; NAME_LEN],
+    len: usize,
+}
+
+impl OwnerName {
+    pub const fn empty() -> Self {
+        Self {
+            bytes: [0; NAME_LEN],
+            len: 0,
+        }
+    }
+
+    // Longer names are cut, non-ASCII characters are dropped
+    pub fn new(name: &str) -> Self {
+        let mut owner = Self::empty();
+        for byte in name.bytes().filter(u8::is_ascii).take(NAME_LEN) {
+            owner.bytes[owner.len] = byte;
+            owner.len += 1;
+        }
+        owner
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only ASCII ever gets in
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+// What the QR code on the display points to, ASCII, at most QR_URL_LEN bytes
+#[derive(Clone, Copy)]
+pub struct QrUrl {
+    bytes: [u8; QR_URL_LEN],
+    len: usize,
+}
+
+impl QrUrl {
+    pub const fn empty() -> Self {
+        Self {
+            bytes: [0; QR_URL_LEN],
+            len: 0,
+        }
+    }
+
+    // None if it's too long or not ASCII, a cut URL would point somewhere else
+    pub fn new(url: &str) -> Option<Self> {
+        if url.len() > QR_URL_LEN || !url.is_ascii() {
+            return None;
+        }
+        let mut qr_url = Self::empty();
+        qr_url.bytes[..url.len()].copy_from_slice(url.as_bytes());
+        qr_url.len = url.len();
+        Some(qr_url)
+    }
+
+    // only the display reads it back
+    #[cfg_attr(not(feature = "oled"), allow(dead_code))]
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+
+    #[cfg_attr(not(feature = "oled"), allow(dead_code))]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// Copies `buf.len()` bytes starting at `offset` from flash
+pub fn read(offset: u32, buf: &mut [u8]) {
+    let base = (XIP_BASE + offset) as *const u8;
+    for (i, byte) in buf.iter_mut().enumerate() {
+        // SAFETY: offset + len stays inside the flash window
+        *byte = unsafe { core::ptr::read_volatile(base.add(i)) };
+    }
+}
+
+// Config page layout: magic, name length, name, old badge id (unused, see info), cold, cool and hot thresholds, brightness,
+// animation mode and its parameters, sleep timeout, LED gains, QR code URL length and URL,
+// solid mode's three colors, spectral mode's three speeds, then a CRC-16 over all of it
+const NAME_LEN_OFFSET: usize = 4;
+const NAME_OFFSET: usize = 5;
+const BADGE_ID_OFFSET: usize = NAME_OFFSET + NAME_LEN;
+const COLD_THRESHOLD_OFFSET: usize = BADGE_ID_OFFSET + 1;
+const COOL_THRESHOLD_OFFSET: usize = COLD_THRESHOLD_OFFSET + 2;
+const HOT_THRESHOLD_OFFSET: usize = COOL_THRESHOLD_OFFSET + 2;
+const BRIGHTNESS_OFFSET: usize = HOT_THRESHOLD_OFFSET + 2;
+const MODE_OFFSET: usize = BRIGHTNESS_OFFSET + 1;
+const MODE_PARAMS_OFFSET: usize = MODE_OFFSET + 1;
+const SLEEP_AFTER_OFFSET: usize = MODE_PARAMS_OFFSET + 3;
+const GAINS_OFFSET: usize = SLEEP_AFTER_OFFSET + 4;
+const QR_URL_LEN_OFFSET: usize = GAINS_OFFSET + CHANNEL_COUNT;
+const QR_URL_OFFSET: usize = QR_URL_LEN_OFFSET + 1;
+const SOLID_COLORS_OFFSET: usize = QR_URL_OFFSET + QR_URL_LEN;
+const SPECTRAL_SPEEDS_OFFSET: usize = SOLID_COLORS_OFFSET + 3 * 3;
+const CRC_OFFSET: usize = SPECTRAL_SPEEDS_OFFSET + 3 * 4;
+
+// Flash chip's 64 bit unique id: the command, four dummy bytes, then the id
+const UNIQUE_ID_CMD: u8 = 0x4b;
+const UNIQUE_ID_DUMMY_LEN: usize = 4;
+pub const UNIQUE_ID_LEN: usize = 8;
+const UNIQUE_ID_TRANSFER_LEN: usize = 1 + UNIQUE_ID_DUMMY_LEN + UNIQUE_ID_LEN;
+
+// SSI and the chip select pad override by address, the RAM routine can't call into pac
+const SSI_SR: u32 = 0x1800_0028;
+const SSI_DR0: u32 = 0x1800_0060;
+const SSI_SR_TFNF: u32 = 1 << 1;
+const SSI_SR_RFNE: u32 = 1 << 3;
+const QSPI_SS_CTRL: u32 = 0x4001_800c;
+const QSPI_SS_OUTOVER_SHIFT: u32 = 8;
+const QSPI_SS_OUTOVER_MASK: u32 = 0b11 << QSPI_SS_OUTOVER_SHIFT;
+const QSPI_SS_LOW: u32 = 2;
+const QSPI_SS_HIGH: u32 = 3;
+// the SSI FIFOs are 16 deep, never have more than this on the way
+const SSI_MAX_IN_FLIGHT: usize = 14;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FlashError {
+    // what we read back isn't what we wrote
+    Verify,
+}
+
+// Everything the badge remembers over a power cycle, besides its id
+#[derive(Clone, Copy)]
+pub struct BadgeConfig {
+    // temperature band edges, see TemperatureBand
+    pub cold_threshold: u16,
+    pub cool_threshold: u16,
+    pub hot_threshold: u16,
+    pub brightness_percent: u8,
+    pub animation_mode: AnimationMode,
+    // idle this long and the badge goes dormant, 0 never
+    pub sleep_after_ms: u32,
+    pub owner_name: OwnerName,
+}
+
+// Compile time fallback, for a fresh badge or a config that doesn't check out
+pub const DEFAULT_BADGE_CONFIG: BadgeConfig = BadgeConfig {
+    cold_threshold: crate::MY_ALPACCA_FEELS_COLD_WHEN_CELSIUS_HITS_UNDER,
+    cool_threshold: crate::MY_ALPACCA_FEELS_COOL_WHEN_CELSIUS_HITS_UNDER,
+    hot_threshold: crate::MY_ALPACCA_FEELS_HOT_WHEN_CELSIUS_HITS_OVER,
+    brightness_percent: 100,
+    animation_mode: crate::ANIMATION_MODE,
+    sleep_after_ms: crate::SLEEP_AFTER_MS,
+    owner_name: OwnerName::empty(),
+};
+
+// CRC-16/CCITT-FALSE, bitwise, it's only ever run over a few dozen bytes
+pub const fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    let mut i = 0;
+    while i < data.len() {
+        crc ^= (data[i] as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+            bit += 1;
+        }
+        i += 1;
+    }
+    crc
+}
+
+// What a fresh badge starts from
+fn default_config_page() -> [u8; PAGE_SIZE] {
+    let mut page = [0xffu8; PAGE_SIZE];
+    page[..4].copy_from_slice(&CONFIG_MAGIC.to_le_bytes());
+    write_config(&mut page, &DEFAULT_BADGE_CONFIG);
+    page[GAINS_OFFSET..QR_URL_LEN_OFFSET].fill(calibration::DEFAULT_GAIN_PERCENT);
+    page[QR_URL_LEN_OFFSET] = 0;
+    page
+}
+
+fn program_config_page(page: &mut [u8; PAGE_SIZE]) -> Result<(), FlashError> {
+    let crc = crc16(&page[..CRC_OFFSET]);
+    page[CRC_OFFSET..CRC_OFFSET + 2].copy_from_slice(&crc.to_le_bytes());
+    erase_and_program(CONFIG_SECTOR_OFFSET, page)
+}
+
+// The page as it is in flash, and whether its CRC checks out
+fn read_config_page() -> Option<([u8; PAGE_SIZE], bool)> {
+    let mut page = [0u8; PAGE_SIZE];
+    read(CONFIG_SECTOR_OFFSET, &mut page);
+
+    let magic = u32::from_le_bytes([page[0], page[1], page[2], page[3]]);
+    let crc = u16::from_le_bytes([page[CRC_OFFSET], page[CRC_OFFSET + 1]]);
+    (magic == CONFIG_MAGIC).then_some((page, crc == crc16(&page[..CRC_OFFSET])))
+}
+
+// Same magic without a good CRC is a page from before the CRC (magic, name length, name,
+// badge id), or one that got corrupted. Either way the name is where it always was, it's
+// kept and everything else goes back to the defaults.
+fn migrate_config_page(page: &[u8; PAGE_SIZE]) -> [u8; PAGE_SIZE] {
+    let mut migrated = default_config_page();
+    write_owner_name(&mut migrated, &read_owner_name(page));
+    migrated
+}
+
+// None if we've never written the config sector. Never writes, core 1 reads through here too.
+fn load_config_page() -> Option<[u8; PAGE_SIZE]> {
+    read_config_page().map(|(page, crc_ok)| if crc_ok { page } else { migrate_config_page(&page) })
+}
+
+// Once at boot, before core 1 runs: a page without a good CRC is rewritten with one
+pub fn migrate_config() -> Result<bool, FlashError> {
+    match read_config_page() {
+        Some((page, false)) => program_config_page(&mut migrate_config_page(&page)).map(|()| true),
+        _ => Ok(false),
+    }
+}
+
+// Changes one thing in the config page and keeps the rest
+fn update_config_page(update: impl FnOnce(&mut [u8; PAGE_SIZE])) -> Result<(), FlashError> {
+    let mut page = load_config_page().unwrap_or_else(default_config_page);
+    update(&mut page);
+    program_config_page(&mut page)
+}
+
+fn read_owner_name(page: &[u8; PAGE_SIZE]) -> OwnerName {
+    let len = usize::from(page[NAME_LEN_OFFSET]);
+    if len > NAME_LEN {
+        return OwnerName::empty();
+    }
+
+    let mut owner = OwnerName::empty();
+    owner.bytes[..len].copy_from_slice(&page[NAME_OFFSET..NAME_OFFSET + len]);
+    owner.len = len;
+    owner
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_owner_name(page: &mut [u8; PAGE_SIZE], owner: &OwnerName) {
+    page[NAME_LEN_OFFSET] = owner.len as u8;
+    page[NAME_OFFSET..NAME_OFFSET + owner.len].copy_from_slice(&owner.bytes[..owner.len]);
+}
+
+fn write_config(page: &mut [u8; PAGE_SIZE], config: &BadgeConfig) {
+    let (mode, params) = config.animation_mode.pack();
+    page[COLD_THRESHOLD_OFFSET..COLD_THRESHOLD_OFFSET + 2].copy_from_slice(&config.cold_threshold.to_le_bytes());
+    page[COOL_THRESHOLD_OFFSET..COOL_THRESHOLD_OFFSET + 2].copy_from_slice(&config.cool_threshold.to_le_bytes());
+    page[HOT_THRESHOLD_OFFSET..HOT_THRESHOLD_OFFSET + 2].copy_from_slice(&config.hot_threshold.to_le_bytes());
+    page[BRIGHTNESS_OFFSET] = config.brightness_percent;
+    page[MODE_OFFSET] = mode;
+    page[MODE_PARAMS_OFFSET..MODE_PARAMS_OFFSET + 3].copy_from_slice(&params.to_le_bytes()[..3]);
+    page[SLEEP_AFTER_OFFSET..SLEEP_AFTER_OFFSET + 4].copy_from_slice(&config.sleep_after_ms.to_le_bytes());
+    if let Some(colors) = config.animation_mode.solid_colors() {
+        for (i, color) in colors.iter().enumerate() {
+            let offset = SOLID_COLORS_OFFSET + 3 * i;
+            page[offset..offset + 3].copy_from_slice(&color.to_bytes());
+        }
+    }
+    if let Some(speeds) = config.animation_mode.spectral_speeds() {
+        for (i, speed) in speeds.iter().enumerate() {
+            let offset = SPECTRAL_SPEEDS_OFFSET + 4 * i;
+            page[offset..offset + 4].copy_from_slice(&speed.to_le_bytes());
+        }
+    }
+    write_owner_name(page, &config.owner_name);
+}
+
+// Defaults for anything that isn't there or doesn't make sense
+pub fn load_config() -> BadgeConfig {
+    let Some(page) = load_config_page() else {
+        return DEFAULT_BADGE_CONFIG;
+    };
+
+    let brightness_percent = page[BRIGHTNESS_OFFSET];
+    let threshold = |offset: usize| u16::from_le_bytes([page[offset], page[offset + 1]]);
+    // bands have to be in order, otherwise all three go back to defaults
+    let (cold, cool, hot) = (
+        threshold(COLD_THRESHOLD_OFFSET),
+        threshold(COOL_THRESHOLD_OFFSET),
+        threshold(HOT_THRESHOLD_OFFSET),
+    );
+    let (cold, cool, hot) = if cold <= cool && cool <= hot {
+        (cold, cool, hot)
+    } else {
+        (
+            DEFAULT_BADGE_CONFIG.cold_threshold,
+            DEFAULT_BADGE_CONFIG.cool_threshold,
+            DEFAULT_BADGE_CONFIG.hot_threshold,
+        )
+    };
+    let params = u32::from_le_bytes([
+        page[MODE_PARAMS_OFFSET],
+        page[MODE_PARAMS_OFFSET + 1],
+        page[MODE_PARAMS_OFFSET + 2],
+        0,
+    ]);
+    let color = |i: usize| {
+        let offset = SOLID_COLORS_OFFSET + 3 * i;
+        Srgb::from_bytes([page[offset], page[offset + 1], page[offset + 2]])
+    };
+    let speed = |i: usize| {
+        let offset = SPECTRAL_SPEEDS_OFFSET + 4 * i;
+        let speed = f32::from_le_bytes([page[offset], page[offset + 1], page[offset + 2], page[offset + 3]]);
+        if (0.0..=spectral::MAX_SPEED).contains(&speed) { speed } else { spectral::DEFAULT_SPEEDS[i] }
+    };
+    BadgeConfig {
+        cold_threshold: cold,
+        cool_threshold: cool,
+        hot_threshold: hot,
+        brightness_percent: if brightness_percent <= 100 {
+            brightness_percent
+        } else {
+            DEFAULT_BADGE_CONFIG.brightness_percent
+        },
+        animation_mode: AnimationMode::unpack(page[MODE_OFFSET], params).map_or(DEFAULT_BADGE_CONFIG.animation_mode, |mode| {
+            mode.with_solid_colors([color(0), color(1), color(2)])
+                .with_spectral_speeds([speed(0), speed(1), speed(2)])
+        }),
+        sleep_after_ms: u32::from_le_bytes([
+            page[SLEEP_AFTER_OFFSET],
+            page[SLEEP_AFTER_OFFSET + 1],
+            page[SLEEP_AFTER_OFFSET + 2],
+            page[SLEEP_AFTER_OFFSET + 3],
+        ]),
+        owner_name: read_owner_name(&page),
+    }
+}
+
+// Erases the sector and writes it all back, badge id stays
+pub fn save_config(config: &BadgeConfig) -> Result<(), FlashError> {
+    update_config_page(|page| write_config(page, config))
+}
+
+// Gains out of range fall back to the default one by one
+pub fn load_calibration() -> [u8; CHANNEL_COUNT] {
+    let mut gains = [calibration::DEFAULT_GAIN_PERCENT; CHANNEL_COUNT];
+    if let Some(page) = load_config_page() {
+        for (gain, &stored) in gains.iter_mut().zip(&page[GAINS_OFFSET..QR_URL_LEN_OFFSET]) {
+            if (calibration::MIN_GAIN_PERCENT..=calibration::MAX_GAIN_PERCENT).contains(&stored) {
+                *gain = stored;
+            }
+        }
+    }
+    gains
+}
+
+pub fn save_calibration(gains: &[u8; CHANNEL_COUNT]) -> Result<(), FlashError> {
+    update_config_page(|page| page[GAINS_OFFSET..QR_URL_LEN_OFFSET].copy_from_slice(gains))
+}
+
+// Empty if none was ever set
+#[cfg_attr(not(feature = "oled"), allow(dead_code))]
+pub fn load_qr_url() -> QrUrl {
+    load_config_page()
+        .and_then(|page| {
+            let len = usize::from(page[QR_URL_LEN_OFFSET]).min(QR_URL_LEN);
+            core::str::from_utf8(&page[QR_URL_OFFSET..QR_URL_OFFSET + len]).ok().and_then(QrUrl::new)
+        })
+        .unwrap_or_else(QrUrl::empty)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+pub fn save_qr_url(url: &QrUrl) -> Result<(), FlashError> {
+    update_config_page(|page| {
+        page[QR_URL_LEN_OFFSET] = url.len as u8;
+        page[QR_URL_OFFSET..QR_URL_OFFSET + url.len].copy_from_slice(&url.bytes[..url.len]);
+    })
+}
+
+pub fn load_owner_name() -> OwnerName {
+    load_config_page().map_or_else(OwnerName::empty, |page| read_owner_name(&page))
+}
+
+pub fn save_owner_name(owner: &OwnerName) -> Result<(), FlashError> {
+    update_config_page(|page| write_owner_name(page, owner))
+}
+
+// The RP2040 has no id of its own, the flash chip next to it does. Same dance as
+// writing: XIP off, everything from RAM, core 1 parked.
+pub fn read_unique_id() -> [u8; UNIQUE_ID_LEN] {
+    let mut transfer = [0u8; UNIQUE_ID_TRANSFER_LEN];
+    transfer[0] = UNIQUE_ID_CMD;
+    crate::core1::parked(|| {
+        // SAFETY: interrupts are off and everything used lives in RAM or ROM
+        with_flash_fns(|fns| unsafe { command_from_ram(fns, &mut transfer) });
+    });
+
+    let mut id = [0u8; UNIQUE_ID_LEN];
+    id.copy_from_slice(&transfer[1 + UNIQUE_ID_DUMMY_LEN..]);
+    id
+}
+
+// Everything the RAM routine needs, looked up while flash is still readable
+struct FlashFns {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+    // RAM copy of boot2, gets XIP back up at full speed
+    boot2: unsafe extern "C" fn(),
+}
+
+// Runs `f` with interrupts off and the ROM flash routines looked up, for the RAM routines
+// below. The boot2 copy is on our stack, so it's still there while `f` runs.
+fn with_flash_fns<T>(f: impl FnOnce(&FlashFns) -> T) -> T {
+    let mut boot2 = [0u32; 64];
+    for (i, word) in boot2.iter_mut().enumerate() {
+        // SAFETY: boot2 is the first 256 bytes of flash
+        *word = unsafe { core::ptr::read_volatile((XIP_BASE as *const u32).add(i)) };
+    }
+
+    // SAFETY: boot2 is position independent thumb code, +1 for thumb mode
+    let boot2_fn: unsafe extern "C" fn() = unsafe { core::mem::transmute(boot2.as_ptr() as usize + 1) };
+
+    let fns = FlashFns {
+        connect_internal_flash: rom_data::connect_internal_flash::ptr(),
+        flash_exit_xip: rom_data::flash_exit_xip::ptr(),
+        flash_range_erase: rom_data::flash_range_erase::ptr(),
+        flash_range_program: rom_data::flash_range_program::ptr(),
+        flash_flush_cache: rom_data::flash_flush_cache::ptr(),
+        boot2: boot2_fn,
+    };
+
+    cortex_m::interrupt::free(|_| f(&fns))
+}
+
+// Erases the sector at `offset`, writes `page` to its beginning and reads it back.
+//
+// While flash is being written nothing can be fetched from it, so interrupts are
+// off, core 1 is parked and the actual work happens in a function that lives in RAM.
+pub fn erase_and_program(offset: u32, page: &[u8; PAGE_SIZE]) -> Result<(), FlashError> {
+    // core 1 runs from flash too, it waits in RAM until we're done
+    crate::core1::parked(|| program(offset, page))
+}
+
+// Same without asking core 1 to park, for when it's been forced off already. Asking a
+// core that's off, or stuck waiting for a lock, would never get an answer.
+pub fn erase_and_program_core1_off(offset: u32, page: &[u8; PAGE_SIZE]) -> Result<(), FlashError> {
+    program(offset, page)
+}
+
+fn program(offset: u32, page: &[u8; PAGE_SIZE]) -> Result<(), FlashError> {
+    // SAFETY: interrupts are off and everything used lives in RAM or ROM
+    with_flash_fns(|fns| unsafe { write_from_ram(fns, offset, page.as_ptr()) });
+
+    let mut written = [0u8; PAGE_SIZE];
+    read(offset, &mut written);
+    if written == *page {
+        Ok(())
+    } else {
+        Err(FlashError::Verify)
+    }
+}
+
+// Sends `buf` to the flash with chip select held low and puts what comes back in its
+// place, like the SDK's flash_do_cmd. Registers are read and written with asm, a debug
+// build would call read_volatile and friends in flash. Same for From, hence the casts.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+#[allow(clippy::cast_possible_truncation, clippy::cast_lossless)]
+unsafe fn command_from_ram(fns: &FlashFns, buf: &mut [u8; UNIQUE_ID_TRANSFER_LEN]) {
+    (fns.connect_internal_flash)();
+    (fns.flash_exit_xip)();
+
+    let mut ctrl: u32;
+    core::arch::asm!("ldr {}, [{}]", out(reg) ctrl, in(reg) QSPI_SS_CTRL);
+    ctrl &= !QSPI_SS_OUTOVER_MASK;
+    core::arch::asm!("str {}, [{}]", in(reg) ctrl | QSPI_SS_LOW << QSPI_SS_OUTOVER_SHIFT, in(reg) QSPI_SS_CTRL);
+
+    let (mut sent, mut received) = (0, 0);
+    while received < UNIQUE_ID_TRANSFER_LEN {
+        let status: u32;
+        core::arch::asm!("ldr {}, [{}]", out(reg) status, in(reg) SSI_SR);
+        if status & SSI_SR_TFNF != 0 && sent < UNIQUE_ID_TRANSFER_LEN && sent - received < SSI_MAX_IN_FLIGHT {
+            core::arch::asm!("str {}, [{}]", in(reg) buf[sent] as u32, in(reg) SSI_DR0);
+            sent += 1;
+        }
+        if status & SSI_SR_RFNE != 0 {
+            let data: u32;
+            core::arch::asm!("ldr {}, [{}]", out(reg) data, in(reg) SSI_DR0);
+            buf[received] = data as u8;
+            received += 1;
+        }
+    }
+
+    core::arch::asm!("str {}, [{}]", in(reg) ctrl | QSPI_SS_HIGH << QSPI_SS_OUTOVER_SHIFT, in(reg) QSPI_SS_CTRL);
+
+    (fns.flash_flush_cache)();
+    (fns.boot2)();
+}
+
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_from_ram(fns: &FlashFns, offset: u32, data: *const u8) {
+    (fns.connect_internal_flash)();
+    (fns.flash_exit_xip)();
+    (fns.flash_range_erase)(offset, SECTOR_SIZE as usize, BLOCK_SIZE, BLOCK_ERASE_CMD);
+    (fns.flash_range_program)(offset, data, PAGE_SIZE);
+    (fns.flash_flush_cache)();
+    (fns.boot2)();
+}
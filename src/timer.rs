@@ -0,0 +1,143 @@
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use critical_section::Mutex;
+use rp2040_hal::pac::{self, interrupt};
+use rp2040_hal::timer::{Alarm, Alarm0, Instant};
+
+// Everything runs in frames of this, cold animations just take two of them per step
+pub const FRAME_RATE_HZ: u32 = 100;
+pub const FRAME_MS: u32 = 1000 / FRAME_RATE_HZ;
+
+// Owned by the alarm interrupt, along with when it's due next
+static FRAME_ALARM: Mutex<RefCell<Option<(Alarm0, Instant)>>> = Mutex::new(RefCell::new(None));
+
+// Set by the interrupt, taken by core 0's main loop
+static FRAME_READY: AtomicBool = AtomicBool::new(false);
+
+// Frames since start_frame_tick, core 1 paces itself on this. Only the interrupt writes it.
+static FRAME_COUNT: AtomicU32 = AtomicU32::new(0);
+
+// Fires TIMER_IRQ_0 every FRAME_MS from now on, on whichever core calls this
+pub fn start_frame_tick(mut alarm: Alarm0) {
+    let first = Instant::from_ticks(uptime_us() + u64::from(FRAME_MS) * 1000);
+    critical_section::with(|cs| {
+        alarm.schedule_at(first).ok();
+        alarm.enable_interrupt();
+        FRAME_ALARM.borrow_ref_mut(cs).replace((alarm, first));
+    });
+
+    // SAFETY: handler is in place and the alarm is armed
+    unsafe { pac::NVIC::unmask(pac::Interrupt::TIMER_IRQ_0) };
+}
+
+// True once per frame, a frame that came and went while we were busy isn't kept around
+pub fn take_frame() -> bool {
+    // no compare and swap on the M0+, the interrupt can't sneak in here though
+    critical_section::with(|_| {
+        let ready = FRAME_READY.load(Ordering::Relaxed);
+        FRAME_READY.store(false, Ordering::Relaxed);
+        ready
+    })
+}
+
+pub fn frame_count() -> u32 {
+    FRAME_COUNT.load(Ordering::Relaxed)
+}
+
+#[interrupt]
+fn TIMER_IRQ_0() {
+    critical_section::with(|cs| {
+        if let Some((alarm, due)) = FRAME_ALARM.borrow_ref_mut(cs).as_mut() {
+            alarm.clear_interrupt();
+            // from when it was due, not from now, so interrupt latency doesn't add up
+            *due += fugit::MicrosDurationU64::millis(u64::from(FRAME_MS));
+            alarm.schedule_at(*due).ok();
+        }
+    });
+    FRAME_COUNT.store(FRAME_COUNT.load(Ordering::Relaxed).wrapping_add(1), Ordering::Relaxed);
+    FRAME_READY.store(true, Ordering::Relaxed);
+}
+
+// Microseconds since boot, doesn't wrap in the lifetime of any battery
+pub fn uptime_us() -> u64 {
+    // reading TIMELR latches the high half into TIMEHR, and there's only one latch
+    // for both cores, so nobody else gets to read in between
+    critical_section::with(|_| {
+        // SAFETY: only the latched pair is read, nobody else uses it outside a critical section
+        let timer = unsafe { &*pac::TIMER::ptr() };
+        let low = timer.timelr.read().bits();
+        let high = timer.timehr.read().bits();
+        u64::from(high) << 32 | u64::from(low)
+    })
+}
+
+// For the panic handler, where the critical section might belong to a core that's gone.
+// The raw registers aren't latched, so high, low and high again until it holds still.
+#[allow(clippy::cast_possible_truncation)]
+pub fn uptime_ms_unlocked() -> u32 {
+    // SAFETY: read only, the raw registers don't touch the latch
+    let timer = unsafe { &*pac::TIMER::ptr() };
+    loop {
+        let high = timer.timerawh.read().bits();
+        let low = timer.timerawl.read().bits();
+        if timer.timerawh.read().bits() == high {
+            return ((u64::from(high) << 32 | u64::from(low)) / 1000) as u32;
+        }
+    }
+}
+
+// Wraps after about 49 days, a conference doesn't last that long
+#[allow(clippy::cast_possible_truncation)]
+pub fn uptime_ms() -> u32 {
+    (uptime_us() / 1000) as u32
+}
+
+// Frame rate is reported this often
+pub const FRAME_STATS_INTERVAL_MS: u32 = 5000;
+
+#[derive(Clone, Copy)]
+pub struct FrameReport {
+    pub fps: u32,
+    pub min_frame_ms: u32,
+    pub max_frame_ms: u32,
+}
+
+// Counts main loop frames, anything slowing the loop down shows up as fps below
+// FRAME_RATE_HZ and a long max_frame_ms
+pub struct FrameStats {
+    frames: u32,
+    elapsed_ms: u32,
+    min_frame_ms: u32,
+    max_frame_ms: u32,
+}
+
+impl FrameStats {
+    pub const fn new() -> Self {
+        Self {
+            frames: 0,
+            elapsed_ms: 0,
+            min_frame_ms: u32::MAX,
+            max_frame_ms: 0,
+        }
+    }
+
+    // Once per frame with the uptime delta since the last one, Some every FRAME_STATS_INTERVAL_MS
+    pub fn update(&mut self, delta_ms: u32) -> Option<FrameReport> {
+        self.frames += 1;
+        self.elapsed_ms += delta_ms;
+        self.min_frame_ms = self.min_frame_ms.min(delta_ms);
+        self.max_frame_ms = self.max_frame_ms.max(delta_ms);
+        if self.elapsed_ms < FRAME_STATS_INTERVAL_MS {
+            return None;
+        }
+
+        let report = FrameReport {
+            fps: self.frames * 1000 / self.elapsed_ms,
+            min_frame_ms: self.min_frame_ms,
+            max_frame_ms: self.max_frame_ms,
+        };
+        *self = Self::new();
+        Some(report)
+    }
+}
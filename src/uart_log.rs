@@ -0,0 +1,163 @@
+use core::fmt;
+
+use rp2040_hal::clocks::PeripheralClock;
+use rp2040_hal::pac;
+use rp2040_hal::uart::{Enabled, UartConfig, UartPeripheral};
+use rp2040_hal::Clock;
+
+use crate::bsp;
+use crate::timer;
+
+// DMA channel that feeds UART0 TX, nobody else touches it
+const DMA_CHANNEL: usize = 0;
+
+// One buffer is being sent while the other one is filled, lines that don't fit are dropped
+const BUFFER_LEN: usize = 256;
+
+type Uart = UartPeripheral<Enabled, pac::UART0, (bsp::UartTx, ())>;
+
+// Plain UART on GPIO0, for when USB CDC is too much hassle. Every line starts with
+// milliseconds since boot: "[12345] temperature: ..."
+pub struct UartLogger {
+    _uart: Uart,
+    buffers: &'static mut [[u8; BUFFER_LEN]; 2],
+    filling: usize,
+    len: usize,
+    line_start: bool,
+}
+
+impl UartLogger {
+    pub fn new(
+        uart: pac::UART0,
+        tx: bsp::UartTx,
+        resets: &mut pac::RESETS,
+        peripheral_clock: &PeripheralClock,
+    ) -> Self {
+        let uart = UartPeripheral::new(uart, (tx, ()), resets)
+            // default config is 115200 8N1
+            .enable(UartConfig::default(), peripheral_clock.freq())
+            .unwrap();
+
+        resets.reset.modify(|_, w| w.dma().clear_bit());
+        while resets.reset_done.read().dma().bit_is_clear() {}
+
+        let buffers = cortex_m::singleton!(: [[u8; BUFFER_LEN]; 2] = [[0; BUFFER_LEN]; 2]).unwrap();
+
+        Self {
+            _uart: uart,
+            buffers,
+            filling: 0,
+            len: 0,
+            line_start: true,
+        }
+    }
+
+    // Divisors for a clk_peri that changed since new(), see power::set_sys_clock_slow.
+    // Whatever is still going out finishes at the old rate first.
+    pub fn set_peripheral_clock(peripheral_clock_hz: u32) {
+        // SAFETY: DMA only reads uartfr and writes uartdr, the divisors are ours
+        let uart = unsafe { &*pac::UART0::ptr() };
+        Self::wait_idle();
+
+        // same as the hal, 64ths of the divider rounded
+        let div = peripheral_clock_hz * 8 / UartConfig::default().baudrate.to_Hz();
+        let (int, frac) = (div >> 7, (div & 0x7f).div_ceil(2));
+        #[allow(clippy::cast_possible_truncation)]
+        unsafe {
+            uart.uartibrd.write(|w| w.baud_divint().bits(int as u16));
+            uart.uartfbrd.write(|w| w.baud_divfrac().bits(frac as u8));
+        }
+        // the divisors only take with a write to LCR_H
+        uart.uartlcr_h.modify(|_, w| w);
+    }
+
+    // Until the last byte handed to DMA is out on the wire
+    pub fn wait_idle() {
+        // SAFETY: only reading the flags
+        let uart = unsafe { &*pac::UART0::ptr() };
+        while Self::dma_busy() || uart.uartfr.read().busy().bit_is_set() {}
+    }
+
+    fn dma_busy() -> bool {
+        // SAFETY: only our own channel is touched
+        let dma = unsafe { &*pac::DMA::ptr() };
+        dma.ch[DMA_CHANNEL].ch_ctrl_trig.read().busy().bit_is_set()
+    }
+
+    // Hands the filled buffer to DMA if the previous one has gone out, never waits.
+    // If it hasn't, lines wait in the buffer and go out with the next one.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn flush(&mut self) {
+        if self.len == 0 || Self::dma_busy() {
+            return;
+        }
+
+        // SAFETY: only our own channel is touched, buffer is 'static and not written
+        // to again until DMA is done with it
+        let dma = unsafe { &*pac::DMA::ptr() };
+        let ch = &dma.ch[DMA_CHANNEL];
+        unsafe {
+            ch.ch_read_addr.write(|w| w.bits(self.buffers[self.filling].as_ptr() as u32));
+            ch.ch_write_addr.write(|w| w.bits(core::ptr::addr_of!((*pac::UART0::ptr()).uartdr) as u32));
+            ch.ch_trans_count.write(|w| w.bits(self.len as u32));
+            ch.ch_ctrl_trig.write(|w| {
+                w.treq_sel().uart0_tx();
+                w.data_size().size_byte();
+                w.incr_read().set_bit();
+                w.incr_write().clear_bit();
+                // chaining to itself means no chaining
+                w.chain_to().bits(DMA_CHANNEL as u8);
+                w.en().set_bit()
+            });
+        }
+
+        self.filling ^= 1;
+        self.len = 0;
+    }
+
+    const fn push(&mut self, byte: u8) {
+        if self.len < BUFFER_LEN {
+            self.buffers[self.filling][self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn push_timestamp(&mut self) {
+        let mut ms = timer::uptime_ms();
+        let mut digits = [0u8; 10];
+        let mut n = 0;
+        loop {
+            digits[n] = b'0' + (ms % 10) as u8;
+            n += 1;
+            ms /= 10;
+            if ms == 0 {
+                break;
+            }
+        }
+
+        self.push(b'[');
+        for &digit in digits[..n].iter().rev() {
+            self.push(digit);
+        }
+        self.push(b']');
+        self.push(b' ');
+    }
+}
+
+impl fmt::Write for UartLogger {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.line_start {
+                self.line_start = false;
+                self.push_timestamp();
+            }
+            self.push(byte);
+            if byte == b'\n' {
+                self.line_start = true;
+                self.flush();
+            }
+        }
+        Ok(())
+    }
+}
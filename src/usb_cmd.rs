@@ -0,0 +1,369 @@
+use core::cell::RefCell;
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+
+use critical_section::Mutex;
+
+use crate::animations::{automata, lab_cycle, lissajous, palettes, AnimationMode};
+use crate::audio::rtttl;
+use crate::calibration;
+use crate::color::{self, Srgb};
+use crate::info;
+use crate::morse::MorseEncoder;
+use crate::power;
+use crate::storage::{self, OwnerName, QrUrl};
+use crate::usb_log::{self, Logger};
+
+// Things the USB commands can change at runtime
+pub struct Settings {
+    pub cold_threshold: u16,
+    pub cool_threshold: u16,
+    pub hot_threshold: u16,
+    pub brightness_percent: u8,
+    // set by set_mode, main loop takes it
+    pub requested_mode: Option<AnimationMode>,
+    // written by the main loop for save_config
+    pub mode: AnimationMode,
+    // idle time before going dormant, 0 never
+    pub sleep_after_ms: u32,
+    // LED gains in flash changed, main loop passes it on to core 1
+    pub calibration_changed: bool,
+    // set by morse, main loop hands it to core 1
+    pub morse: Option<MorseEncoder>,
+    // RTTTL string set by play, main loop hands it to the buzzer
+    pub melody: Option<&'static str>,
+    // QR URL in flash changed, main loop puts it on the display
+    pub qr_changed: bool,
+    // written by the main loop for get_temp
+    pub temperature: u16,
+}
+
+pub static SETTINGS: Mutex<RefCell<Settings>> = Mutex::new(RefCell::new(Settings {
+    cold_threshold: crate::MY_ALPACCA_FEELS_COLD_WHEN_CELSIUS_HITS_UNDER,
+    cool_threshold: crate::MY_ALPACCA_FEELS_COOL_WHEN_CELSIUS_HITS_UNDER,
+    hot_threshold: crate::MY_ALPACCA_FEELS_HOT_WHEN_CELSIUS_HITS_OVER,
+    brightness_percent: 100,
+    requested_mode: None,
+    mode: crate::ANIMATION_MODE,
+    sleep_after_ms: crate::SLEEP_AFTER_MS,
+    calibration_changed: false,
+    morse: None,
+    melody: None,
+    qr_changed: false,
+    temperature: 0,
+}));
+
+const LINE_LEN: usize = 64;
+
+// Collects bytes from USB CDC into lines and runs them as commands:
+//
+//   set_cold_thresh <n>      cold threshold in celsius, ice under it
+//   set_cool_thresh <n>      cool threshold in celsius, blue under it
+//   set_hot_thresh <n>       hot threshold in celsius, fire over it
+//   set_brightness <0-100>   LED brightness in percent
+//   set_mode <name>          rainbow, breathe, solid, fire, ice, aurora, sparks, off, strobe,
+//                            white, siren, nordic_siren, automata, lissajous, lab,
+//                            complementary, triadic, thermometer, disco, spectral
+//   set_white <kelvin>       white mode at 1000-12000 K, 2700 warm, 6500 daylight
+//   set_solid <eyes> <heart> fixed hex colors like ff8000, or <left> <right> <heart>
+//   set_palette <name>       aurora mode in the aurora or bands palette
+//   set_lissajous <a:b>      lissajous mode with a 1:2, 2:3 or 3:4 figure
+//   set_thermometer <min> <max>  thermometer mode, blue at min to red at max celsius
+//   set_lab <rrggbb>         lab mode as bright and colorful as that color
+//   set_rule <0-255>         automata mode with that Wolfram rule, 30 chaos, 110 complex
+//   get_temp                 last measured temperature
+//   get_battery              last measured battery charge
+//   info                     owner, firmware version, build time and badge id
+//   set_name <name>          owner's name, stored in flash and typed on long press
+//   set_sleep_after <s>      go dormant after this many idle seconds, 0 never
+//   save_config              keep thresholds, brightness, mode and sleep over a power cycle
+//   reset_calibration        all LED gains back to 100%
+//   morse <message>          blink it once on the heart, A-Z and 0-9
+//   play <melody>            ode, twinkle or alpakka on the buzzer
+//   show_qr <url>            QR code of it on the display, stored in flash, long press toggles it
+pub struct CommandParser {
+    line: [u8; LINE_LEN],
+    len: usize,
+    // didn't fit, the whole line goes when it ends
+    overflow: bool,
+}
+
+impl CommandParser {
+    pub const fn new() -> Self {
+        Self {
+            line: [0; LINE_LEN],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    pub fn poll(&mut self, logger: &mut Logger) {
+        let mut buf = [0u8; 64];
+        let count = usb_log::read(&mut buf);
+
+        for &byte in &buf[..count] {
+            match byte {
+                // half a command is worse than none, a cut set_name would be stored as is
+                b'\r' | b'\n' if self.overflow => {
+                    writeln!(logger, "ERR: line longer than {LINE_LEN} bytes\r").ok();
+                    self.len = 0;
+                    self.overflow = false;
+                }
+                b'\r' | b'\n' if self.len > 0 => {
+                    let line = core::str::from_utf8(&self.line[..self.len]).unwrap_or("");
+                    dispatch(line.trim(), logger);
+                    self.len = 0;
+                }
+                // empty line
+                b'\r' | b'\n' => {}
+                _ if self.len < LINE_LEN => {
+                    self.line[self.len] = byte;
+                    self.len += 1;
+                }
+                _ => self.overflow = true,
+            }
+        }
+    }
+}
+
+// Main loop takes it from there
+fn request_mode(mode: AnimationMode, logger: &mut Logger) {
+    critical_section::with(|cs| SETTINGS.borrow_ref_mut(cs).requested_mode = Some(mode));
+    writeln!(logger, "OK\r").ok();
+}
+
+// Two colors are eyes and heart, three give each eye its own
+fn set_solid(colors: &str, logger: &mut Logger) {
+    let mut parsed = [None; 3];
+    let mut count = 0;
+    for word in colors.split_whitespace() {
+        if count == parsed.len() {
+            count += 1;
+            break;
+        }
+        parsed[count] = Srgb::from_hex(word);
+        count += 1;
+    }
+    let mode = match (count, parsed) {
+        (2, [Some(eyes), Some(heart), None]) => AnimationMode::Solid {
+            left_eye: eyes,
+            right_eye: eyes,
+            heart,
+        },
+        (3, [Some(left_eye), Some(right_eye), Some(heart)]) => AnimationMode::Solid {
+            left_eye,
+            right_eye,
+            heart,
+        },
+        _ => {
+            writeln!(logger, "ERR: set_solid <eyes> <heart> or <left> <right> <heart>, as rrggbb\r").ok();
+            return;
+        }
+    };
+    request_mode(mode, logger);
+}
+
+fn set_thermometer(range: &str, logger: &mut Logger) {
+    let mut words = range.split_whitespace().map(str::parse::<i8>);
+    match (words.next(), words.next(), words.next()) {
+        (Some(Ok(min_celsius)), Some(Ok(max_celsius)), None) if min_celsius < max_celsius => {
+            request_mode(AnimationMode::Thermometer { min_celsius, max_celsius }, logger);
+        }
+        _ => {
+            writeln!(logger, "ERR: set_thermometer <min> <max>, min under max\r").ok();
+        }
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn dispatch(line: &str, logger: &mut Logger) {
+    // name can have spaces in it, take the whole rest of the line
+    if let Some(name) = line.strip_prefix("set_name ") {
+        reply_saved(storage::save_owner_name(&OwnerName::new(name.trim())), logger);
+        return;
+    }
+    if let Some(message) = line.strip_prefix("morse ") {
+        let encoder = MorseEncoder::new(message.trim());
+        critical_section::with(|cs| SETTINGS.borrow_ref_mut(cs).morse = Some(encoder));
+        writeln!(logger, "OK\r").ok();
+        return;
+    }
+
+    if let Some(colors) = line.strip_prefix("set_solid ") {
+        set_solid(colors, logger);
+        return;
+    }
+
+    if let Some(range) = line.strip_prefix("set_thermometer ") {
+        set_thermometer(range, logger);
+        return;
+    }
+
+    if let Some(url) = line.strip_prefix("show_qr ") {
+        let Some(url) = QrUrl::new(url.trim()) else {
+            writeln!(logger, "ERR: URL is ASCII and at most {} bytes\r", storage::QR_URL_LEN).ok();
+            return;
+        };
+        let saved = storage::save_qr_url(&url);
+        if saved.is_ok() {
+            critical_section::with(|cs| SETTINGS.borrow_ref_mut(cs).qr_changed = true);
+        }
+        reply_saved(saved, logger);
+        return;
+    }
+
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("");
+    let argument = words.next();
+
+    match (command, argument) {
+        ("set_cold_thresh", Some(arg)) => set_threshold(arg, |settings| &mut settings.cold_threshold, logger),
+        ("set_cool_thresh", Some(arg)) => set_threshold(arg, |settings| &mut settings.cool_threshold, logger),
+        ("set_hot_thresh", Some(arg)) => set_threshold(arg, |settings| &mut settings.hot_threshold, logger),
+        ("set_brightness", Some(arg)) => match arg.parse::<u8>() {
+            Ok(percent) if percent <= 100 => {
+                critical_section::with(|cs| SETTINGS.borrow_ref_mut(cs).brightness_percent = percent);
+                writeln!(logger, "OK\r").ok();
+            }
+            _ => {
+                writeln!(logger, "ERR: brightness is 0-100\r").ok();
+            }
+        },
+        ("set_mode", Some(arg)) => match AnimationMode::from_name(arg) {
+            Some(mode) => {
+                request_mode(mode, logger);
+            }
+            None => {
+                writeln!(logger, "ERR: unknown mode\r").ok();
+            }
+        },
+        ("set_white", Some(arg)) => match arg.parse::<u16>() {
+            Ok(kelvin) if (color::MIN_KELVIN..=color::MAX_KELVIN).contains(&kelvin) => {
+                request_mode(AnimationMode::WhiteBalance { kelvin }, logger);
+            }
+            _ => {
+                writeln!(logger, "ERR: kelvin is {}-{}\r", color::MIN_KELVIN, color::MAX_KELVIN).ok();
+            }
+        },
+        ("set_lissajous", Some(ratio)) => match lissajous::find_figure(ratio) {
+            Some(figure) => {
+                request_mode(AnimationMode::Lissajous(figure), logger);
+            }
+            None => {
+                writeln!(logger, "ERR: ratio is 1:2, 2:3 or 3:4\r").ok();
+            }
+        },
+        ("set_lab", Some(arg)) => match Srgb::from_hex(arg) {
+            Some(color) => {
+                let (lightness, chroma) = lab_cycle::lightness_and_chroma(color);
+                request_mode(AnimationMode::LabCycle { lightness, chroma }, logger);
+            }
+            None => {
+                writeln!(logger, "ERR: color is rrggbb\r").ok();
+            }
+        },
+        ("set_rule", Some(arg)) => match arg.parse::<u8>() {
+            Ok(rule) => {
+                let mode = AnimationMode::Automata {
+                    rule,
+                    step_interval_ms: automata::DEFAULT_STEP_INTERVAL_MS,
+                };
+                request_mode(mode, logger);
+            }
+            Err(_) => {
+                writeln!(logger, "ERR: rule is 0-255\r").ok();
+            }
+        },
+        ("set_palette", Some(name)) => match palettes::find_palette(name) {
+            Some(palette) => {
+                request_mode(AnimationMode::Aurora { palette }, logger);
+            }
+            None => {
+                writeln!(logger, "ERR: unknown palette\r").ok();
+            }
+        },
+        ("set_sleep_after", Some(arg)) => match arg.parse::<u32>() {
+            Ok(seconds) => {
+                let ms = seconds.saturating_mul(1000);
+                critical_section::with(|cs| SETTINGS.borrow_ref_mut(cs).sleep_after_ms = ms);
+                writeln!(logger, "OK\r").ok();
+            }
+            Err(_) => {
+                writeln!(logger, "ERR: bad number\r").ok();
+            }
+        },
+        ("play", Some(name)) => match rtttl::find_melody(name) {
+            Some(melody) => {
+                critical_section::with(|cs| SETTINGS.borrow_ref_mut(cs).melody = Some(melody));
+                writeln!(logger, "OK\r").ok();
+            }
+            None => {
+                writeln!(logger, "ERR: unknown melody\r").ok();
+            }
+        },
+        ("get_temp", None) => {
+            let temperature = critical_section::with(|cs| SETTINGS.borrow_ref(cs).temperature);
+            writeln!(logger, "{temperature}\r").ok();
+        }
+        ("save_config", None) => {
+            let config = critical_section::with(|cs| {
+                let settings = SETTINGS.borrow_ref(cs);
+                storage::BadgeConfig {
+                    cold_threshold: settings.cold_threshold,
+                    cool_threshold: settings.cool_threshold,
+                    hot_threshold: settings.hot_threshold,
+                    brightness_percent: settings.brightness_percent,
+                    animation_mode: settings.mode,
+                    sleep_after_ms: settings.sleep_after_ms,
+                    owner_name: storage::load_owner_name(),
+                }
+            });
+            reply_saved(storage::save_config(&config), logger);
+        }
+        ("reset_calibration", None) => {
+            let result = storage::save_calibration(&[calibration::DEFAULT_GAIN_PERCENT; calibration::CHANNEL_COUNT]);
+            critical_section::with(|cs| SETTINGS.borrow_ref_mut(cs).calibration_changed = true);
+            reply_saved(result, logger);
+        }
+        ("get_battery", None) => {
+            let percent = power::BATTERY_PERCENT.load(Ordering::Relaxed);
+            writeln!(logger, "{percent}\r").ok();
+        }
+        ("info", None) => {
+            writeln!(logger, "{}\r", info::get_badge_info()).ok();
+        }
+        _ => {
+            writeln!(logger, "ERR: unknown command\r").ok();
+        }
+    }
+}
+
+// Bands have to stay in order, cold under cool under hot
+fn set_threshold(arg: &str, threshold: impl Fn(&mut Settings) -> &mut u16, logger: &mut Logger) {
+    let Ok(celsius) = arg.parse::<u16>() else {
+        writeln!(logger, "ERR: bad number\r").ok();
+        return;
+    };
+    let in_order = critical_section::with(|cs| {
+        let mut settings = SETTINGS.borrow_ref_mut(cs);
+        let previous = core::mem::replace(threshold(&mut settings), celsius);
+        let in_order = settings.cold_threshold <= settings.cool_threshold
+            && settings.cool_threshold <= settings.hot_threshold;
+        if !in_order {
+            *threshold(&mut settings) = previous;
+        }
+        in_order
+    });
+    if in_order {
+        writeln!(logger, "OK\r").ok();
+    } else {
+        writeln!(logger, "ERR: thresholds out of order\r").ok();
+    }
+}
+
+fn reply_saved(result: Result<(), storage::FlashError>, logger: &mut Logger) {
+    match result {
+        Ok(()) => writeln!(logger, "OK\r").ok(),
+        Err(storage::FlashError::Verify) => writeln!(logger, "ERR: flash write didn't stick\r").ok(),
+    };
+}
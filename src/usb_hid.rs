@@ -0,0 +1,97 @@
+use usbd_hid::descriptor::KeyboardReport;
+
+use crate::storage::OwnerName;
+use crate::usb_log;
+
+// One character per this many ms, key is let go half way
+pub const KEY_INTERVAL_MS: u32 = 50;
+
+const LEFT_SHIFT: u8 = 0x02;
+
+// US layout HID usage for an ASCII character, (modifier, keycode)
+const fn keycode(c: u8) -> Option<(u8, u8)> {
+    match c {
+        b'a'..=b'z' => Some((0, 0x04 + (c - b'a'))),
+        b'A'..=b'Z' => Some((LEFT_SHIFT, 0x04 + (c - b'A'))),
+        b'1'..=b'9' => Some((0, 0x1e + (c - b'1'))),
+        b'0' => Some((0, 0x27)),
+        b'\n' => Some((0, 0x28)),
+        b' ' => Some((0, 0x2c)),
+        b'-' => Some((0, 0x2d)),
+        b'_' => Some((LEFT_SHIFT, 0x2d)),
+        b'.' => Some((0, 0x37)),
+        b'/' => Some((0, 0x38)),
+        b':' => Some((LEFT_SHIFT, 0x33)),
+        b'@' => Some((LEFT_SHIFT, 0x1f)),
+        _ => None,
+    }
+}
+
+const fn report(modifier: u8, keycode: u8) -> KeyboardReport {
+    KeyboardReport {
+        modifier,
+        reserved: 0,
+        leds: 0,
+        keycodes: [keycode, 0, 0, 0, 0, 0],
+    }
+}
+
+// Types text as a USB keyboard, a bit every frame so the animation keeps going
+pub struct HidKeyboard {
+    text: OwnerName,
+    next: usize,
+    key_down: bool,
+    wait_ms: u32,
+}
+
+impl HidKeyboard {
+    pub const fn new() -> Self {
+        Self {
+            text: OwnerName::empty(),
+            next: 0,
+            key_down: false,
+            wait_ms: 0,
+        }
+    }
+
+    pub const fn type_text(&mut self, text: OwnerName) {
+        self.text = text;
+        self.next = 0;
+        self.key_down = false;
+        self.wait_ms = 0;
+    }
+
+    pub fn push_report(report: &KeyboardReport) -> bool {
+        usb_log::push_keyboard_report(report)
+    }
+
+    pub fn tick(&mut self, delta_ms: u32) {
+        self.wait_ms = self.wait_ms.saturating_sub(delta_ms);
+        if self.wait_ms > 0 {
+            return;
+        }
+
+        if self.key_down {
+            if Self::push_report(&report(0, 0)) {
+                self.key_down = false;
+                self.wait_ms = KEY_INTERVAL_MS / 2;
+            }
+            return;
+        }
+
+        let Some(&c) = self.text.as_str().as_bytes().get(self.next) else {
+            return;
+        };
+        match keycode(c) {
+            Some((modifier, code)) => {
+                if Self::push_report(&report(modifier, code)) {
+                    self.next += 1;
+                    self.key_down = true;
+                    self.wait_ms = KEY_INTERVAL_MS / 2;
+                }
+            }
+            // no key for this one, skip it
+            None => self.next += 1,
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use core::cell::RefCell;
+use core::fmt;
+
+use critical_section::Mutex;
+use rp2040_hal::clocks::UsbClock;
+use rp2040_hal::pac::{self, interrupt};
+use rp2040_hal::usb::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usb_device::prelude::*;
+use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
+use usbd_hid::hid_class::HIDClass;
+use usbd_serial::SerialPort;
+
+// Composite device: CDC serial for logs and commands, HID keyboard for typing the owner's name
+struct UsbSerial {
+    device: UsbDevice<'static, UsbBus>,
+    serial: SerialPort<'static, UsbBus>,
+    keyboard: HIDClass<'static, UsbBus>,
+    // what the host sent, taken out of the endpoint by the interrupt and out of here by read()
+    rx: RxRing,
+}
+
+// Plenty for a few command lines between two frames
+const RX_LEN: usize = 256;
+
+struct RxRing {
+    bytes: [u8; RX_LEN],
+    start: usize,
+    len: usize,
+}
+
+impl RxRing {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; RX_LEN],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    // Full means what doesn't fit is lost
+    fn push(&mut self, data: &[u8]) {
+        for &byte in data {
+            if self.len == RX_LEN {
+                return;
+            }
+            self.bytes[(self.start + self.len) % RX_LEN] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn pop_into(&mut self, buf: &mut [u8]) -> usize {
+        let count = self.len.min(buf.len());
+        for byte in &mut buf[..count] {
+            *byte = self.bytes[self.start];
+            self.start = (self.start + 1) % RX_LEN;
+        }
+        self.len -= count;
+        count
+    }
+}
+
+// Owned by the USB interrupt, borrowed by Logger when writing
+static USB_SERIAL: Mutex<RefCell<Option<UsbSerial>>> = Mutex::new(RefCell::new(None));
+
+// Brings up USB CDC ACM and HID keyboard. After this the USB interrupt keeps the device
+// serviced so the animation loop never waits for the host.
+pub fn init(
+    regs: pac::USBCTRL_REGS,
+    dpram: pac::USBCTRL_DPRAM,
+    usb_clock: UsbClock,
+    resets: &mut pac::RESETS,
+) {
+    let bus: &'static UsbBusAllocator<UsbBus> = cortex_m::singleton!(
+        : UsbBusAllocator<UsbBus> = UsbBusAllocator::new(UsbBus::new(regs, dpram, usb_clock, true, resets))
+    )
+    .unwrap();
+
+    let serial = SerialPort::new(bus);
+    let keyboard = HIDClass::new(bus, KeyboardReport::desc(), 10);
+    let device = UsbDeviceBuilder::new(bus, UsbVidPid(0x16c0, 0x27dd))
+        .manufacturer("AlpakkaFarmi")
+        .product("Alpakkabadge")
+        .serial_number("2024")
+        .composite_with_iads()
+        .build();
+
+    critical_section::with(|cs| {
+        USB_SERIAL
+            .borrow_ref_mut(cs)
+            .replace(UsbSerial {
+                device,
+                serial,
+                keyboard,
+                rx: RxRing::new(),
+            });
+    });
+
+    // SAFETY: USB_SERIAL is set up, the handler only touches it inside a critical section
+    unsafe {
+        pac::NVIC::unmask(pac::Interrupt::USBCTRL_IRQ);
+    }
+}
+
+#[interrupt]
+fn USBCTRL_IRQ() {
+    critical_section::with(|cs| {
+        if let Some(usb) = USB_SERIAL.borrow_ref_mut(cs).as_mut() {
+            usb.device.poll(&mut [&mut usb.serial, &mut usb.keyboard]);
+            // the endpoint has to be emptied right here, data left in it keeps the interrupt
+            // firing and the main loop never gets to run
+            let mut chunk = [0u8; 64];
+            while let Ok(count @ 1..) = usb.serial.read(&mut chunk) {
+                usb.rx.push(&chunk[..count]);
+            }
+        }
+    });
+}
+
+// Host has enumerated us
+pub fn is_connected() -> bool {
+    critical_section::with(|cs| {
+        USB_SERIAL
+            .borrow_ref(cs)
+            .as_ref()
+            .is_some_and(|usb| usb.device.state() == UsbDeviceState::Configured)
+    })
+}
+
+// False if the report didn't fit, try again on the next frame
+pub fn push_keyboard_report(report: &KeyboardReport) -> bool {
+    critical_section::with(|cs| {
+        USB_SERIAL
+            .borrow_ref_mut(cs)
+            .as_mut()
+            .is_some_and(|usb| usb.keyboard.push_input(report).is_ok())
+    })
+}
+
+// Whatever the host has sent since last time, never waits
+pub fn read(buf: &mut [u8]) -> usize {
+    critical_section::with(|cs| {
+        USB_SERIAL
+            .borrow_ref_mut(cs)
+            .as_mut()
+            .map_or(0, |usb| usb.rx.pop_into(buf))
+    })
+}
+
+// Debug output over USB, use with writeln!(). Lines are dropped when
+// nobody is listening or the buffer is full, logging never blocks.
+pub struct Logger;
+
+impl fmt::Write for Logger {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        critical_section::with(|cs| {
+            if let Some(usb) = USB_SERIAL.borrow_ref_mut(cs).as_mut() {
+                if usb.device.state() != UsbDeviceState::Configured {
+                    return;
+                }
+                let mut bytes = s.as_bytes();
+                while !bytes.is_empty() {
+                    match usb.serial.write(bytes) {
+                        Ok(written) => bytes = &bytes[written..],
+                        Err(_) => return,
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}